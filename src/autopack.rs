@@ -1,21 +1,44 @@
+mod config;
 mod crypto;
 mod filesystem;
+mod schema;
 #[cfg(test)]
 mod tests;
 
-use self::filesystem::StateFiles;
-use super::{pack::Pack, runtime::Runtime};
+pub(crate) use self::{
+    config::ConfigKey,
+    crypto::Keyring,
+    filesystem::{StateFiles, Verification},
+    schema::{StateFormat, SCHEMA_VERSION},
+};
+use super::{
+    pack::Pack,
+    runtime::{Relocation, Runtime},
+};
 use crate::{
     buildpack::BuildPackProject,
-    docker::Docker,
+    docker::{canonical_project_dir, Docker},
     error::AppError,
-    log::{banner, trying},
+    fingerprint::{list_changes, FileFilter, Fingerprint},
+    history::{BuildRecord, History},
+    log::{banner, instruct, success, trying, warn},
     package_json::Project,
+    scaffold::Scaffold,
+    sync::ArtifactSync,
 };
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use tracing::debug;
 
+/// Changed inputs listed before a rebuild
+const MAX_LISTED_CHANGES: usize = 10;
+
+/// Port the dev server listens on inside the container
+const DEV_SERVER_PORT: usize = 3000;
+
+/// Host paths bind-mounted into the dev server container, when present
+const DEV_MOUNTS: [&str; 2] = ["src", "public"];
+
 #[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
 pub(crate) struct AutoPack {
     pub(crate) runtime: Runtime,
@@ -44,6 +67,37 @@ impl AutoPack {
         StateFiles::new(runtime_dir)?.load_autopack()
     }
 
+    /// Project edits recorded by the state in `runtime_dir`, for a re-init to keep track of.
+    /// States which fail verification don't get to name files to edit.
+    pub(crate) fn recorded_scaffold(runtime_dir: &Path) -> Scaffold {
+        if !runtime_dir.join(".state").is_dir() {
+            return Scaffold::default();
+        }
+        let recorded = StateFiles::new(Some(runtime_dir)).and_then(|files| {
+            files.verify(&Keyring::new()?)?;
+            files.decode()
+        });
+        match recorded {
+            Ok(autopack) => autopack.runtime.scaffold().clone(),
+            Err(e) => {
+                debug!(
+                    "No project edits carried over from the previous state :: {}",
+                    e
+                );
+                Scaffold::default()
+            }
+        }
+    }
+
+    /// Copy with the paths mapped between their stored and absolute forms
+    pub(crate) fn relocated(&self, paths: &Relocation) -> AutoPack {
+        let mut autopack = self.clone();
+        autopack.runtime.relocate(paths);
+        autopack.pack_cli.relocate(paths);
+        autopack.client_project.relocate(paths);
+        autopack
+    }
+
     pub(crate) fn validate(&self) -> anyhow::Result<bool> {
         debug!("validating autopack");
 
@@ -67,34 +121,192 @@ impl AutoPack {
         Ok(autopack)
     }
 
-    pub(crate) fn build(&self, clear_cache: bool) -> anyhow::Result<()> {
+    /// Content hash of everything `pack build` gets to see
+    pub(crate) fn fingerprint(&self) -> anyhow::Result<Fingerprint> {
+        let (include, exclude) = self.buildpack.file_patterns();
+        let mut builder = Fingerprint::builder();
+        builder
+            .context(
+                &self.client_project.build_context(),
+                &FileFilter::new(include, exclude),
+            )?
+            .path(
+                "autopack: project.toml",
+                Path::new(&self.runtime.project_toml()),
+            )?
+            .path(
+                "autopack: Procfile",
+                Path::new(&self.runtime.proc_file().proc_file_path()),
+            )?
+            .value(
+                "autopack: node version",
+                self.buildpack.env("BP_NODE_VERSION").unwrap_or_default(),
+            )
+            .value("autopack: pack version", &self.pack_cli.release_version);
+        Ok(builder.build())
+    }
+
+    /// Whether the image of the previous build is still in the daemon
+    fn image_present(&self, previous: &BuildRecord) -> bool {
+        match self.docker.image_id(&previous.image) {
+            Ok(Some(id)) => previous
+                .digest
+                .as_deref()
+                .map(|d| id.starts_with(d.trim_start_matches("sha256:")))
+                .unwrap_or(true),
+            Ok(None) => false,
+            Err(e) => {
+                debug!("Failed inspecting image {} :: {}", previous.image, e);
+                false
+            }
+        }
+    }
+
+    /// Whether the latest successful build is up to date; otherwise lists the inputs which changed
+    fn up_to_date(&self, history: &History, fingerprint: &Fingerprint) -> anyhow::Result<bool> {
+        let previous = match history.latest_success()? {
+            Some(previous) => previous,
+            None => return Ok(false),
+        };
+        let changes = match previous.fingerprint.as_ref() {
+            Some(f) if f.digest == fingerprint.digest => {
+                if self.image_present(&previous) {
+                    success(&format!(
+                        "Sources unchanged since build {}, skipping the build. Use --force to rebuild.",
+                        previous.id
+                    ));
+                    return Ok(true);
+                }
+                instruct(&format!(
+                    "Image {} of build {} is gone, rebuilding",
+                    previous.image, previous.id
+                ));
+                return Ok(false);
+            }
+            Some(f) => fingerprint.changes(f),
+            None => return Ok(false),
+        };
+
+        instruct(&format!(
+            "{} input(s) changed since build {}:",
+            changes.len(),
+            previous.id
+        ));
+        list_changes(&changes, MAX_LISTED_CHANGES);
+        Ok(false)
+    }
+
+    /// Builds the image, skipping it when the inputs are unchanged since the last successful
+    /// build unless `force` or `clear_cache` is given
+    pub(crate) fn build(&self, clear_cache: bool, force: bool) -> anyhow::Result<()> {
         trying("Auto packing project");
 
         self.validate()?;
+        self.pack_cli.check_outdated()?;
 
-        self.pack_cli.build_image(
-            self.runtime.project_toml().as_str(),
-            &self.runtime.proc_file().proc_default_command(),
-            &self.runtime.proc_file().proc_file_path(),
-            &self.runtime.proc_file().container_bindings_path(),
+        let history = History::new(&self.runtime.dir());
+        let fingerprint = self.fingerprint()?;
+        if !(force || clear_cache) && self.up_to_date(&history, &fingerprint)? {
+            return Ok(());
+        }
+
+        let mut record = history.start(
             &self.client_project.image_name,
-            clear_cache,
+            Path::new(&self.runtime.project_toml()),
+            &self.client_project.path,
         )?;
+        record.fingerprint = Some(fingerprint);
+        let result = self.pack_cli.build_image(
+            &self.runtime,
+            &self.client_project,
+            clear_cache,
+            &mut record,
+        );
+        // a failure to record the build must not mask its outcome
+        match history.finish(
+            &mut record,
+            result.as_ref().err().map(|e| e.to_string()),
+            self.runtime.keep_builds(),
+        ) {
+            Ok(_) => debug!("Recorded build {} at {:?}", record.id, record.dir()),
+            Err(e) => warn(&format!("Failed recording build {} :: {}", record.id, e)),
+        }
 
-        Ok(())
+        result
     }
 
     pub(crate) async fn run(self, port: usize) -> anyhow::Result<()> {
         banner("Running autopack(ed) project");
 
+        let container_src = format!("{}/src", self.client_project.container_app_dir());
+
         self.docker
             .run(
                 self.client_project.image_name,
                 &self.client_project.path,
+                &container_src,
                 port,
             )
             .await?;
 
         Ok(())
     }
+
+    /// Runs the container while syncing host builds of the project into it
+    pub(crate) async fn run_synced(self, port: usize, sync: ArtifactSync) -> anyhow::Result<()> {
+        let run = self.run(port);
+        tokio::pin!(run);
+        tokio::select! {
+            result = &mut run => result,
+            result = sync.watch() => {
+                if let Err(e) = result {
+                    warn(&format!("Stopped syncing build output :: {}", e));
+                }
+                run.await
+            }
+        }
+    }
+
+    /// Runs the framework dev server inside the image with the sources bind-mounted, so that
+    /// changes are picked up through HMR instead of a rebuild
+    pub(crate) async fn dev(self, port: usize) -> anyhow::Result<()> {
+        banner("Running the dev server in the autopack(ed) image");
+
+        let project = &self.client_project;
+        let script = project
+            .dev_script()
+            .ok_or_else(|| anyhow::anyhow!("package.json has no `start` script"))?;
+        let app_dir = project.container_app_dir();
+        // the package manager may not be part of the image, npm always is
+        let command = format!("cd {} && npm run {}", app_dir, script);
+
+        let host_dir = canonical_project_dir(&project.path)?;
+        let mounts = DEV_MOUNTS
+            .iter()
+            .filter(|m| host_dir.join(m).exists())
+            .map(|m| (host_dir.join(m), format!("{}/{}", app_dir, m)))
+            .collect::<Vec<_>>();
+        let env = [
+            ("HOST", "0.0.0.0".to_string()),
+            ("PORT", DEV_SERVER_PORT.to_string()),
+            ("BROWSER", "none".to_string()),
+            // file events don't cross bind mounts on Docker Desktop
+            ("CHOKIDAR_USEPOLLING", "true".to_string()),
+            ("WATCHPACK_POLLING", "true".to_string()),
+            // the browser reaches the HMR websocket through the forwarded port
+            ("WDS_SOCKET_PORT", port.to_string()),
+        ];
+        instruct(&format!("Dev server: http://localhost:{}", port));
+
+        self.docker
+            .run_command(
+                project.image_name.clone(),
+                &host_dir,
+                &command,
+                &mounts,
+                &env,
+                &[(port, DEV_SERVER_PORT)],
+            )
+            .await
+    }
 }
@@ -0,0 +1,229 @@
+use super::{AutoPack, StateFormat};
+use crate::{lock::write_atomic, runtime::default_keep_builds};
+use std::{fmt, path::Path, str::FromStr};
+
+/// Env vars set through their own key
+const MANAGED_ENV: [(&str, &str); 2] = [
+    ("BP_NODE_VERSION", "node-version"),
+    ("BP_LIVE_RELOAD_ENABLED", "live-reload"),
+];
+
+/// Setting of the autopack state, as edited by `auto-pack config`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ConfigKey {
+    LiveReload,
+    NodeVersion,
+    Port,
+    KeepBuilds,
+    StateFormat,
+    /// build env var in project.toml
+    Env(String),
+}
+
+impl ConfigKey {
+    pub(crate) const KEYS: [&'static str; 6] = [
+        "live-reload",
+        "node-version",
+        "port",
+        "keep-builds",
+        "state-format",
+        "env.<NAME>",
+    ];
+
+    /// Checks `value` for the key, returning its canonical form
+    pub(crate) fn validate(&self, value: &str) -> anyhow::Result<String> {
+        let value = value.trim();
+        match self {
+            ConfigKey::LiveReload => match value {
+                "true" | "on" | "yes" => Ok("true".to_string()),
+                "false" | "off" | "no" => Ok("false".to_string()),
+                _ => anyhow::bail!("Expected true or false, got {:?}", value),
+            },
+            ConfigKey::NodeVersion => {
+                let allowed = |c: char| c.is_ascii_digit() || "xX*.^~<>=| -".contains(c);
+                if value.is_empty() || !value.chars().all(allowed) {
+                    anyhow::bail!(
+                        "Expected a Node.js version or range, e.g. 18 or ^18.12.0, got {:?}",
+                        value
+                    );
+                }
+                Ok(value.to_string())
+            }
+            ConfigKey::Port => match value.parse::<u16>() {
+                Ok(port) if port > 0 => Ok(port.to_string()),
+                _ => anyhow::bail!("Expected a port between 1 and 65535, got {:?}", value),
+            },
+            ConfigKey::KeepBuilds => value
+                .parse::<usize>()
+                .map(|n| n.to_string())
+                .map_err(|_| anyhow::anyhow!("Expected a number of builds, got {:?}", value)),
+            ConfigKey::StateFormat => Ok(value.parse::<StateFormat>()?.to_string()),
+            ConfigKey::Env(_) => Ok(value.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ConfigKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigKey::LiveReload => f.write_str("live-reload"),
+            ConfigKey::NodeVersion => f.write_str("node-version"),
+            ConfigKey::Port => f.write_str("port"),
+            ConfigKey::KeepBuilds => f.write_str("keep-builds"),
+            ConfigKey::StateFormat => f.write_str("state-format"),
+            ConfigKey::Env(name) => write!(f, "env.{}", name),
+        }
+    }
+}
+
+impl FromStr for ConfigKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "live-reload" => Ok(ConfigKey::LiveReload),
+            "node-version" => Ok(ConfigKey::NodeVersion),
+            "port" => Ok(ConfigKey::Port),
+            "keep-builds" => Ok(ConfigKey::KeepBuilds),
+            "state-format" => Ok(ConfigKey::StateFormat),
+            _ => match s.strip_prefix("env.") {
+                Some(name) => {
+                    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                    if !valid {
+                        anyhow::bail!("Invalid env var name {:?}", name);
+                    }
+                    if let Some((_, key)) = MANAGED_ENV.iter().find(|(env, _)| *env == name) {
+                        anyhow::bail!("{} is set through `{}`", name, key);
+                    }
+                    Ok(ConfigKey::Env(name.to_string()))
+                }
+                None => anyhow::bail!(
+                    "Unknown key {:?}, expected one of {}",
+                    s,
+                    ConfigKey::KEYS.join(", ")
+                ),
+            },
+        }
+    }
+}
+
+/// Files generated from the state, rewritten when the configuration changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Artifacts {
+    pub(crate) project_toml: String,
+    pub(crate) procfile: String,
+}
+
+impl AutoPack {
+    /// Current value of `key`; `None` when unset
+    pub(crate) fn config_get(&self, key: &ConfigKey) -> Option<String> {
+        match key {
+            ConfigKey::LiveReload => Some(self.buildpack.live_reload().to_string()),
+            ConfigKey::NodeVersion => self.buildpack.env("BP_NODE_VERSION").map(String::from),
+            ConfigKey::Port => self.runtime.port().map(|p| p.to_string()),
+            ConfigKey::KeepBuilds => Some(self.runtime.keep_builds().to_string()),
+            ConfigKey::StateFormat => Some(self.runtime.state_format().to_string()),
+            ConfigKey::Env(name) => self.buildpack.env(name).map(String::from),
+        }
+    }
+
+    /// Every key with its value, build env vars included
+    pub(crate) fn config_list(&self) -> Vec<(ConfigKey, Option<String>)> {
+        let keys = [
+            ConfigKey::LiveReload,
+            ConfigKey::NodeVersion,
+            ConfigKey::Port,
+            ConfigKey::KeepBuilds,
+            ConfigKey::StateFormat,
+        ];
+        let envs = self
+            .buildpack
+            .envs()
+            .into_iter()
+            .filter(|(name, _)| !MANAGED_ENV.iter().any(|(env, _)| env == name))
+            .map(|(name, _)| ConfigKey::Env(name.to_string()))
+            .collect::<Vec<_>>();
+        keys.into_iter()
+            .chain(envs)
+            .map(|key| {
+                let value = self.config_get(&key);
+                (key, value)
+            })
+            .collect()
+    }
+
+    /// Copy with `key` set to `value`, or back to its default for `None`
+    pub(crate) fn config_set(
+        &self,
+        key: &ConfigKey,
+        value: Option<&str>,
+    ) -> anyhow::Result<AutoPack> {
+        let value = value.map(|v| key.validate(v)).transpose()?;
+        let value = value.as_deref();
+        let mut autopack = self.clone();
+        match key {
+            ConfigKey::LiveReload => {
+                let live_reload = value == Some("true");
+                autopack.buildpack.set_live_reload(live_reload);
+                let app_path = self
+                    .client_project
+                    .workspace
+                    .as_ref()
+                    .map(|w| w.container_app_path());
+                let proc_file = self
+                    .runtime
+                    .proc_file()
+                    .with_live_reload(live_reload, app_path);
+                autopack.runtime.set_proc_file(proc_file);
+            }
+            ConfigKey::NodeVersion => {
+                autopack.buildpack.set_env("BP_NODE_VERSION", value);
+            }
+            ConfigKey::Port => {
+                autopack
+                    .runtime
+                    .set_port(value.map(|p| p.parse()).transpose()?);
+            }
+            ConfigKey::KeepBuilds => {
+                let keep = value.map(|n| n.parse()).transpose()?;
+                autopack
+                    .runtime
+                    .set_keep_builds(keep.unwrap_or_else(default_keep_builds));
+            }
+            ConfigKey::StateFormat => {
+                let format = value.map(|f| f.parse()).transpose()?;
+                autopack
+                    .runtime
+                    .set_state_format(format.unwrap_or_default());
+            }
+            ConfigKey::Env(name) => {
+                autopack.buildpack.set_env(name, value);
+            }
+        }
+        Ok(autopack)
+    }
+
+    /// Contents of the generated files
+    pub(crate) fn artifacts(&self) -> anyhow::Result<Artifacts> {
+        Ok(Artifacts {
+            project_toml: self.buildpack.to_toml()?,
+            procfile: self.runtime.proc_file().contents(),
+        })
+    }
+
+    /// Rewrites the generated files which differ from `before`, returning their names
+    pub(crate) fn export_artifacts(&self, before: &Artifacts) -> anyhow::Result<Vec<&'static str>> {
+        let after = self.artifacts()?;
+        let mut exported = vec![];
+        if after.project_toml != before.project_toml {
+            write_atomic(Path::new(&self.runtime.project_toml()), &after.project_toml)?;
+            exported.push("project.toml");
+        }
+        if after.procfile != before.procfile {
+            self.runtime.proc_file().export()?;
+            exported.push("Procfile");
+        }
+        Ok(exported)
+    }
+}
@@ -1,141 +1,267 @@
-use aes_gcm::aead::consts::{U12, U32};
-use aes_gcm::aead::generic_array::GenericArray;
-use aes_gcm::{aead::Aead, KeyInit};
-use blake2::{Blake2b512, Digest};
+use crate::lock::write_atomic;
+use blake2::{digest::Mac, Blake2bMac512};
 use rand::Rng;
-use std::fs;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
 use tracing::debug;
 
-use crate::autopack::filesystem::StateFiles;
+/// Overrides the user level config directory holding the state key
+const CONFIG_DIR_ENV: &str = "AUTOPACK_CONFIG_DIR";
 
-pub(super) struct APCrypto {
-    cipher_key: GenericArray<u8, U32>,
-    nonce: GenericArray<u8, U12>,
-    content_hash: Option<Vec<u8>>,
-}
+const KEY_FILE: &str = "state.key";
+
+/// First line of MAC files, naming the scheme
+const MAC_SCHEME: &str = "blake2b-512-mac";
 
-pub(super) struct APEncryptVal {
-    pub(super) cipher_text: Vec<u8>,
-    pub(super) nonce: GenericArray<u8, U12>,
+#[cfg(not(test))]
+fn default_config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("autopack"))
 }
 
-impl APEncryptVal {
-    pub(super) fn new(ct: &str, n: &[u8; 12]) -> Self {
-        APEncryptVal {
-            cipher_text: hex::decode(ct).unwrap(),
-            nonce: *GenericArray::from_slice(n),
-        }
-    }
+// keeps tests away from the user's key
+#[cfg(test)]
+fn default_config_dir() -> Option<PathBuf> {
+    Some(env::temp_dir().join("autopack-test-config"))
 }
 
-pub(super) struct APCryptoBuilder {
-    cipher_key: GenericArray<u8, U32>,
-    // nonce_key: Option<GenericArray<u8, U12>>,
-    content_hash: Option<Vec<u8>>,
+/// A state signing key
+#[derive(Clone)]
+pub(crate) struct Key {
+    pub(crate) id: String,
+    secret: [u8; 32],
 }
 
-impl APCrypto {
-    pub(super) fn builder() -> APCryptoBuilder {
-        let cipher_key =
-            &hex::decode("b52c505a37d78eda5dd34f20c22540ea1b58963cf8e5bf8ffa85f9f2492505b4")
-                .unwrap();
-        let cipher_key = *GenericArray::from_slice(cipher_key);
-
-        APCryptoBuilder {
-            cipher_key,
-            // nonce_key: None,
-            content_hash: None,
+impl Key {
+    fn generate() -> Key {
+        let mut rng = rand::thread_rng();
+        Key {
+            id: hex::encode(rng.gen::<[u8; 4]>()),
+            secret: rng.gen(),
         }
     }
 
-    pub(super) fn encrypt(&self) -> anyhow::Result<APEncryptVal> {
-        let content_hash = self
-            .content_hash
-            .clone()
-            .ok_or_else(|| anyhow::anyhow!("no content to encrypt"))?;
+    fn mac(&self) -> Blake2bMac512 {
+        <Blake2bMac512 as Mac>::new_from_slice(&self.secret)
+            .expect("32 bytes is a valid key length")
+    }
 
-        let cipher = aes_gcm::Aes256Gcm::new(&self.cipher_key);
+    /// Keyed BLAKE2b MAC of the content, hex encoded
+    pub(crate) fn sign(&self, content: &[u8]) -> String {
+        let mut mac = self.mac();
+        mac.update(content);
+        hex::encode(mac.finalize().into_bytes())
+    }
 
-        let cipher_text = cipher
-            .encrypt(&self.nonce, content_hash.as_slice())
-            .map_err(|e| anyhow::anyhow!("encryption error :: {:?}", e))?;
+    /// Checks the MAC in constant time
+    pub(crate) fn verify(&self, content: &[u8], tag: &str) -> anyhow::Result<()> {
+        let tag = hex::decode(tag.trim()).map_err(|e| anyhow::anyhow!("Malformed MAC :: {}", e))?;
+        let mut mac = self.mac();
+        mac.update(content);
+        mac.verify_slice(&tag)
+            .map_err(|_| anyhow::anyhow!("State content doesn't match its MAC"))
+    }
+}
 
-        Ok(APEncryptVal {
-            cipher_text,
-            nonce: self.nonce,
+/// Per-user state keys kept in `<config dir>/autopack/state.key`, readable by the user only.
+/// The first key signs, the retired ones after it still verify states signed before a rotation.
+pub(crate) struct Keyring {
+    path: PathBuf,
+}
+
+impl Keyring {
+    pub(crate) fn new() -> anyhow::Result<Keyring> {
+        let dir = env::var_os(CONFIG_DIR_ENV)
+            .map(PathBuf::from)
+            .or_else(default_config_dir)
+            .ok_or_else(|| anyhow::anyhow!("Could not locate the user config directory"))?;
+        Ok(Keyring {
+            path: dir.join(KEY_FILE),
         })
     }
 
-    pub(super) fn validate_hashes(state_files: &StateFiles) -> anyhow::Result<&StateFiles> {
-        debug!("Validating content hashes");
-
-        let StateFiles {
-            state_content,
-            checksum,
-            ..
-        } = state_files;
+    #[cfg(test)]
+    pub(crate) fn at(dir: &Path) -> Keyring {
+        Keyring {
+            path: dir.join(KEY_FILE),
+        }
+    }
 
-        let reference_crypto_val = {
-            let check_content = fs::read_to_string(checksum.clone())?;
-            let lines: Vec<_> = check_content.lines().map(|l| l.trim()).collect();
-            let nonce = hex::decode(lines[1]).unwrap();
-            let mut x = [0; 12];
-            x[..=11].clone_from_slice(&nonce);
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
 
-            APEncryptVal::new(lines[0], &x)
-        };
+    fn parse(content: &str) -> anyhow::Result<Vec<Key>> {
+        content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| {
+                let (id, secret) = l
+                    .trim()
+                    .split_once(' ')
+                    .ok_or_else(|| anyhow::anyhow!("Malformed key entry"))?;
+                let secret = hex::decode(secret)?
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("Key {} is not 32 bytes", id))?;
+                Ok(Key {
+                    id: id.to_string(),
+                    secret,
+                })
+            })
+            .collect()
+    }
 
-        let decrypted = APCrypto::builder().decrypt(reference_crypto_val)?;
+    /// Writes the keys through a temporary file, only readable by the user; with `replace`
+    /// unset an existing keyring wins, so concurrent first runs agree on one key
+    fn write(&self, keys: &[Key], replace: bool) -> anyhow::Result<()> {
+        let dir = self
+            .path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid key path {:?}", self.path))?;
+        fs::create_dir_all(dir)?;
+        let content = keys
+            .iter()
+            .map(|k| format!("{} {}\n", k.id, hex::encode(k.secret)))
+            .collect::<String>();
 
-        let existing = {
-            let state_content = fs::read(state_content.clone())?;
-            APCrypto::builder().content_hash(state_content).build()
-        };
+        let tmp = tempfile::NamedTempFile::new_in(dir)?;
+        fs::write(tmp.path(), content)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(tmp.path(), fs::Permissions::from_mode(0o600))?;
+        }
+        if replace {
+            tmp.persist(&self.path)?;
+        } else if let Err(e) = tmp.persist_noclobber(&self.path) {
+            if e.error.kind() != io::ErrorKind::AlreadyExists {
+                return Err(e.error.into());
+            }
+        }
+        Ok(())
+    }
 
-        if decrypted.content_hash == existing.content_hash {
-            Ok(state_files)
-        } else {
-            anyhow::bail!("Hashes don't match")
+    /// Keys, current first; generated on first use
+    pub(crate) fn keys(&self) -> anyhow::Result<Vec<Key>> {
+        if !self.path.exists() {
+            debug!("Generating the state key at {:?}", self.path);
+            self.write(&[Key::generate()], false)?;
         }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&self.path)?.permissions().mode();
+            if mode & 0o077 != 0 {
+                anyhow::bail!(
+                    "State key {} is accessible by other users; run `chmod 600` on it",
+                    self.path.display()
+                );
+            }
+        }
+        let keys = Keyring::parse(&fs::read_to_string(&self.path)?)
+            .map_err(|e| anyhow::anyhow!("Invalid state key {:?} :: {}", self.path, e))?;
+        if keys.is_empty() {
+            anyhow::bail!("State key {} holds no keys", self.path.display());
+        }
+        Ok(keys)
+    }
+
+    pub(crate) fn current(&self) -> anyhow::Result<Key> {
+        Ok(self.keys()?.remove(0))
     }
-}
 
-impl APCryptoBuilder {
-    pub(super) fn content_hash(&mut self, contents: Vec<u8>) -> &mut Self {
-        self.content_hash = Some(
-            Blake2b512::new_with_prefix(contents)
-                .finalize()
-                .as_slice()
-                .into(),
-        );
-        self
+    pub(crate) fn get(&self, id: &str) -> anyhow::Result<Option<Key>> {
+        Ok(self.keys()?.into_iter().find(|k| k.id == id))
     }
 
-    pub(super) fn build(&self) -> APCrypto {
-        let rand_gen = rand::thread_rng().gen::<[u8; 12]>();
+    /// Makes a new key current, retiring the previous ones
+    pub(crate) fn rotate(&self) -> anyhow::Result<Key> {
+        let key = Key::generate();
+        let mut keys = vec![key.clone()];
+        keys.extend(self.keys()?);
+        self.write(&keys, true)?;
+        Ok(key)
+    }
 
-        let nonce = *GenericArray::from_slice(&rand_gen);
+    /// Drops the retired keys; states signed with them no longer verify
+    pub(crate) fn drop_retired(&self) -> anyhow::Result<usize> {
+        let keys = self.keys()?;
+        self.write(&keys[..1], true)?;
+        Ok(keys.len() - 1)
+    }
+}
+
+/// Contents of a `.mac` file next to the state
+pub(crate) struct StateMac {
+    pub(crate) key_id: String,
+    tag: String,
+}
 
-        APCrypto {
-            cipher_key: self.cipher_key,
-            content_hash: self.content_hash.clone(),
-            nonce,
+impl StateMac {
+    pub(crate) fn sign(key: &Key, content: &[u8]) -> StateMac {
+        StateMac {
+            key_id: key.id.clone(),
+            tag: key.sign(content),
         }
     }
 
-    pub(super) fn decrypt(&mut self, val: APEncryptVal) -> anyhow::Result<APCrypto> {
-        let cipher = aes_gcm::Aes256Gcm::new(&self.cipher_key);
+    pub(crate) fn read(path: &Path) -> anyhow::Result<StateMac> {
+        let content = fs::read_to_string(path)?;
+        match content.lines().collect::<Vec<_>>()[..] {
+            [MAC_SCHEME, key_id, tag] => Ok(StateMac {
+                key_id: key_id.trim().to_string(),
+                tag: tag.trim().to_string(),
+            }),
+            _ => anyhow::bail!("Malformed state MAC {:?}", path),
+        }
+    }
 
-        let content_hash = cipher
-            .decrypt(&val.nonce, val.cipher_text.as_slice())
-            .map_err(|e| anyhow::anyhow!("Decryption error :: {:?}", e.to_string()))?;
+    pub(crate) fn write(&self, path: &Path) -> anyhow::Result<()> {
+        write_atomic(
+            path,
+            format!("{}\n{}\n{}\n", MAC_SCHEME, self.key_id, self.tag),
+        )?;
+        Ok(())
+    }
 
-        self.content_hash = Some(content_hash);
+    pub(crate) fn verify(&self, keyring: &Keyring, content: &[u8]) -> anyhow::Result<()> {
+        keyring
+            .get(&self.key_id)?
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "State was signed with key {}, which is not in {}; review the state and run \
+                    `auto-pack state trust` if it was signed by someone else",
+                    self.key_id,
+                    keyring.path().display()
+                )
+            })?
+            .verify(content, &self.tag)
+    }
+}
 
-        Ok(APCrypto {
-            cipher_key: self.cipher_key,
-            content_hash: self.content_hash.clone(),
-            nonce: val.nonce,
-        })
+/// Checksums written before the keyed MACs: a Blake2b hash encrypted with a key shared by all
+/// installs. They are never accepted; tests forge them to check that.
+#[cfg(test)]
+pub(crate) mod legacy {
+    use aes_gcm::{
+        aead::{generic_array::GenericArray, Aead},
+        Aes256Gcm, KeyInit,
+    };
+    use blake2::{Blake2b512, Digest};
+    use rand::Rng;
+
+    const CIPHER_KEY: &str = "b52c505a37d78eda5dd34f20c22540ea1b58963cf8e5bf8ffa85f9f2492505b4";
+
+    pub(crate) fn checksum(content: &[u8]) -> String {
+        let nonce = rand::thread_rng().gen::<[u8; 12]>();
+        let cipher = Aes256Gcm::new(GenericArray::from_slice(&hex::decode(CIPHER_KEY).unwrap()));
+        let cipher_text = cipher
+            .encrypt(
+                GenericArray::from_slice(&nonce),
+                Blake2b512::digest(content).as_slice(),
+            )
+            .unwrap();
+        format!("{}\n{}", hex::encode(cipher_text), hex::encode(nonce))
     }
 }
@@ -1,27 +1,63 @@
 use super::{
-    crypto::{APCrypto, APEncryptVal},
+    crypto::{Keyring, StateMac},
+    schema::{self, StateFormat},
     AutoPack,
 };
-use crate::{log::error, runtime::Runtime};
-use rmp_serde::{decode, encode};
+use crate::{
+    lock::write_atomic,
+    log::{error, instruct, record, success, warn},
+    prompt::Interaction,
+    runtime::{self, Relocation},
+};
 use std::{
     ffi::OsStr,
-    fs::{self, File},
+    fs,
     path::{Path, PathBuf},
-    time,
 };
+use tracing::debug;
+
+/// Name of the state file, followed by the format's extension
+const STATE_FILE: &str = "state";
+
+/// How the state passed verification
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Verification {
+    /// signed with the current key
+    Current(String),
+    /// signed with a retired key and re-signed with the current one
+    Resigned { from: String, to: String },
+}
+
+/// Files of the state directory which make up the state
+enum StateFile {
+    /// `state.<format>`
+    Versioned,
+    /// state written before the versioned schema
+    Unversioned,
+    /// checksum of an unversioned state
+    LegacyChecksum,
+}
 
 pub(crate) struct StateFiles {
     pub(crate) state_content: PathBuf,
-    pub(crate) checksum: PathBuf,
+    pub(crate) mac: PathBuf,
+    /// checksum written by versions before the keyed MACs; it proves nothing, since anyone
+    /// can compute it, so such states have to be trusted explicitly
+    pub(crate) legacy_checksum: Option<PathBuf>,
     // pub(crate) state_dir: PathBuf,
 }
 
+fn mac_path(state_content: &Path) -> PathBuf {
+    let mut name = state_content.as_os_str().to_os_string();
+    name.push(".mac");
+    PathBuf::from(name)
+}
+
 impl StateFiles {
-    pub(super) fn new(runtime_dir: Option<&Path>) -> anyhow::Result<StateFiles> {
+    pub(crate) fn new(runtime_dir: Option<&Path>) -> anyhow::Result<StateFiles> {
         let runtime_dir = runtime_dir
             .map(|e| e.to_path_buf())
-            .unwrap_or_else(|| Runtime::default().dir());
+            .unwrap_or_else(|| runtime::locate(Path::new(".")));
 
         let state_dir = runtime_dir.join(".state");
 
@@ -35,99 +71,207 @@ impl StateFiles {
                     e.to_string()
                 )
             })?
-            .fold((None, None), |mut acc, f| match f {
-                Ok(x) => {
-                    let ext = x.path();
-                    let ext = ext.extension().and_then(OsStr::to_str).unwrap_or_default();
-                    if ext == "checksum" {
-                        acc.1 = Some(x.path());
-                    } else {
-                        acc.0 = Some(x.path());
-                    }
-                    acc
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .fold((None, None, None), |mut acc, path| {
+                match StateFiles::kind(&path) {
+                    Some(StateFile::Versioned) => acc.0 = Some(path),
+                    Some(StateFile::Unversioned) => acc.1 = Some(path),
+                    Some(StateFile::LegacyChecksum) => acc.2 = Some(path),
+                    None => debug!("Ignoring {:?} in the state directory", path),
                 }
-                _ => acc,
+                acc
             });
 
         let state_content =
-            fs.0.ok_or_else(|| anyhow::anyhow!("State content path not found"))?;
-        let checksum =
-            fs.1.ok_or_else(|| anyhow::anyhow!("State content checksum path not found"))?;
+            fs.0.or(fs.1)
+                .ok_or_else(|| anyhow::anyhow!("State content path not found"))?;
 
         Ok(StateFiles {
+            mac: mac_path(&state_content),
             state_content,
-            checksum,
+            legacy_checksum: fs.2,
             // state_dir,
         })
     }
+
+    /// What a file of the state directory holds; `None` for MACs and files others left
+    /// there, e.g. the temporary files of interrupted writes
+    fn kind(path: &Path) -> Option<StateFile> {
+        let name = path.file_name().and_then(OsStr::to_str)?;
+        if name.starts_with('.') {
+            return None;
+        }
+        if name.ends_with(".checksum") {
+            return Some(StateFile::LegacyChecksum);
+        }
+        match name.split_once('.') {
+            Some((STATE_FILE, ext)) if StateFormat::from_extension(ext).is_some() => {
+                return Some(StateFile::Versioned);
+            }
+            _ => (),
+        }
+        // releases before the versioned schema named the state `<app>_<version>_<millis>`
+        name.rsplit_once('_')
+            .filter(|(_, millis)| !millis.is_empty() && millis.bytes().all(|b| b.is_ascii_digit()))
+            .map(|_| StateFile::Unversioned)
+    }
+
     pub(super) fn save(
         autopack: &AutoPack,
         save_path: Option<&Path>,
     ) -> anyhow::Result<StateFiles> {
         let default_path = autopack.runtime.dir().join(".state");
         let save_path = save_path.unwrap_or(&default_path);
+        let key = Keyring::new()?.current()?;
 
-        if !save_path.exists() {
-            fs::create_dir(save_path)?;
-        } else {
-            fs::remove_dir_all(save_path)?;
-            fs::create_dir(save_path)?;
-        }
-
-        let version = autopack.client_project.package_json.version.clone();
+        fs::create_dir_all(save_path)?;
 
-        let state_file_name = format!(
-            "{}_{}_{}",
-            autopack.client_project.package_json.name,
-            version,
-            time::SystemTime::now()
-                .duration_since(time::UNIX_EPOCH)
-                .map_err(|e| anyhow::anyhow!("could not get current time :: {:?}", e))?
-                .as_millis()
-        );
-        let state_file = save_path.join(state_file_name.clone());
+        let format = autopack.runtime.state_format();
+        let save_file = save_path.join(format!("{}.{}", STATE_FILE, format.extension()));
+        let stored = autopack.relocated(&Relocation::store(&autopack.runtime.dir()));
+        let contents = schema::encode(&stored, format)?;
 
-        let save_file = save_path.join(state_file);
-        let checksum_file = save_path.join(format!("{}.checksum", state_file_name));
-        let contents = encode::to_vec(&autopack)?;
+        write_atomic(&save_file, &contents)
+            .map_err(|e| anyhow::anyhow!("error writing state file :: {:?}", e))?;
 
-        let a = APCrypto::builder().content_hash(contents.clone()).build();
+        let state_files = StateFiles {
+            mac: mac_path(&save_file),
+            state_content: save_file,
+            legacy_checksum: None,
+            // state_dir: save_path.to_path_buf(),
+        };
+        StateMac::sign(&key, &contents)
+            .write(&state_files.mac)
+            .map_err(|e| anyhow::anyhow!("error writing state MAC :: {:?}", e))?;
 
-        let APEncryptVal { cipher_text, nonce } = a.encrypt()?;
+        // states of another format, or from before the versioned schema
+        for entry in fs::read_dir(save_path)?.filter_map(Result::ok) {
+            let path = entry.path();
+            if path != state_files.state_content && path != state_files.mac {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(state_files)
+    }
 
-        fs::write(save_file.clone(), contents)
-            .map_err(|e| anyhow::anyhow!("error writing state file :: {:?}", e))?;
+    /// Checks the state against its MAC. States signed with a retired key are re-signed
+    /// with the current one; states with a legacy checksum only are rejected until signed.
+    pub(crate) fn verify(&self, keyring: &Keyring) -> anyhow::Result<Verification> {
+        let content = fs::read(&self.state_content)?;
+        let current = keyring.current()?;
 
-        fs::write(
-            checksum_file.clone(),
-            format!(
-                r"{}
-{}",
-                hex::encode(cipher_text),
-                hex::encode(nonce)
-            ),
-        )
-        .map_err(|e| anyhow::anyhow!("error writing checksum :: {:?}", e))?;
+        if !self.mac.exists() {
+            match self.legacy_checksum.as_ref() {
+                Some(checksum) => anyhow::bail!(
+                    "State only carries the checksum {:?} of an older autopack, which anyone can \
+                    forge; review the state and sign it with your key when asked, or with \
+                    `auto-pack state trust`",
+                    checksum
+                ),
+                None => anyhow::bail!("State content MAC not found"),
+            }
+        }
 
-        Ok(StateFiles {
-            checksum: checksum_file,
-            state_content: save_file,
-            // state_dir: save_path.to_path_buf(),
+        let mac = StateMac::read(&self.mac)?;
+        mac.verify(keyring, &content)?;
+        if mac.key_id == current.id {
+            return Ok(Verification::Current(current.id));
+        }
+        debug!("Re-signing state signed with retired key {}", mac.key_id);
+        StateMac::sign(&current, &content).write(&self.mac)?;
+        Ok(Verification::Resigned {
+            from: mac.key_id,
+            to: current.id,
         })
     }
-    pub(super) fn load_autopack(&self) -> anyhow::Result<AutoPack> {
-        APCrypto::validate_hashes(self)?;
 
-        let autpack = decode::from_read(File::open(self.state_content.clone()).map_err(|e| {
+    /// Format of the state file; `None` for states written before the versioned schema
+    pub(crate) fn format(&self) -> Option<StateFormat> {
+        self.state_content
+            .extension()
+            .and_then(OsStr::to_str)
+            .and_then(StateFormat::from_extension)
+    }
+
+    /// Runtime directory holding the `.state` directory
+    fn runtime_dir(&self) -> &Path {
+        self.state_content
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new("."))
+    }
+
+    /// Decodes the state without checking its MAC, resolving its paths
+    pub(crate) fn decode(&self) -> anyhow::Result<AutoPack> {
+        let content = fs::read(&self.state_content).map_err(|e| {
             anyhow::anyhow!("Failed to open file at {:?} :: {:?}", self.state_content, e)
-        })?)
+        })?;
+        match self.format() {
+            Some(format) => schema::decode(&content, format),
+            None => schema::decode_unversioned(&content),
+        }
         .map_err(|e| {
             anyhow::anyhow!(
-                "Fail to deserialize application state from file at {:?} :: {:?}",
+                "Fail to deserialize application state from file at {:?} :: {}",
                 self.state_content,
                 e
             )
-        })?;
-        Ok(autpack)
+        })
+        .map(|autopack| autopack.relocated(&Relocation::load(self.runtime_dir())))
+    }
+
+    /// Shows the decoded state, warning about a legacy checksum, for it to be trusted
+    pub(crate) fn review(&self) -> anyhow::Result<()> {
+        let state = serde_json::to_value(self.decode()?)?;
+        record(
+            &state,
+            &serde_json::to_string_pretty(&state).unwrap_or_default(),
+        );
+        if self.legacy_checksum.is_some() {
+            warn(
+                "This state was written by an older autopack, whose checksums anyone can forge. \
+                Only sign it if you recognise everything above.",
+            );
+        }
+        Ok(())
+    }
+
+    /// Signs a state which decodes with the current key, whatever its MAC says
+    pub(crate) fn trust(&self, keyring: &Keyring) -> anyhow::Result<String> {
+        self.decode()?;
+        let key = keyring.current()?;
+        StateMac::sign(&key, &fs::read(&self.state_content)?).write(&self.mac)?;
+        if let Some(checksum) = self.legacy_checksum.as_ref() {
+            fs::remove_file(checksum)?;
+        }
+        Ok(key.id)
+    }
+
+    /// Asks once to sign a state carrying only a legacy checksum, which then loads as any
+    /// other; declined states keep failing verification
+    fn migrate_legacy(&self, keyring: &Keyring, interaction: Interaction) -> anyhow::Result<()> {
+        self.review()?;
+        if !interaction.confirm("Sign this state with your key?", false)? {
+            instruct("Left the state unsigned");
+            return Ok(());
+        }
+        let key = self.trust(keyring)?;
+        success(&format!("Signed the state with key {}", key));
+        Ok(())
+    }
+
+    /// Verifies and decodes the state, offering to sign one of an older autopack first
+    pub(super) fn load_with(&self, interaction: Interaction) -> anyhow::Result<AutoPack> {
+        let keyring = Keyring::new()?;
+        if self.legacy_checksum.is_some() && !self.mac.exists() {
+            self.migrate_legacy(&keyring, interaction)?;
+        }
+        self.verify(&keyring)?;
+        self.decode()
+    }
+
+    pub(super) fn load_autopack(&self) -> anyhow::Result<AutoPack> {
+        self.load_with(Interaction::current())
     }
 }
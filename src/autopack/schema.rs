@@ -0,0 +1,209 @@
+mod v0;
+
+use super::AutoPack;
+use crate::{
+    buildpack::BuildPackProject, docker::Docker, pack::Pack, package_json::Project,
+    runtime::Runtime,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{fmt, str::FromStr};
+
+/// Version of the state layout written by this release; bump it with a migration whenever
+/// a change to the state structs would break decoding older states
+pub(crate) const SCHEMA_VERSION: u32 = 2;
+
+/// Upgrades a state document of schema `.0` to the next schema version, in place
+pub(crate) type Migration = (u32, fn(&mut Value) -> anyhow::Result<()>);
+
+/// Migrations from every older schema, in order; schema 0 is the unversioned one
+const MIGRATIONS: &[Migration] = &[
+    (0, v0::migrate),
+    // schema 2 stores paths relative to the project; the absolute ones of schema 1 resolve
+    // as they are, but releases before schema 2 can't read relative paths
+    (1, |_| Ok(())),
+];
+
+/// Encoding of the state file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum StateFormat {
+    #[default]
+    Msgpack,
+    Json,
+    /// human readable, e.g. to diff and commit the state
+    Toml,
+}
+
+impl StateFormat {
+    pub(crate) const NAMES: [&'static str; 3] = ["msgpack", "json", "toml"];
+
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            StateFormat::Msgpack => "msgpack",
+            StateFormat::Json => "json",
+            StateFormat::Toml => "toml",
+        }
+    }
+
+    /// Format of a state file by its extension; `None` for states written before the
+    /// versioned schema
+    pub(crate) fn from_extension(extension: &str) -> Option<StateFormat> {
+        extension.parse().ok()
+    }
+}
+
+impl fmt::Display for StateFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for StateFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "msgpack" => Ok(StateFormat::Msgpack),
+            "json" => Ok(StateFormat::Json),
+            "toml" => Ok(StateFormat::Toml),
+            _ => anyhow::bail!(
+                "Unknown state format {:?}, expected one of {}",
+                s,
+                StateFormat::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// State file contents: the schema header and the state itself
+#[derive(Debug, Serialize, Deserialize)]
+struct StateDocument<T> {
+    schema_version: u32,
+    /// autopack release which wrote the state
+    autopack_version: String,
+    autopack: T,
+}
+
+/// TOML has no null; absent keys decode as `None` all the same
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_nulls),
+        _ => (),
+    }
+}
+
+pub(crate) fn encode(autopack: &AutoPack, format: StateFormat) -> anyhow::Result<Vec<u8>> {
+    let document = StateDocument {
+        schema_version: SCHEMA_VERSION,
+        autopack_version: env!("CARGO_PKG_VERSION").to_string(),
+        autopack: serde_json::to_value(autopack)?,
+    };
+    Ok(match format {
+        StateFormat::Msgpack => rmp_serde::to_vec_named(&document)?,
+        StateFormat::Json => serde_json::to_vec_pretty(&document)?,
+        StateFormat::Toml => {
+            let mut value = serde_json::to_value(&document)?;
+            strip_nulls(&mut value);
+            toml::to_string(&toml::Value::try_from(value)?)?.into_bytes()
+        }
+    })
+}
+
+/// Applies the migrations from schema `from` on
+pub(crate) fn migrate(
+    autopack: &mut Value,
+    from: u32,
+    migrations: &[Migration],
+) -> anyhow::Result<()> {
+    let mut version = from;
+    for (step, migration) in migrations.iter() {
+        if *step == version {
+            migration(autopack).map_err(|e| {
+                anyhow::anyhow!("Failed migrating the state from schema {} :: {}", step, e)
+            })?;
+            version += 1;
+        }
+    }
+    Ok(())
+}
+
+type SectionCheck = fn(Value) -> Result<(), serde_json::Error>;
+
+/// Sections of the state, decoded one by one to tell which one fails
+const SECTIONS: [(&str, SectionCheck); 5] = [
+    ("runtime", |v| {
+        serde_json::from_value::<Runtime>(v).map(drop)
+    }),
+    ("pack_cli", |v| serde_json::from_value::<Pack>(v).map(drop)),
+    ("docker", |v| serde_json::from_value::<Docker>(v).map(drop)),
+    ("buildpack", |v| {
+        serde_json::from_value::<BuildPackProject>(v).map(drop)
+    }),
+    ("client_project", |v| {
+        serde_json::from_value::<Project>(v).map(drop)
+    }),
+];
+
+/// Decoding error naming the state section and field which failed
+fn decode_error(autopack: &Value, error: serde_json::Error) -> anyhow::Error {
+    SECTIONS
+        .iter()
+        .find_map(|(section, check)| match autopack.get(section) {
+            None => Some(anyhow::anyhow!("State section `{}` is missing", section)),
+            Some(value) => check(value.clone())
+                .err()
+                .map(|e| anyhow::anyhow!("Failed decoding state section `{}` :: {}", section, e)),
+        })
+        .unwrap_or_else(|| anyhow::anyhow!("Failed decoding the state :: {}", error))
+}
+
+pub(crate) fn decode(content: &[u8], format: StateFormat) -> anyhow::Result<AutoPack> {
+    let document: Value = match format {
+        StateFormat::Msgpack => rmp_serde::from_slice(content)?,
+        StateFormat::Json => serde_json::from_slice(content)?,
+        StateFormat::Toml => serde_json::to_value(toml::from_slice::<toml::Value>(content)?)?,
+    };
+    let StateDocument {
+        schema_version,
+        autopack_version,
+        mut autopack,
+    } = serde_json::from_value::<StateDocument<Value>>(document)
+        .map_err(|e| anyhow::anyhow!("Invalid state header :: {}", e))?;
+
+    if schema_version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "State schema {} was written by autopack {}, which is newer than this one; upgrade autopack",
+            schema_version,
+            autopack_version
+        );
+    }
+    migrate(&mut autopack, schema_version, MIGRATIONS)?;
+
+    serde_json::from_value(autopack.clone()).map_err(|e| {
+        anyhow::anyhow!(
+            "{} (state schema {}, written by autopack {})",
+            decode_error(&autopack, e),
+            schema_version,
+            autopack_version
+        )
+    })
+}
+
+/// Decodes a state written before the versioned schema, a msgpack blob of the 0.1.0 structs
+pub(crate) fn decode_unversioned(content: &[u8]) -> anyhow::Result<AutoPack> {
+    let mut autopack = v0::decode(content).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed decoding a state written before versioned state schemas :: {}",
+            e
+        )
+    })?;
+    migrate(&mut autopack, 0, MIGRATIONS)?;
+
+    serde_json::from_value(autopack.clone())
+        .map_err(|e| anyhow::anyhow!("{} (unversioned state)", decode_error(&autopack, e)))
+}
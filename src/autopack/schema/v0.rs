@@ -0,0 +1,164 @@
+//! State layout written before the versioned schema (schema 0): the structs of autopack
+//! 0.1.0 encoded with `rmp_serde::encode::to_vec`, which stores struct fields by position.
+//! These copies are frozen; never change them along with the live structs.
+
+use crate::{
+    buildpack::{default_shell, default_uri, default_version},
+    ser_deser_str_with_def,
+};
+use npm_package_json::Package;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, path::PathBuf};
+use toml::value::Table;
+
+ser_deser_str_with_def!(VersionSerDeser, default_version);
+ser_deser_str_with_def!(UriSerDeser, default_uri);
+ser_deser_str_with_def!(ShellSerDeser, default_shell);
+
+#[derive(Serialize, Deserialize)]
+struct AutoPack {
+    runtime: Runtime,
+    pack_cli: Pack,
+    docker: Docker,
+    buildpack: BuildPackProject,
+    client_project: Project,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Runtime {
+    dir: PathBuf,
+    proc_file: ProcFile,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProcFile {
+    command_entries: HashMap<String, String>,
+    default_command: String,
+    binding_provider: String,
+    binding_type: String,
+    file_path: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Pack {
+    bin_dir_path: PathBuf,
+    bin_file_path: PathBuf,
+    release_url: String,
+    release_version: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Docker {
+    version: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Project {
+    path: PathBuf,
+    package_json: Package,
+    image_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BuildPackProject {
+    project: Option<BuildPackProjectInfo>,
+    build: Option<Build>,
+    metadata: Option<Metadata>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct BuildPackProjectInfo {
+    id: Option<String>,
+    name: Option<String>,
+    version: Option<String>,
+    source_url: Option<String>,
+    documentation_url: Option<String>,
+    authors: Option<Vec<String>>,
+    licenses: Option<Vec<License>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Build {
+    #[serde(flatten)]
+    file_list: Option<FileList>,
+    env: Option<Vec<Env>>,
+    buildpacks: Option<Vec<BuildPack>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Metadata {
+    Meta(Table),
+}
+
+#[derive(Serialize, Deserialize)]
+struct License {
+    #[serde(rename = "type")]
+    licence_type: Option<String>,
+    uri: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BuildPack {
+    id: Option<String>,
+    #[serde(flatten)]
+    buildpack_field: BuildPackField,
+}
+
+#[derive(Serialize, Deserialize)]
+enum BuildPackField {
+    #[serde(rename = "version", with = "VersionSerDeser")]
+    Version(Option<String>),
+    #[serde(rename = "uri", with = "UriSerDeser")]
+    Uri(Option<String>),
+    #[serde(rename = "script")]
+    Script(Option<Vec<Script>>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Script {
+    api: String,
+    inline: String,
+    #[serde(with = "ShellSerDeser")]
+    shell: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Env {
+    name: Option<String>,
+    value: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FileList {
+    Include(Vec<String>),
+    Exclude(Vec<String>),
+}
+
+/// Decodes a schema 0 state into a document with named fields, ready for the migrations
+pub(super) fn decode(content: &[u8]) -> anyhow::Result<Value> {
+    let autopack: AutoPack = rmp_serde::from_slice(content)?;
+    Ok(serde_json::to_value(autopack)?)
+}
+
+/// Schema 1 holds a single inline buildpack script, as the project descriptor does
+pub(super) fn migrate(autopack: &mut Value) -> anyhow::Result<()> {
+    let buildpacks = autopack
+        .pointer_mut("/buildpack/build/buildpacks")
+        .and_then(Value::as_array_mut);
+    for buildpack in buildpacks.into_iter().flatten() {
+        if let Some(script) = buildpack.get_mut("script") {
+            *script = match script.take() {
+                Value::Array(mut scripts) if scripts.len() <= 1 => {
+                    scripts.pop().unwrap_or_default()
+                }
+                Value::Array(_) => anyhow::bail!("A buildpack holds more than one inline script"),
+                other => other,
+            };
+        }
+    }
+    Ok(())
+}
@@ -1,103 +1,538 @@
-use crate::{autopack::AutoPack, runtime::Runtime};
-use std::fs::{self, OpenOptions};
-use tempfile::Builder;
+use super::{
+    crypto::{legacy, Keyring, StateMac},
+    schema::{self, Migration, StateFormat, SCHEMA_VERSION},
+    StateFiles, Verification,
+};
+use crate::{
+    autopack::AutoPack,
+    prompt::Interaction,
+    runtime::Runtime,
+    scaffold::{self, Scaffold},
+    tools::ToolCache,
+};
+use std::{
+    fs::{self, OpenOptions},
+    path::Path,
+};
+use tempfile::{Builder, TempDir};
+
+/// App laid out the way init does, with absolute paths
+fn app(project_dir: &Path, format: StateFormat) -> AutoPack {
+    let runtime = Runtime::builder(project_dir)
+        .dir(false)
+        .expect("")
+        .proc_file(false, None)
+        .expect("")
+        .state_format(Some(format))
+        .build();
+    let mut app = AutoPack::new(Some(runtime));
+    app.client_project.path = project_dir.canonicalize().unwrap();
+    let pack_dir = ToolCache::new().unwrap().tool_dir("pack", "0.27.0");
+    app.pack_cli.bin_file_path = pack_dir.join("pack");
+    app.pack_cli.bin_dir_path = pack_dir;
+    app
+}
+
+fn saved_app() -> (TempDir, StateFiles) {
+    let runtime_dir = Builder::new()
+        .tempdir()
+        .expect("expecting a temp file to be created");
+    let files = app(runtime_dir.path(), StateFormat::default())
+        .save(None)
+        .expect("failed save");
+    (runtime_dir, files)
+}
+
+fn load(runtime_dir: &TempDir) -> anyhow::Result<AutoPack> {
+    AutoPack::load(Some(&runtime_dir.path().join(".autopack")))
+}
 
 #[test]
 fn app_state_save_load() {
     let runtime_dir = Builder::new()
         .tempdir()
         .expect("expecting a temp file to be created");
-    let runtime = Runtime::builder(runtime_dir.path())
-        .dir(false)
-        .expect("")
-        .build();
-    let app = AutoPack::new(Some(runtime.clone()));
+    let app = app(runtime_dir.path(), StateFormat::default());
 
     app.save(None).expect("failed save");
-    let saved_app = AutoPack::load(Some(&runtime.dir())).expect("failed load");
+    let saved_app = AutoPack::load(Some(&app.runtime.dir())).expect("failed load");
 
     assert_eq!(app, saved_app);
 }
 
 #[test]
-#[should_panic]
+fn ignores_stray_files_in_the_state_dir() {
+    let (runtime_dir, s) = saved_app();
+    let state_dir = s.state_content.parent().unwrap();
+    // left by an interrupted atomic write, and by other tools
+    fs::write(state_dir.join(".tmp-a1B2c3"), "partial").unwrap();
+    fs::write(state_dir.join("notes.txt"), "mine").unwrap();
+
+    let files = StateFiles::new(Some(&runtime_dir.path().join(".autopack"))).unwrap();
+    assert_eq!(files.state_content, s.state_content);
+    assert_eq!(files.legacy_checksum, None);
+    assert!(load(&runtime_dir).is_ok());
+}
+
+#[test]
 fn app_state_bad_content() {
     use std::io::Write;
 
-    let app = AutoPack::default();
-    let save_path = Builder::new()
-        .tempdir()
-        .expect("expecting a temp file to be created");
-
-    let s = app.save(Some(save_path.path())).expect("failed save");
-    // let st = StateFiles::new(&s.state_content).expect("failed to parse the state files");
+    let (runtime_dir, s) = saved_app();
     let mut f = OpenOptions::new()
         .append(true)
         .open(s.state_content.clone())
         .expect("failed opening state file");
     writeln!(f, "A new line").expect("failed to write to state file");
 
-    AutoPack::load(Some(s.state_content.as_path())).expect("failed load");
+    assert!(load(&runtime_dir).is_err());
 }
 
 #[test]
-#[should_panic]
-fn app_state_bad_hash() {
-    let app = AutoPack::default();
-    let save_path = Builder::new()
-        .tempdir()
-        .expect("expecting a temp file to be created");
+fn app_state_bad_mac() {
+    let (runtime_dir, s) = saved_app();
+    let mac = fs::read_to_string(&s.mac).expect("failed reading MAC");
+    let lines = mac.lines().collect::<Vec<_>>();
+    let tag = lines[2];
+    let flipped = if tag.ends_with('0') { "1" } else { "0" };
+    fs::write(
+        &s.mac,
+        format!(
+            "{}\n{}\n{}{}\n",
+            lines[0],
+            lines[1],
+            &tag[..tag.len() - 1],
+            flipped
+        ),
+    )
+    .expect("failed to write MAC");
+
+    assert!(load(&runtime_dir).is_err());
+}
+
+#[test]
+fn app_state_unknown_key() {
+    let (runtime_dir, s) = saved_app();
+    let mac = fs::read_to_string(&s.mac).expect("failed reading MAC");
+    let lines = mac.lines().collect::<Vec<_>>();
+    fs::write(&s.mac, format!("{}\nffffffff\n{}\n", lines[0], lines[2]))
+        .expect("failed to write MAC");
+
+    let err = load(&runtime_dir).unwrap_err().to_string();
+    assert!(err.contains("signed with key ffffffff"), "{}", err);
+}
 
-    let s = app.save(Some(save_path.path())).expect("failed save");
-    // let st = StateFiles::new(&s).expect("failed to parse the state files");
+#[test]
+fn rejects_legacy_checksums() {
+    // anyone can forge a legacy checksum with the key embedded in older releases
+    let (runtime_dir, s) = saved_app();
+    let mut forged = app(runtime_dir.path(), StateFormat::default());
+    forged.client_project.image_name = "attacker/image".to_string();
+    let content = schema::encode(&forged, StateFormat::default()).unwrap();
+    fs::write(&s.state_content, &content).unwrap();
+    fs::remove_file(&s.mac).unwrap();
+    let checksum = s.state_content.with_extension("checksum");
+    fs::write(&checksum, legacy::checksum(&content)).unwrap();
 
-    let lines = fs::read_to_string(s.checksum.clone()).expect("failed reading checksum");
-    let ls = lines.lines().collect::<Vec<_>>();
-    let ls1 = ls[0];
-    let mut ls11: Vec<_> = ls1.chars().take(ls1.len() - 2).collect();
-    ls11.push('0');
-    ls11.push('1');
+    let files = StateFiles::new(Some(&runtime_dir.path().join(".autopack"))).unwrap();
+    assert_eq!(files.legacy_checksum.as_deref(), Some(checksum.as_path()));
+    // declining to sign it on load, as happens without a terminal
+    let err = files.load_autopack().unwrap_err().to_string();
+    assert!(err.contains("auto-pack state trust"), "{}", err);
+    let err = files.load_with(Interaction::Defaults).unwrap_err();
+    assert!(err.to_string().contains("auto-pack state trust"), "{}", err);
+    assert!(!s.mac.exists());
+    assert!(checksum.exists());
 
-    let x = ls11.into_iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    // until explicitly trusted
+    let keyring = Keyring::new().unwrap();
+    files.trust(&keyring).unwrap();
+    assert!(!checksum.exists());
+    assert!(matches!(
+        files.verify(&keyring).unwrap(),
+        Verification::Current(_)
+    ));
+}
+
+#[test]
+fn rotates_keys() {
+    let config = TempDir::new().unwrap();
+    let keyring = Keyring::at(config.path());
+    let (_runtime_dir, s) = saved_app();
+    let content = fs::read(&s.state_content).unwrap();
+    let first = keyring.current().unwrap();
+    StateMac::sign(&first, &content).write(&s.mac).unwrap();
 
-    let lss = format!(
-        r"{}
-    {}",
-        &x.join(""),
-        ls[1]
+    let second = keyring.rotate().unwrap();
+    assert_ne!(first.id, second.id);
+    assert_eq!(
+        s.verify(&keyring).unwrap(),
+        Verification::Resigned {
+            from: first.id.clone(),
+            to: second.id.clone()
+        }
     );
-    fs::write(s.checksum, lss).expect("failed to write checksum");
+    assert_eq!(
+        s.verify(&keyring).unwrap(),
+        Verification::Current(second.id)
+    );
+
+    // states still signed with a dropped key fail
+    StateMac::sign(&first, &content).write(&s.mac).unwrap();
+    assert_eq!(keyring.drop_retired().unwrap(), 1);
+    assert!(s.verify(&keyring).is_err());
 
-    AutoPack::load(Some(s.state_content.as_path())).expect("failed load");
+    // another user's key doesn't verify
+    let other_config = TempDir::new().unwrap();
+    let other = Keyring::at(other_config.path());
+    assert!(s.verify(&other).is_err());
 }
 
+#[cfg(unix)]
 #[test]
-#[should_panic]
-fn app_state_bad_nonce() {
-    let app = AutoPack::default();
-    let save_path = Builder::new()
+fn keeps_the_key_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let config = TempDir::new().unwrap();
+    let keyring = Keyring::at(config.path());
+    keyring.current().unwrap();
+    let mode = fs::metadata(keyring.path()).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    fs::set_permissions(keyring.path(), fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(keyring.current().is_err());
+}
+
+fn saved_app_as(format: StateFormat) -> (TempDir, AutoPack, StateFiles) {
+    let runtime_dir = Builder::new()
         .tempdir()
         .expect("expecting a temp file to be created");
+    let app = app(runtime_dir.path(), format);
+    let files = app.save(None).expect("failed save");
+    (runtime_dir, app, files)
+}
+
+#[test]
+fn state_formats_roundtrip() {
+    for format in [StateFormat::Msgpack, StateFormat::Json, StateFormat::Toml] {
+        let (runtime_dir, app, files) = saved_app_as(format);
+        assert_eq!(files.state_content.extension().unwrap(), format.extension());
+        assert_eq!(load(&runtime_dir).expect("failed load"), app);
+    }
+
+    let (_runtime_dir, _, files) = saved_app_as(StateFormat::Toml);
+    let content = fs::read_to_string(&files.state_content).unwrap();
+    assert!(content.contains(&format!("schema_version = {}", SCHEMA_VERSION)));
+}
+
+#[test]
+fn decodes_unversioned_states() {
+    // written by autopack 0.1.0 for a CRA app at /tmp/autopack-fixture/my-app
+    let runtime_dir = TempDir::new().unwrap();
+    let state_dir = runtime_dir.path().join(".autopack/.state");
+    fs::create_dir_all(&state_dir).unwrap();
+    let state = state_dir.join("my-app_0.1.0_1792387866698");
+    fs::write(&state, include_bytes!("fixtures/state-v0")).unwrap();
+    fs::write(
+        state.with_extension("checksum"),
+        include_bytes!("fixtures/state-v0.checksum"),
+    )
+    .unwrap();
+
+    let files = StateFiles::new(Some(&runtime_dir.path().join(".autopack"))).unwrap();
+    assert_eq!(files.format(), None);
+    let app = files.decode().expect("failed decoding the 0.1.0 state");
+    assert_eq!(app.client_project.image_name, "my-app");
+    assert_eq!(app.client_project.package_json.name, "my-app");
+    assert_eq!(
+        app.client_project.path,
+        Path::new("/tmp/autopack-fixture/my-app")
+    );
+    assert_eq!(app.pack_cli.release_version, "0.27.0");
+    assert_eq!(
+        app.pack_cli.bin_file_path,
+        Path::new("/tmp/autopack-fixture/my-app/.autopack/.bin/pack")
+    );
+    assert_eq!(app.buildpack.env("BP_NODE_VERSION"), Some("16.x"));
+    assert!(app.runtime.proc_file().contents().contains("watchexec"));
+
+    // its legacy checksum doesn't count until the state is signed, which loading offers once
+    assert!(files.load_autopack().is_err());
+    assert_eq!(files.load_with(Interaction::Yes).unwrap(), app);
+    assert!(files.mac.exists());
+    assert!(!state.with_extension("checksum").exists());
+    assert_eq!(files.load_autopack().unwrap(), app);
+}
+
+#[test]
+fn rejects_newer_schemas() {
+    let (_runtime_dir, _, files) = saved_app_as(StateFormat::Json);
+    let content = fs::read_to_string(&files.state_content).unwrap().replacen(
+        &format!("\"schema_version\": {}", SCHEMA_VERSION),
+        &format!("\"schema_version\": {}", SCHEMA_VERSION + 1),
+        1,
+    );
+
+    let err = schema::decode(content.as_bytes(), StateFormat::Json)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("upgrade autopack"), "{}", err);
+}
+
+#[test]
+fn names_the_failing_section() {
+    let (_runtime_dir, _, files) = saved_app_as(StateFormat::Json);
+    let mut document: serde_json::Value =
+        serde_json::from_slice(&fs::read(&files.state_content).unwrap()).unwrap();
+    document["autopack"]["docker"]["version"] = serde_json::json!(42);
+
+    let err = schema::decode(&serde_json::to_vec(&document).unwrap(), StateFormat::Json)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("section `docker`"), "{}", err);
+    assert!(err.contains(env!("CARGO_PKG_VERSION")), "{}", err);
+}
+
+#[test]
+fn migrates_in_order() {
+    const MIGRATIONS: &[Migration] = &[
+        (1, |v| {
+            v["renamed"] = v["original"].take();
+            Ok(())
+        }),
+        (2, |v| {
+            v["steps"] = serde_json::json!(["2"]);
+            Ok(())
+        }),
+        (3, |_| anyhow::bail!("broken")),
+    ];
+
+    let mut value = serde_json::json!({ "original": 1 });
+    schema::migrate(&mut value, 1, &MIGRATIONS[..2]).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({ "original": null, "renamed": 1, "steps": ["2"] })
+    );
+
+    let mut value = serde_json::json!({ "original": 1 });
+    schema::migrate(&mut value, 2, &MIGRATIONS[..2]).unwrap();
+    assert_eq!(value, serde_json::json!({ "original": 1, "steps": ["2"] }));
+
+    let err = schema::migrate(&mut value, 3, MIGRATIONS).unwrap_err();
+    assert!(err.to_string().contains("from schema 3"));
+}
+
+#[test]
+fn trusts_decodable_states() {
+    let (runtime_dir, _, files) = saved_app_as(StateFormat::Toml);
+    let mac = fs::read_to_string(&files.mac).unwrap();
+    let lines = mac.lines().collect::<Vec<_>>();
+    fs::write(
+        &files.mac,
+        format!("{}\nffffffff\n{}\n", lines[0], lines[2]),
+    )
+    .unwrap();
+    assert!(load(&runtime_dir).is_err());
+
+    files.trust(&Keyring::new().unwrap()).unwrap();
+    assert!(load(&runtime_dir).is_ok());
+
+    fs::write(&files.state_content, "not = [a state").unwrap();
+    assert!(files.trust(&Keyring::new().unwrap()).is_err());
+}
+
+#[test]
+fn moves_with_the_project() {
+    let parent = TempDir::new().unwrap();
+    let project = parent.path().join("app");
+    fs::create_dir(&project).unwrap();
+    let files = app(&project, StateFormat::Json)
+        .save(None)
+        .expect("failed save");
+    let content = fs::read_to_string(&files.state_content).unwrap();
+    let project = project.canonicalize().unwrap();
+    assert!(
+        !content.contains(&*project.to_string_lossy()),
+        "{}",
+        content
+    );
+    assert!(!content.contains(&*ToolCache::new().unwrap().root().to_string_lossy()));
+
+    let moved = parent.path().join("moved");
+    fs::rename(&project, &moved).unwrap();
+    let moved = moved.canonicalize().unwrap();
+    let loaded = AutoPack::load(Some(&moved.join(".autopack"))).expect("failed load");
+    assert_eq!(loaded.client_project.path, moved);
+    assert_eq!(loaded.runtime.dir(), moved.join(".autopack"));
+    assert!(Path::new(&loaded.runtime.proc_file().proc_file_path()).starts_with(&moved));
+    assert_eq!(
+        loaded.pack_cli.bin_dir_path,
+        ToolCache::new().unwrap().tool_dir("pack", "0.27.0")
+    );
+}
+
+#[test]
+fn reinit_keeps_track_of_the_scaffold() {
+    let project = TempDir::new().unwrap();
+    let package_json = project.path().join("package.json");
+    let original =
+        r#"{"name": "web", "version": "0.1.0", "scripts": {"start": "react-scripts start"}}"#;
+    fs::write(&package_json, original).unwrap();
+    let runtime_dir = project.path().join(".autopack");
+    // no state to carry over on the first init
+    assert_eq!(
+        AutoPack::recorded_scaffold(&runtime_dir),
+        Scaffold::default()
+    );
 
-    let s = app.save(Some(save_path.path())).expect("failed save");
-    // let st = StateFiles::new(&s).expect("failed to parse the state files");
+    // `init --scripts` adds them
+    let mut first = app(project.path(), StateFormat::default());
+    let (edit, outcome) = scaffold::scripts_edit(project.path()).unwrap();
+    edit.unwrap().write().unwrap();
+    let mut scaffold = Scaffold {
+        scripts: outcome.added,
+        ..Default::default()
+    };
+    scaffold.merge(AutoPack::recorded_scaffold(&runtime_dir));
+    first.runtime.set_scaffold(scaffold);
+    first.save(None).unwrap();
 
-    let lines = fs::read_to_string(s.checksum.clone()).expect("failed reading checksum");
-    let ls = lines.lines().collect::<Vec<_>>();
-    let ls1 = ls[1];
-    let mut ls11: Vec<_> = ls1.chars().take(ls1.len() - 2).collect();
-    ls11.push('0');
-    ls11.push('0');
+    // `init -f --scripts` finds them in place already, `init` doesn't add any
+    for scripts in [true, false] {
+        let recorded = AutoPack::recorded_scaffold(&runtime_dir);
+        let mut again = app(project.path(), StateFormat::default());
+        let mut scaffold = Scaffold::default();
+        if scripts {
+            let (edit, outcome) = scaffold::scripts_edit(project.path()).unwrap();
+            assert!(edit.is_none());
+            scaffold.scripts = outcome.added;
+        }
+        scaffold.merge(recorded);
+        again.runtime.set_scaffold(scaffold);
+        again.save(None).unwrap();
+    }
 
-    let x = ls11.into_iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    let loaded = AutoPack::load(Some(&runtime_dir)).unwrap();
+    assert_eq!(
+        loaded.runtime.scaffold().scripts,
+        ["build:pack", "start:pack"]
+    );
+    for edit in loaded.runtime.scaffold().revert(project.path()).unwrap() {
+        edit.write().unwrap();
+    }
+    assert_eq!(fs::read_to_string(&package_json).unwrap(), original);
+}
+
+#[test]
+fn replaces_states_of_another_format() {
+    let (runtime_dir, app, json) = saved_app_as(StateFormat::Json);
+    let mut toml = app;
+    toml.runtime = Runtime::builder(runtime_dir.path())
+        .dir(false)
+        .expect("")
+        .proc_file(false, None)
+        .expect("")
+        .state_format(Some(StateFormat::Toml))
+        .build();
+    let files = toml.save(None).expect("failed save");
+
+    assert!(!json.state_content.exists() && !json.mac.exists());
+    let state_dir = fs::read_dir(files.state_content.parent().unwrap()).unwrap();
+    assert_eq!(state_dir.count(), 2);
+    assert_eq!(load(&runtime_dir).expect("failed load"), toml);
+}
+
+#[test]
+fn parses_and_validates_config_keys() {
+    use super::ConfigKey;
+
+    assert_eq!("port".parse::<ConfigKey>().unwrap(), ConfigKey::Port);
+    assert_eq!(
+        "env.NODE_OPTIONS".parse::<ConfigKey>().unwrap(),
+        ConfigKey::Env("NODE_OPTIONS".to_string())
+    );
+    for key in ["colour", "env.", "env.1X", "env.A-B", "env.BP_NODE_VERSION"] {
+        assert!(key.parse::<ConfigKey>().is_err(), "{}", key);
+    }
 
-    let lss = format!(
-        r"{}
-    {}",
-        ls[0],
-        &x.join("")
+    assert_eq!(ConfigKey::LiveReload.validate("on").unwrap(), "true");
+    assert_eq!(
+        ConfigKey::NodeVersion.validate(" ^18.12 ").unwrap(),
+        "^18.12"
     );
-    fs::write(s.checksum, lss).expect("failed to write checksum");
+    assert_eq!(ConfigKey::StateFormat.validate("toml").unwrap(), "toml");
+    assert!(ConfigKey::LiveReload.validate("maybe").is_err());
+    assert!(ConfigKey::NodeVersion.validate("18; rm -rf /").is_err());
+    assert!(ConfigKey::Port.validate("0").is_err());
+    assert!(ConfigKey::Port.validate("70000").is_err());
+    assert!(ConfigKey::KeepBuilds.validate("-1").is_err());
+}
+
+#[test]
+fn config_changes_persist() {
+    use super::ConfigKey;
+
+    let dir = TempDir::new().unwrap();
+    let app = app(dir.path(), StateFormat::default());
+    let options = ConfigKey::Env("NODE_OPTIONS".to_string());
+    let changed = app
+        .config_set(&ConfigKey::Port, Some("9000"))
+        .and_then(|a| a.config_set(&ConfigKey::KeepBuilds, Some("3")))
+        .and_then(|a| a.config_set(&options, Some("--max-old-space-size=4096")))
+        .unwrap();
+    changed.save(None).expect("failed save");
+
+    let loaded = AutoPack::load(Some(&changed.runtime.dir())).expect("failed load");
+    assert_eq!(loaded.config_get(&ConfigKey::Port).as_deref(), Some("9000"));
+    assert_eq!(loaded.runtime.keep_builds(), 3);
+    assert_eq!(
+        loaded.config_get(&options).as_deref(),
+        Some("--max-old-space-size=4096")
+    );
+    assert!(loaded.config_list().iter().any(|(k, _)| *k == options));
+
+    let reset = loaded
+        .config_set(&ConfigKey::Port, None)
+        .and_then(|a| a.config_set(&ConfigKey::KeepBuilds, None))
+        .and_then(|a| a.config_set(&options, None))
+        .unwrap();
+    assert_eq!(reset.config_get(&ConfigKey::Port), None);
+    assert_eq!(reset.config_get(&options), None);
+    assert_eq!(reset.buildpack, app.buildpack);
+    assert_eq!(reset.runtime, app.runtime);
+}
+
+#[test]
+fn live_reload_regenerates_only_its_artifacts() {
+    use super::ConfigKey;
+
+    let dir = TempDir::new().unwrap();
+    let app = app(dir.path(), StateFormat::default());
+    let before = app.artifacts().unwrap();
+    app.buildpack
+        .export_toml(Path::new(&app.runtime.project_toml()))
+        .unwrap();
+
+    let live = app
+        .config_set(&ConfigKey::LiveReload, Some("true"))
+        .unwrap();
+    assert_eq!(
+        live.config_get(&ConfigKey::LiveReload).as_deref(),
+        Some("true")
+    );
+    let exported = live.export_artifacts(&before).unwrap();
+    assert_eq!(exported, ["project.toml", "Procfile"]);
+    let procfile = Path::new(&live.runtime.proc_file().proc_file_path()).join("Procfile");
+    assert!(fs::read_to_string(procfile).unwrap().contains("watchexec"));
+    let project_toml = fs::read_to_string(live.runtime.project_toml()).unwrap();
+    assert!(project_toml.contains("BP_LIVE_RELOAD_ENABLED"));
+
+    let port = live.config_set(&ConfigKey::Port, Some("9000")).unwrap();
+    assert!(port
+        .export_artifacts(&live.artifacts().unwrap())
+        .unwrap()
+        .is_empty());
 
-    AutoPack::load(Some(s.state_content.as_path())).expect("failed load");
+    let off = live.config_set(&ConfigKey::LiveReload, None).unwrap();
+    assert_eq!(off.artifacts().unwrap(), before);
 }
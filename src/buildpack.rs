@@ -3,7 +3,12 @@ mod spec;
 mod tests;
 
 use super::log::{success, trying};
-use crate::error::AppError;
+use crate::{
+    error::AppError,
+    lock::write_atomic,
+    package_manager::{PackageManagerInfo, LOCKFILES},
+    workspace::WorkspaceApp,
+};
 use npm_package_json::{Package, RepositoryReference};
 use std::{env, fs, path::Path, vec};
 use toml::Value;
@@ -33,6 +38,42 @@ fn node_version_from_nvmrc() -> Option<String> {
     .ok()
 }
 
+/// Inline buildpack installing dependencies and running the `build` script with a
+/// corepack-provisioned pnpm / Yarn Berry, which the Paketo Node.js buildpack can't install with.
+///
+/// Build env from project.toml is only available under the platform dir (`$2`) to buildpacks.
+fn corepack_install(pm: &PackageManagerInfo) -> BuildPack {
+    let (install, build) = pm.manager.install_and_build();
+    let inline = format!(
+        r#"set -e
+mkdir -p "$1/corepack/bin"
+corepack enable --install-directory "$1/corepack/bin"
+export PATH="$1/corepack/bin:$PATH"
+if [ -f "$2/bindings/npmrc/.npmrc" ]; then export NPM_CONFIG_USERCONFIG="$2/bindings/npmrc/.npmrc"; fi
+{}
+if [ -f "$2/env/BP_NODE_PROJECT_PATH" ]; then cd "$(cat "$2/env/BP_NODE_PROJECT_PATH")"; fi
+{}
+"#,
+        install, build
+    );
+
+    BuildPack {
+        id: Some("autopack/corepack-install".to_string()),
+        buildpack_field: BuildPackField::Script(Some(Script {
+            api: "0.8".to_string(),
+            inline,
+            shell: None,
+        })),
+    }
+}
+
+fn paketo(name: &str) -> BuildPack {
+    BuildPack {
+        id: None,
+        buildpack_field: BuildPackField::Uri(Some(format!("paketo-buildpacks/{}", name))),
+    }
+}
+
 impl Default for BuildPackProject {
     fn default() -> Self {
         BuildPackProject::node_cra_template()
@@ -57,7 +98,7 @@ impl BuildPackProject {
                 },
             ]),
             file_list: Some(FileList::Exclude(
-                vec![
+                [
                     ".devcontainer",
                     ".husky",
                     ".editorconfig",
@@ -74,10 +115,7 @@ impl BuildPackProject {
                 .map(|&x| x.to_string())
                 .collect(),
             )),
-            buildpacks: Some(vec![BuildPack {
-                id: None,
-                buildpack_field: BuildPackField::Uri(Some("paketo-buildpacks/nodejs".to_string())),
-            }]),
+            buildpacks: Some(vec![paketo("nodejs")]),
         });
 
         BuildPackProject {
@@ -106,6 +144,153 @@ impl BuildPackProject {
         self
     }
 
+    /// Whether the image restarts the app on source changes
+    pub(crate) fn live_reload(&self) -> bool {
+        self.has_env("BP_LIVE_RELOAD_ENABLED")
+    }
+
+    pub(crate) fn set_live_reload(&mut self, live_reload: bool) -> &mut Self {
+        self.set_env("BP_LIVE_RELOAD_ENABLED", live_reload.then_some("true"));
+        // buildpacks listed for corepack need watchexec to restart the app
+        let buildpacks = self.build.as_mut().and_then(|b| b.buildpacks.as_mut());
+        if let Some(buildpacks) = buildpacks {
+            buildpacks.retain(|b| *b != paketo("watchexec"));
+            let procfile = buildpacks.iter().position(|b| *b == paketo("procfile"));
+            if let Some(procfile) = procfile.filter(|_| live_reload) {
+                buildpacks.insert(procfile, paketo("watchexec"));
+            }
+        }
+        self
+    }
+
+    /// Sets the build env var `name`, or removes it for `None`
+    pub(crate) fn set_env(&mut self, name: &str, value: Option<&str>) -> &mut Self {
+        if let Some(b) = self.build.as_mut() {
+            let env = b.env.get_or_insert_with(Vec::new);
+            let position = env.iter().position(|e| e.name.as_deref() == Some(name));
+            match (position, value) {
+                (Some(i), Some(value)) => env[i].value = Some(value.to_string()),
+                (None, Some(value)) => env.push(Env {
+                    name: Some(name.to_string()),
+                    value: Some(value.to_string()),
+                }),
+                (Some(i), None) => {
+                    env.remove(i);
+                }
+                (None, None) => (),
+            }
+        }
+        self
+    }
+
+    /// Build env vars as `(name, value)`
+    pub(crate) fn envs(&self) -> Vec<(&str, &str)> {
+        self.build
+            .as_ref()
+            .and_then(|b| b.env.as_ref())
+            .map(|e| {
+                e.iter()
+                    .filter_map(|e| {
+                        e.name
+                            .as_deref()
+                            .map(|n| (n, e.value.as_deref().unwrap_or_default()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// project.toml contents
+    pub(crate) fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Narrows the build context to the app and its local workspace dependencies
+    /// and points the node buildpacks to the app directory.
+    pub(crate) fn with_workspace(&mut self, workspace: &WorkspaceApp) -> &mut Self {
+        if let Some(b) = self.build.as_mut() {
+            b.file_list = Some(FileList::Include(workspace.include_patterns()));
+            if let Some(e) = b.env.as_mut() {
+                e.push(Env {
+                    name: Some("BP_NODE_PROJECT_PATH".to_string()),
+                    value: Some(workspace.container_app_path()),
+                })
+            }
+        }
+        self
+    }
+
+    /// Keeps the given files, relative to the build context, out of it
+    pub(crate) fn leave_out(&mut self, files: &[String]) -> &mut Self {
+        if let Some(b) = self.build.as_mut() {
+            match b.file_list.as_mut() {
+                Some(FileList::Exclude(ex)) => ex.extend(files.iter().cloned()),
+                Some(FileList::Include(inc)) => {
+                    inc.retain(|i| !files.contains(i));
+                    // nested files are part of an included directory
+                    inc.extend(
+                        files
+                            .iter()
+                            .filter(|f| f.contains('/'))
+                            .map(|f| format!("!{}", f)),
+                    );
+                }
+                None => b.file_list = Some(FileList::Exclude(files.to_vec())),
+            }
+        }
+        self
+    }
+
+    fn has_env(&self, name: &str) -> bool {
+        self.env(name).is_some()
+    }
+
+    /// Value of a build env var, e.g. `BP_NODE_VERSION`
+    pub(crate) fn env(&self, name: &str) -> Option<&str> {
+        self.build
+            .as_ref()
+            .and_then(|b| b.env.as_ref())
+            .and_then(|e| e.iter().find(|e| e.name.as_deref() == Some(name)))
+            .map(|e| e.value.as_deref().unwrap_or_default())
+    }
+
+    /// Include and exclude patterns of the file list, as `(include, exclude)`
+    pub(crate) fn file_patterns(&self) -> (Vec<String>, Vec<String>) {
+        match self.build.as_ref().and_then(|b| b.file_list.as_ref()) {
+            Some(FileList::Include(inc)) => (inc.clone(), vec![]),
+            Some(FileList::Exclude(ex)) => (vec![], ex.clone()),
+            None => (vec![], vec![]),
+        }
+    }
+
+    /// Leaves other package managers' lockfiles out of the build context so that the buildpacks
+    /// can't guess wrong, and swaps in a corepack install for pnpm and Yarn Berry.
+    pub(crate) fn with_package_manager(&mut self, pm: &PackageManagerInfo) -> &mut Self {
+        let live_reload = self.has_env("BP_LIVE_RELOAD_ENABLED");
+        let others = LOCKFILES
+            .iter()
+            .filter(|l| !pm.manager.lockfiles().contains(l))
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>();
+        self.leave_out(&others);
+
+        if let Some(b) = self.build.as_mut() {
+            if pm.manager.needs_corepack() {
+                let mut buildpacks = vec![
+                    paketo("ca-certificates"),
+                    paketo("node-engine"),
+                    corepack_install(pm),
+                ];
+                if live_reload {
+                    buildpacks.push(paketo("watchexec"));
+                }
+                buildpacks.push(paketo("procfile"));
+                b.buildpacks = Some(buildpacks);
+            }
+        }
+        self
+    }
+
     pub(crate) fn setup(pkg_json: &Package, live_reload: bool) -> Self {
         trying("Configuring Cloud Native Buildpack configuration");
         let mut base = BuildPackProject::node_cra_template();
@@ -133,18 +318,14 @@ impl BuildPackProject {
             }),
         });
 
-        match base.build.as_mut() {
-            Some(b) => match b.env.as_mut() {
-                Some(e) => e.push(Env {
-                    name: Some("BP_NODE_VERSION".to_string()),
-                    value: node_version_from_env()
-                        .or_else(node_version_from_nvmrc)
-                        .or_else(|| node_version_from_engine(pkg_json.clone()))
-                        .or_else(|| Some("^16.0.0".to_string())),
-                }),
-                None => {}
-            },
-            None => {}
+        if let Some(e) = base.build.as_mut().and_then(|b| b.env.as_mut()) {
+            e.push(Env {
+                name: Some("BP_NODE_VERSION".to_string()),
+                value: node_version_from_env()
+                    .or_else(node_version_from_nvmrc)
+                    .or_else(|| node_version_from_engine(pkg_json.clone()))
+                    .or_else(|| Some("^16.0.0".to_string())),
+            })
         }
 
         success("Finished configuring Cloud Native Buildpacks");
@@ -154,7 +335,8 @@ impl BuildPackProject {
     pub(crate) fn export_toml(&self, export_path: &Path) -> Result<(), AppError> {
         toml::to_string_pretty(self)
             .and_then(|s| {
-                fs::write(export_path, s).map_err(|e| toml::ser::Error::Custom(e.to_string()))?;
+                write_atomic(export_path, s)
+                    .map_err(|e| toml::ser::Error::Custom(e.to_string()))?;
                 Ok(())
             })
             .map_err(|e| {
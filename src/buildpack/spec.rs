@@ -77,7 +77,7 @@ pub(super) enum BuildPackField {
     #[serde(rename = "uri", with = "UriSerDeser")]
     Uri(Option<String>),
     #[serde(rename = "script")]
-    Script(Option<Vec<Script>>),
+    Script(Option<Script>),
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -73,3 +73,64 @@ fn field_test() {
 
     assert_eq!(bp, ret);
 }
+
+#[test]
+fn package_manager_test() {
+    use super::FileList;
+    use crate::package_manager::{PackageManager, PackageManagerInfo};
+
+    let mut bp = BuildPackProject::default();
+    bp.with_package_manager(&PackageManagerInfo {
+        manager: PackageManager::Pnpm,
+        version: Some("8.6.0".to_string()),
+        lockfile: Some("pnpm-lock.yaml".to_string()),
+    });
+
+    let build = bp.build.as_ref().expect("expected build");
+    match build.file_list.as_ref() {
+        Some(FileList::Exclude(ex)) => {
+            assert!(ex.contains(&"package-lock.json".to_string()));
+            assert!(!ex.contains(&"pnpm-lock.yaml".to_string()));
+        }
+        _ => panic!("expected an exclude list"),
+    }
+    assert_eq!(build.buildpacks.as_ref().map(|b| b.len()), Some(4));
+
+    let str = toml::to_string(&bp).expect("failed to serialize");
+    assert!(str.contains("pnpm install --frozen-lockfile"));
+
+    let ret: BuildPackProject = toml::from_str(&str).expect("failed to deserialize");
+    assert_eq!(bp, ret);
+}
+
+#[test]
+fn live_reload_test() {
+    use super::paketo;
+    use crate::package_manager::{PackageManager, PackageManagerInfo};
+
+    let mut bp = BuildPackProject::default();
+    bp.with_package_manager(&PackageManagerInfo {
+        manager: PackageManager::Pnpm,
+        version: None,
+        lockfile: None,
+    });
+    bp.set_live_reload(true);
+    assert_eq!(bp.env("BP_LIVE_RELOAD_ENABLED"), Some("true"));
+    let buildpacks = bp
+        .build
+        .as_ref()
+        .and_then(|b| b.buildpacks.clone())
+        .unwrap();
+    assert_eq!(buildpacks[3], paketo("watchexec"));
+    assert_eq!(buildpacks[4], paketo("procfile"));
+
+    bp.set_live_reload(false);
+    assert!(!bp.live_reload());
+    let buildpacks = bp
+        .build
+        .as_ref()
+        .and_then(|b| b.buildpacks.clone())
+        .unwrap();
+    assert!(!buildpacks.contains(&paketo("watchexec")));
+    assert_eq!(buildpacks.len(), 4);
+}
@@ -1,24 +1,68 @@
 use self::init::Init;
 use crate::{
-    autopack::AutoPack,
+    autopack::{AutoPack, ConfigKey, StateFormat},
     error::AppError,
-    log::{banner, error},
+    lock::{FileLock, LockMode},
+    log::{
+        self, banner, error,
+        file::{self, Verbosity},
+        OutputFormat,
+    },
+    pack::PackSource,
+    prompt::Interaction,
+    runtime::{self, RuntimeLocation},
+    sync::{ArtifactSync, RELOAD_PORT},
+    workspace::{find_app_dir, resolve_app_dir},
 };
 use clap::{self, Parser, Subcommand};
-use std::path::PathBuf;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
 
+mod config;
+mod deinit;
+mod history;
 pub(crate) mod init;
+mod state;
+mod tools;
 
 #[derive(Parser)]
 #[clap(version, about)]
 #[clap(propagate_version = true)]
 /// Auto Pack CLI
 pub struct Cli {
+    /// Wait for other autopack processes working on the project instead of failing
+    #[clap(long = "wait", global = true, action)]
+    wait: bool,
+    /// Never prompt, taking the default answers (default: on when stdin isn't a terminal or `CI` is set)
+    #[clap(long = "non-interactive", global = true, action)]
+    non_interactive: bool,
+    /// Answer yes to every prompt
+    #[clap(short = 'y', long = "yes", global = true, action)]
+    yes: bool,
+    /// Output format; `json` writes an event per line, ending with the result (default: text)
+    #[clap(long = "output", global = true, default_value = "text", possible_values = OutputFormat::NAMES)]
+    output: OutputFormat,
+    /// Shows debug events on the terminal, `-vv` those of libraries too (default: errors only)
+    #[clap(short = 'v', long = "verbose", global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Only shows warnings, errors and results
+    #[clap(
+        short = 'q',
+        long = "quiet",
+        global = true,
+        action,
+        conflicts_with = "verbose"
+    )]
+    quiet: bool,
     #[clap(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
+// parsed once per invocation, so the size of `Init` doesn't matter
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Initializes auto-pack
     Init {
@@ -34,6 +78,56 @@ enum Commands {
         /// Create runtime directory anyway (default: false)
         #[clap(short = 'f', long = "force-create-runtime", action)]
         force_create_runtime: bool,
+        /// App to pack when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+        /// `.npmrc` with private registry credentials (default: project `.npmrc` holding credentials)
+        #[clap(long = "npmrc")]
+        npmrc: Option<PathBuf>,
+        /// Environment variable holding the npm auth token, read at build time
+        #[clap(long = "npm-token-env")]
+        npm_token_env: Option<String>,
+        /// Registry the npm auth token is for
+        #[clap(long = "npm-registry", requires = "npm-token-env")]
+        npm_registry: Option<String>,
+        /// Extra PEM CA certificates to trust, e.g. for a TLS-inspecting proxy
+        #[clap(
+            long = "ca-cert",
+            env = "AUTOPACK_CA_CERTS",
+            multiple_occurrences = true,
+            use_value_delimiter = true
+        )]
+        ca_certs: Vec<PathBuf>,
+        /// pack cli binary to use instead of the one autopack installs
+        #[clap(long = "pack-binary", env = "AUTOPACK_PACK_BINARY")]
+        pack_binary: Option<PathBuf>,
+        /// Local pack cli release archive to install, verified against `<archive>.sha256` if present
+        #[clap(long = "pack-archive", conflicts_with = "pack-binary")]
+        pack_archive: Option<PathBuf>,
+        /// Mirror URL template for pack cli releases, e.g. `https://mirror/pack/{version}/{artifact}`
+        #[clap(long = "pack-mirror", env = "AUTOPACK_PACK_MIRROR")]
+        pack_mirror: Option<String>,
+        /// pack cli version to install (default: the version this autopack release is tested with)
+        #[clap(long = "pack-version", env = "AUTOPACK_PACK_VERSION")]
+        pack_version: Option<String>,
+        /// Number of builds kept in the history, 0 to keep all (default: 20)
+        #[clap(long = "keep-builds")]
+        keep_builds: Option<usize>,
+        /// Never access the network; fail if pack cli isn't available locally
+        #[clap(long = "offline", action)]
+        offline: bool,
+        /// Encoding of the saved state; toml and json can be diffed and committed (default: msgpack)
+        #[clap(long = "state-format", possible_values = StateFormat::NAMES)]
+        state_format: Option<StateFormat>,
+        /// Add `build:pack` and `start:pack` npm scripts to package.json
+        #[clap(long = "scripts", action)]
+        scripts: bool,
+        /// Leave package.json without the npm scripts, as happens unless `--scripts` is given
+        #[clap(long = "no-scripts", action, conflicts_with = "scripts")]
+        no_scripts: bool,
+        /// Where to keep the runtime directory; `cache` keeps it out of the project, under the user cache (default: project)
+        #[clap(long = "runtime-dir", possible_values = RuntimeLocation::NAMES)]
+        runtime_dir: Option<RuntimeLocation>,
     },
 
     /// Build auto-pack
@@ -41,10 +135,20 @@ enum Commands {
         /// Clears cache when building
         #[clap(long = "clear-cache", action)]
         clear_cache: bool,
+        /// Builds even when the sources are unchanged since the last build
+        #[clap(long = "force", action)]
+        force: bool,
+        /// App to build when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
     },
 
     /// Runs auto-pack
     Run {
+        /// App to run when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+
         /// Re-build auto-pack
         #[clap(short = 'b', long = "build", action)]
         build: bool,
@@ -53,10 +157,184 @@ enum Commands {
         #[clap(long = "clear-cache", action)]
         clear_cache: bool,
 
-        /// Port to run the application on
-        #[clap(long = "port", default_value_t = 8080)]
+        /// Re-builds even when the sources are unchanged since the last build
+        #[clap(long = "force", action, requires = "build")]
+        force: bool,
+
+        /// Port to run the application on (default: `port` of `auto-pack config`, else 8080)
+        #[clap(long = "port")]
+        port: Option<usize>,
+
+        /// Builds on the host on every change and copies the changed build output into the
+        /// running container, reloading connected browsers
+        #[clap(long = "sync", action)]
+        sync: bool,
+
+        /// Port browsers connect to for reloads when syncing
+        #[clap(long = "reload-port", default_value_t = RELOAD_PORT, requires = "sync")]
+        reload_port: u16,
+    },
+
+    /// Runs the CRA dev server with HMR inside the built image
+    Dev {
+        /// App to run when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+
+        /// Re-build auto-pack first
+        #[clap(short = 'b', long = "build", action)]
+        build: bool,
+
+        /// Port to reach the dev server and its HMR websocket on
+        #[clap(long = "port", default_value_t = 3000)]
         port: usize,
     },
+
+    /// Lists recorded builds, or compares two of them
+    History {
+        /// App when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+        /// Compares two builds by id
+        #[clap(long = "compare", number_of_values = 2, value_names = &["ID", "ID"])]
+        compare: Option<Vec<u64>>,
+    },
+
+    /// Shows logs
+    Logs {
+        /// Build whose pack output to replay (default: the latest build)
+        #[clap(long = "build")]
+        build: Option<u64>,
+        /// Shows the debug log of autopack itself instead, e.g. for bug reports
+        #[clap(long = "autopack", action, conflicts_with = "build")]
+        autopack: bool,
+        /// App when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+    },
+
+    /// Manages the user level cache of tools autopack installs, e.g. pack
+    Tools {
+        #[clap(subcommand)]
+        command: ToolsCommands,
+    },
+
+    /// Checks and re-signs the autopack state
+    State {
+        #[clap(subcommand)]
+        command: StateCommands,
+    },
+
+    /// Removes what autopack created for the project: containers, images, build cache
+    /// volumes, npm scripts, .gitignore entries, tool cache refs and the runtime folder
+    Deinit {
+        /// Keeps the images and their build cache volumes
+        #[clap(long = "keep-images", action)]
+        keep_images: bool,
+        /// Only lists what would be removed
+        #[clap(long = "dry-run", action)]
+        dry_run: bool,
+        /// App when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+    },
+
+    /// Shows and changes settings of an initialized project without re-initializing it
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Lists the settings with their values
+    List {
+        /// App when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+    },
+    /// Prints the value of a setting
+    Get {
+        /// Setting, e.g. `port` or `env.NODE_OPTIONS`
+        key: ConfigKey,
+        /// App when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+    },
+    /// Changes a setting, regenerating the files it affects
+    #[clap(allow_hyphen_values = true)]
+    Set {
+        /// Setting, e.g. `port` or `env.NODE_OPTIONS`
+        key: ConfigKey,
+        value: String,
+        /// Only shows what would change
+        #[clap(long = "dry-run", action)]
+        dry_run: bool,
+        /// App when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+    },
+    /// Resets a setting to its default, removing env vars
+    Unset {
+        /// Setting, e.g. `port` or `env.NODE_OPTIONS`
+        key: ConfigKey,
+        /// Only shows what would change
+        #[clap(long = "dry-run", action)]
+        dry_run: bool,
+        /// App when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum StateCommands {
+    /// Verifies the state against its MAC, migrating legacy checksums
+    Verify {
+        /// App when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+    },
+    /// Shows the state and signs it with your key once confirmed, e.g. a state committed by
+    /// someone else or written by an older autopack
+    Trust {
+        /// App when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+    },
+    /// Generates a new state key and re-signs the project state with it
+    RotateKey {
+        /// App when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+        /// Removes the retired keys; states of other projects signed with them stop verifying
+        #[clap(long = "drop-old", action)]
+        drop_old: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ToolsCommands {
+    /// Lists the cached tools
+    List,
+    /// Removes cached tools other than the ones this version of autopack and the current project use
+    Prune {
+        /// Removes every cached tool
+        #[clap(long = "all", action)]
+        all: bool,
+    },
+    /// Installs another pack cli version next to the current one and switches the project to it
+    Upgrade {
+        /// pack cli version to switch to (default: the version this autopack release is tested with)
+        version: Option<String>,
+        /// Allow switching to a version older than the current one
+        #[clap(long = "allow-downgrade")]
+        allow_downgrade: bool,
+        /// App to upgrade when the project is a monorepo (package or directory name)
+        #[clap(long = "app")]
+        app: Option<String>,
+    },
 }
 
 impl Cli {
@@ -64,70 +342,377 @@ impl Cli {
         Cli::parse()
     }
 
-    fn build(&self, clear_cache: bool) -> Result<(), AppError> {
+    /// Writes diagnostics to the terminal per `-v`/`-q` and to the log of the project's
+    /// runtime directory; call once before [`Cli::run`]
+    pub fn init_tracing(&self) {
+        file::init(Verbosity::new(self.verbose, self.quiet), self.log_dir());
+    }
+
+    /// Runtime directory the log of this run goes to; `None` when the command removes it
+    fn log_dir(&self) -> Option<PathBuf> {
+        let cwd = env::current_dir().ok()?;
+        let app = match &self.command {
+            Commands::Deinit { dry_run: false, .. } => return None,
+            Commands::Init {
+                client_project_path,
+                app,
+                runtime_dir,
+                ..
+            } => {
+                let root = client_project_path.clone().unwrap_or(cwd);
+                let root = match app {
+                    Some(app) => find_app_dir(&root, app).ok()?,
+                    None => root,
+                };
+                return match runtime_dir {
+                    Some(location) => runtime::runtime_dir(&root, *location).ok(),
+                    None => Some(runtime::locate(&root)),
+                };
+            }
+            Commands::Build { app, .. }
+            | Commands::Run { app, .. }
+            | Commands::Dev { app, .. }
+            | Commands::History { app, .. }
+            | Commands::Logs { app, .. }
+            | Commands::Deinit { app, .. }
+            | Commands::Config {
+                command:
+                    ConfigCommands::List { app }
+                    | ConfigCommands::Get { app, .. }
+                    | ConfigCommands::Set { app, .. }
+                    | ConfigCommands::Unset { app, .. },
+            }
+            | Commands::State {
+                command:
+                    StateCommands::Verify { app }
+                    | StateCommands::Trust { app }
+                    | StateCommands::RotateKey { app, .. },
+            }
+            | Commands::Tools {
+                command: ToolsCommands::Upgrade { app, .. },
+            } => app,
+            Commands::Tools { .. } => &None,
+        };
+        match app {
+            Some(app) => find_app_dir(&cwd, app)
+                .ok()
+                .map(|dir| runtime::locate(&dir)),
+            None => Some(runtime::locate(&cwd)),
+        }
+    }
+
+    /// Runtime directory of the app selected with `--app`, or `None` for the current directory
+    fn runtime_dir(app: Option<&str>) -> Result<Option<PathBuf>, AppError> {
+        match app {
+            None => Ok(None),
+            Some(_) => {
+                let cwd = env::current_dir()
+                    .map_err(|e| AppError::IOError("Failed getting the current directory", e))?;
+                Ok(Some(runtime::locate(&resolve_app_dir(&cwd, app)?)))
+            }
+        }
+    }
+
+    /// `auto-pack build` for the same app, run in the background while syncing
+    fn rebuild_command(app: &Option<String>) -> Result<Vec<String>, AppError> {
+        let exe = env::current_exe()
+            .map_err(|e| AppError::IOError("Failed locating the auto-pack executable", e))?;
+        let mut argv = vec![
+            exe.to_string_lossy().to_string(),
+            "build".to_string(),
+            "--wait".to_string(),
+        ];
+        if let Some(app) = app {
+            argv.extend(["--app".to_string(), app.clone()]);
+        }
+        Ok(argv)
+    }
+
+    /// Locks the runtime directory of the app for the command
+    fn lock(
+        &self,
+        runtime_dir: Option<&Path>,
+        mode: LockMode,
+    ) -> Result<Option<FileLock>, AppError> {
+        let runtime_dir = runtime_dir
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| runtime::locate(Path::new(".")));
+        FileLock::runtime(&runtime_dir, mode, self.wait)
+    }
+
+    /// Loads the state under a shared lock, released once loaded
+    fn load(&self, runtime_dir: Option<&Path>) -> Result<AutoPack, AppError> {
+        let _lock = self.lock(runtime_dir, LockMode::Shared)?;
+        AutoPack::load_validate(runtime_dir).inspect_err(|_| {
+            error("Failed validating autopack. Please run `auto-pack init` again.");
+        })
+    }
+
+    fn build(
+        &self,
+        clear_cache: bool,
+        force: bool,
+        runtime_dir: Option<&Path>,
+    ) -> Result<(), AppError> {
         banner("Building project using autopack");
-        let autopack = AutoPack::load_validate(None).map_err(|e| {
+        let _lock = self.lock(runtime_dir, LockMode::Exclusive)?;
+        let autopack = AutoPack::load_validate(runtime_dir).inspect_err(|_| {
             error("Failed validating autopack. Please run `auto-pack init` again.");
-            e
         })?;
 
-        autopack.build(clear_cache).map_err(|e| {
+        autopack.build(clear_cache, force).map_err(|e| {
             error("Autopack build failure. Exiting.");
-            AppError::BuildError("Build failure", e)
+            // diagnosed failures carry their own remediation
+            e.downcast::<AppError>()
+                .unwrap_or_else(|e| AppError::BuildError("Build failure", e))
         })?;
         Ok(())
     }
+    /// Runs the command; with `--output json` its last event is the `result`, which
+    /// [`AppError::handle`] reports for failures
     pub async fn run(self) -> Result<(), AppError> {
-        // let cli = Cli::parse();
+        log::set_output(self.output, self.quiet);
+        Interaction::detect(self.non_interactive, self.yes).set();
+        let result = self.execute().await;
+        if result.is_ok() {
+            log::result(None);
+        }
+        result
+    }
+
+    async fn execute(self) -> Result<(), AppError> {
         match self.command {
             Commands::Init {
                 ref client_project_path,
                 live_reload,
                 no_build,
                 force_create_runtime,
+                ref app,
+                ref npmrc,
+                ref npm_token_env,
+                ref npm_registry,
+                ref ca_certs,
+                ref pack_binary,
+                ref pack_archive,
+                ref pack_mirror,
+                ref pack_version,
+                keep_builds,
+                offline,
+                state_format,
+                scripts,
+                no_scripts,
+                runtime_dir,
             } => {
                 banner("Initializing autopack");
-                let ap = Init::pre_configure(client_project_path.clone())?
+                let mut init = Init::pre_configure(
+                    client_project_path.clone(),
+                    app.clone(),
+                    Interaction::current(),
+                )?;
+                let ap = init
+                    .ca_certs(ca_certs.clone())?
+                    .pack_source(
+                        PackSource {
+                            binary: pack_binary.clone(),
+                            archive: pack_archive.clone(),
+                            mirror: pack_mirror.clone(),
+                            offline,
+                        },
+                        pack_version.clone(),
+                    )
+                    .npmrc(npmrc.clone(), npm_token_env.clone(), npm_registry.clone())?
                     .configure(live_reload)?
+                    .keep_builds(keep_builds)
+                    .state_format(state_format)
+                    .runtime_location(runtime_dir)
+                    .wait(self.wait)
                     .post_configure(force_create_runtime, live_reload)
                     .await?
+                    .scaffold(scripts && !no_scripts)?
                     .install();
 
                 ap.save(None).map_err(|e| {
                     AppError::PostConfigureError("Failed to serialize autopack state", e)
                 })?;
+                // releases the lock for the build
+                drop(init);
 
                 banner("Initialized autopack");
 
                 if !no_build {
-                    self.build(false)?;
+                    self.build(false, false, Some(&ap.runtime.dir()))?;
                 }
 
                 Ok(())
             }
 
-            Commands::Build { clear_cache } => self.build(clear_cache),
+            Commands::Build {
+                clear_cache,
+                force,
+                ref app,
+            } => self.build(
+                clear_cache,
+                force,
+                Cli::runtime_dir(app.as_deref())?.as_deref(),
+            ),
 
             Commands::Run {
+                ref app,
                 build,
                 clear_cache,
+                force,
                 port,
+                sync,
+                reload_port,
             } => {
-                let autopack = AutoPack::load_validate(None).map_err(|e| {
-                    error("Failed validating autopack. Please run `auto-pack init` again.");
-                    e
-                })?;
+                let runtime_dir = Cli::runtime_dir(app.as_deref())?;
+                let autopack = self.load(runtime_dir.as_deref())?;
 
                 if build {
-                    self.build(clear_cache)?;
+                    self.build(clear_cache, force, runtime_dir.as_deref())?;
                 }
 
-                autopack.run(port).await.map_err(|e| {
+                let port = port.or_else(|| autopack.runtime.port()).unwrap_or(8080);
+                let result = if sync {
+                    let sync =
+                        ArtifactSync::new(&autopack, reload_port, Cli::rebuild_command(app)?);
+                    autopack.run_synced(port, sync).await
+                } else {
+                    autopack.run(port).await
+                };
+                result.map_err(|e| {
                     AppError::RunError("Failed running autopack project", anyhow::anyhow!(e))
                 })?;
 
                 Ok(())
             }
+
+            Commands::Dev {
+                ref app,
+                build,
+                port,
+            } => {
+                let runtime_dir = Cli::runtime_dir(app.as_deref())?;
+                let autopack = self.load(runtime_dir.as_deref())?;
+
+                if build {
+                    self.build(false, false, runtime_dir.as_deref())?;
+                }
+
+                autopack.dev(port).await.map_err(|e| {
+                    AppError::RunError("Failed running the dev server", anyhow::anyhow!(e))
+                })?;
+
+                Ok(())
+            }
+
+            Commands::History {
+                ref app,
+                ref compare,
+            } => {
+                let runtime_dir = Cli::runtime_dir(app.as_deref())?;
+                match compare.as_deref() {
+                    Some([a, b]) => history::compare(runtime_dir.as_deref(), *a, *b),
+                    _ => history::list(runtime_dir.as_deref()),
+                }
+            }
+
+            Commands::Logs {
+                build,
+                autopack,
+                ref app,
+            } => {
+                let runtime_dir = Cli::runtime_dir(app.as_deref())?;
+                if autopack {
+                    history::autopack_logs(runtime_dir.as_deref())
+                } else {
+                    history::logs(runtime_dir.as_deref(), build)
+                }
+            }
+
+            Commands::Tools { ref command } => match command {
+                ToolsCommands::List => tools::list(),
+                ToolsCommands::Prune { all } => tools::prune(*all),
+                ToolsCommands::Upgrade {
+                    ref version,
+                    allow_downgrade,
+                    ref app,
+                } => {
+                    let runtime_dir = Cli::runtime_dir(app.as_deref())?;
+                    let _lock = self.lock(runtime_dir.as_deref(), LockMode::Exclusive)?;
+                    tools::upgrade(version.as_deref(), *allow_downgrade, runtime_dir.as_deref())
+                        .await
+                }
+            },
+
+            Commands::State { ref command } => {
+                let app = match command {
+                    StateCommands::Verify { app }
+                    | StateCommands::Trust { app }
+                    | StateCommands::RotateKey { app, .. } => app,
+                };
+                let runtime_dir = Cli::runtime_dir(app.as_deref())?;
+                // verifying re-signs states signed with a retired key
+                let _lock = self.lock(runtime_dir.as_deref(), LockMode::Exclusive)?;
+                match command {
+                    StateCommands::Verify { .. } => state::verify(runtime_dir.as_deref()),
+                    StateCommands::Trust { .. } => {
+                        state::trust(runtime_dir.as_deref(), Interaction::current())
+                    }
+                    StateCommands::RotateKey { drop_old, .. } => {
+                        state::rotate_key(runtime_dir.as_deref(), *drop_old)
+                    }
+                }
+            }
+
+            Commands::Deinit {
+                keep_images,
+                dry_run,
+                ref app,
+            } => {
+                let runtime_dir = Cli::runtime_dir(app.as_deref())?;
+                let mode = if dry_run {
+                    LockMode::Shared
+                } else {
+                    LockMode::Exclusive
+                };
+                let lock = self.lock(runtime_dir.as_deref(), mode)?;
+                deinit::deinit(runtime_dir, lock, keep_images, dry_run)
+            }
+
+            Commands::Config { ref command } => {
+                let app = match command {
+                    ConfigCommands::List { app }
+                    | ConfigCommands::Get { app, .. }
+                    | ConfigCommands::Set { app, .. }
+                    | ConfigCommands::Unset { app, .. } => app,
+                };
+                let runtime_dir = Cli::runtime_dir(app.as_deref())?;
+                let runtime_dir = runtime_dir.as_deref();
+                match command {
+                    ConfigCommands::List { .. } => {
+                        let _lock = self.lock(runtime_dir, LockMode::Shared)?;
+                        config::list(runtime_dir)
+                    }
+                    ConfigCommands::Get { key, .. } => {
+                        let _lock = self.lock(runtime_dir, LockMode::Shared)?;
+                        config::get(runtime_dir, key)
+                    }
+                    ConfigCommands::Set {
+                        key,
+                        value,
+                        dry_run,
+                        ..
+                    } => {
+                        let _lock = self.lock(runtime_dir, LockMode::Exclusive)?;
+                        config::set(runtime_dir, key, Some(value), *dry_run)
+                    }
+                    ConfigCommands::Unset { key, dry_run, .. } => {
+                        let _lock = self.lock(runtime_dir, LockMode::Exclusive)?;
+                        config::set(runtime_dir, key, None, *dry_run)
+                    }
+                }
+            }
         }
     }
 }
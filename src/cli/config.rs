@@ -0,0 +1,99 @@
+use crate::{
+    autopack::{AutoPack, ConfigKey},
+    error::AppError,
+    log::{diff, instruct, is_json, print, record, success, trying},
+};
+use serde_json::json;
+use std::path::Path;
+
+fn load(runtime_dir: Option<&Path>) -> Result<AutoPack, AppError> {
+    AutoPack::load(runtime_dir).map_err(|e| {
+        crate::log::error("Please run `auto-pack init` to initialize autopack.");
+        AppError::RunError("Failed loading autopack state", e)
+    })
+}
+
+fn shown(value: Option<&str>) -> &str {
+    value.unwrap_or("(unset)")
+}
+
+/// Prints every setting with its value
+pub(crate) fn list(runtime_dir: Option<&Path>) -> Result<(), AppError> {
+    let autopack = load(runtime_dir)?;
+    for (key, value) in autopack.config_list() {
+        let row = format!("{:<24} {}", key.to_string(), shown(value.as_deref()));
+        record(&json!({ "key": key.to_string(), "value": value }), &row);
+    }
+    Ok(())
+}
+
+/// Prints the value of `key`, nothing when it's unset
+pub(crate) fn get(runtime_dir: Option<&Path>, key: &ConfigKey) -> Result<(), AppError> {
+    let value = load(runtime_dir)?.config_get(key);
+    let data = json!({ "key": key.to_string(), "value": value });
+    match value {
+        Some(value) => record(&data, &value),
+        None if is_json() => record(&data, ""),
+        None => (),
+    }
+    Ok(())
+}
+
+/// Sets `key`, or resets it for `None`, after previewing the change; only the generated
+/// files which change are rewritten
+pub(crate) fn set(
+    runtime_dir: Option<&Path>,
+    key: &ConfigKey,
+    value: Option<&str>,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let config_error = |e| AppError::RunError("Failed changing the configuration", e);
+    let autopack = load(runtime_dir)?;
+    let changed = autopack.config_set(key, value).map_err(config_error)?;
+    if changed == autopack {
+        instruct(&format!(
+            "{} is already {}",
+            key,
+            shown(autopack.config_get(key).as_deref())
+        ));
+        return Ok(());
+    }
+
+    let before = autopack.artifacts().map_err(config_error)?;
+    let after = changed.artifacts().map_err(config_error)?;
+    print(&format!(
+        "{}: {} -> {}",
+        key,
+        shown(autopack.config_get(key).as_deref()),
+        shown(changed.config_get(key).as_deref())
+    ));
+    if before.project_toml != after.project_toml {
+        print("  project.toml");
+        diff(&before.project_toml, &after.project_toml);
+    }
+    if before.procfile != after.procfile {
+        print("  Procfile");
+        diff(&before.procfile, &after.procfile);
+    }
+    if dry_run {
+        instruct("Dry run, nothing changed");
+        return Ok(());
+    }
+
+    trying(&format!("Updating {}", key));
+    let exported = changed.export_artifacts(&before).map_err(config_error)?;
+    changed
+        .save(None)
+        .map_err(|e| AppError::RunError("Failed saving autopack state", e))?;
+    if exported.is_empty() {
+        success(&format!("Updated {}", key));
+    } else {
+        success(&format!(
+            "Updated {}, regenerated {}",
+            key,
+            exported.join(", ")
+        ));
+        instruct("Run `auto-pack build` to apply it to the image.");
+    }
+    Ok(())
+}
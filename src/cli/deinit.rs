@@ -0,0 +1,211 @@
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    autopack::AutoPack,
+    error::AppError,
+    history::History,
+    lock::FileLock,
+    log::{diff, error, instruct, print, success, trying, warn},
+    pack::cache_volumes,
+    scaffold::FileEdit,
+    tools::ToolCache,
+};
+use std::{fmt, fs, path::PathBuf};
+use tracing::debug;
+
+/// Something autopack created for the project
+#[derive(Debug, Clone, PartialEq)]
+enum Resource {
+    Container(String),
+    Image(String),
+    Volume(String),
+    /// npm scripts or `.gitignore` entries added to a project file
+    Edit(FileEdit),
+    ToolRef(String),
+    RuntimeDir(PathBuf),
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Container(id) => write!(f, "container {}", id),
+            Resource::Image(image) => write!(f, "image {}", image),
+            Resource::Volume(name) => write!(f, "build cache volume {}", name),
+            Resource::Edit(edit) => write!(f, "autopack entries in {}", edit.path.display()),
+            Resource::ToolRef(version) => write!(f, "tool cache ref to pack cli {}", version),
+            Resource::RuntimeDir(dir) => write!(f, "runtime folder {}", dir.display()),
+        }
+    }
+}
+
+/// Docker resources of the project, per the recorded image name and build history
+fn docker_resources(autopack: &AutoPack, keep_images: bool) -> anyhow::Result<Vec<Resource>> {
+    let docker = &autopack.docker;
+    let image_name = &autopack.client_project.image_name;
+    let mut resources = docker
+        .project_containers(&autopack.client_project.path, image_name)?
+        .into_iter()
+        .map(Resource::Container)
+        .collect::<Vec<_>>();
+    if keep_images {
+        return Ok(resources);
+    }
+
+    let mut ids = vec![];
+    if let Some(id) = docker.image_id(image_name)? {
+        ids.push(id);
+        resources.push(Resource::Image(image_name.clone()));
+    }
+    // images of earlier builds, untagged by the later ones
+    let records = History::new(&autopack.runtime.dir()).list()?;
+    for digest in records.iter().rev().filter_map(|r| r.digest.as_deref()) {
+        let digest = digest.trim_start_matches("sha256:");
+        if let Some(id) = docker.image_id(digest)? {
+            if !ids.contains(&id) {
+                resources.push(Resource::Image(digest.to_string()));
+                ids.push(id);
+            }
+        }
+    }
+    resources.extend(
+        cache_volumes(image_name)
+            .into_iter()
+            .filter(|v| docker.volume_exists(v))
+            .map(Resource::Volume),
+    );
+    Ok(resources)
+}
+
+/// Everything to remove, the runtime folder last. Without Docker the images would be lost
+/// track of, unless they're kept anyway.
+fn resources(autopack: &AutoPack, keep_images: bool) -> Result<Vec<Resource>, AppError> {
+    let mut resources = match docker_resources(autopack, keep_images) {
+        Ok(resources) => resources,
+        Err(e) if keep_images => {
+            warn(&format!(
+                "Docker isn't reachable; leaving its containers :: {}",
+                e
+            ));
+            vec![]
+        }
+        Err(e) => {
+            instruct("Start Docker, or pass `--keep-images` to leave the images behind.");
+            return Err(AppError::RunError(
+                "Failed looking up the images of the project",
+                e,
+            ));
+        }
+    };
+
+    let project_root = &autopack.client_project.path;
+    let edits = autopack
+        .runtime
+        .scaffold()
+        .revert(project_root)
+        .map_err(|e| AppError::RunError("Failed reading the project files", e))?;
+    resources.extend(edits.into_iter().map(Resource::Edit));
+
+    let pack = &autopack.pack_cli;
+    let referenced = ToolCache::new()
+        .map(|cache| {
+            let root = project_root
+                .canonicalize()
+                .unwrap_or_else(|_| project_root.clone());
+            cache.refs("pack", &pack.release_version).contains(&root)
+        })
+        .unwrap_or(false);
+    if referenced {
+        resources.push(Resource::ToolRef(pack.release_version.clone()));
+    }
+    resources.push(Resource::RuntimeDir(autopack.runtime.dir()));
+    Ok(resources)
+}
+
+fn remove(autopack: &AutoPack, resource: &Resource) -> anyhow::Result<()> {
+    let docker = &autopack.docker;
+    match resource {
+        Resource::Container(id) => docker.remove_container(id),
+        Resource::Image(image) => docker.remove_image(image),
+        Resource::Volume(name) => docker.remove_volume(name),
+        Resource::Edit(edit) => Ok(edit.write()?),
+        Resource::ToolRef(version) => ToolCache::new()
+            .and_then(|c| c.remove_ref("pack", version, &autopack.client_project.path))
+            .map(|_| ()),
+        Resource::RuntimeDir(dir) => Ok(fs::remove_dir_all(dir)?),
+    }
+}
+
+/// Removes what autopack created for the project, as recorded in its state: containers,
+/// images, build cache volumes, npm scripts and `.gitignore` entries, its tool cache refs
+/// and lastly the runtime folder. Anything
+/// failing to be removed keeps the runtime folder, so that `deinit` can be run again.
+pub(crate) fn deinit(
+    runtime_dir: Option<PathBuf>,
+    lock: Option<FileLock>,
+    keep_images: bool,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    if lock.is_none() {
+        instruct("autopack isn't initialized here, nothing to remove.");
+        return Ok(());
+    }
+    let autopack = AutoPack::load(runtime_dir.as_deref()).map_err(|e| {
+        error("Remove the runtime folder by hand if the state can't be recovered.");
+        AppError::RunError("Failed loading autopack state", e)
+    })?;
+
+    trying("Looking up what autopack created");
+    let resources = resources(&autopack, keep_images)?;
+    if dry_run {
+        for resource in resources.iter() {
+            print(&format!("  would remove {}", resource));
+            if let Resource::Edit(edit) = resource {
+                diff(&edit.before, &edit.after);
+            }
+        }
+        instruct("Dry run, nothing removed");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for resource in resources.iter() {
+        if let Resource::RuntimeDir(_) = resource {
+            if failed > 0 {
+                break;
+            }
+            // the lock file goes with the folder
+            drop(lock);
+            remove(&autopack, resource)
+                .map_err(|e| AppError::RunError("Failed removing the runtime folder", e))?;
+            success(&format!("Removed {}", resource));
+            break;
+        }
+        match remove(&autopack, resource) {
+            Ok(()) => success(&format!("Removed {}", resource)),
+            Err(e) => {
+                debug!("Failed removing {} :: {:?}", resource, e);
+                warn(&format!("Failed removing {} :: {}", resource, e));
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(AppError::RunError(
+            "Failed removing some of what autopack created",
+            anyhow::anyhow!(
+                "{} resource(s) left; the runtime folder is kept so that `auto-pack deinit` can be run again",
+                failed
+            ),
+        ));
+    }
+    if keep_images {
+        instruct(&format!(
+            "Kept image {} and its build cache",
+            autopack.client_project.image_name
+        ));
+    }
+    success("Removed autopack from the project");
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use super::{remove, resources, Resource};
+use crate::{
+    autopack::AutoPack,
+    runtime::Runtime,
+    scaffold::{self, Scaffold},
+};
+use std::{fs, path::Path};
+use tempfile::TempDir;
+
+const PACKAGE_JSON: &str =
+    r#"{"name": "web", "version": "0.1.0", "scripts": {"start": "react-scripts start"}}"#;
+
+/// Saves the state of an init of the project, carrying over the edits of the previous one
+fn init(project_dir: &Path, scripts: bool) {
+    let runtime = Runtime::builder(project_dir)
+        .dir(false)
+        .unwrap()
+        .proc_file(false, None)
+        .unwrap()
+        .build();
+    let mut scaffold = Scaffold::default();
+    if scripts {
+        let (edit, outcome) = scaffold::scripts_edit(project_dir).unwrap();
+        if let Some(edit) = edit {
+            edit.write().unwrap();
+        }
+        scaffold.scripts = outcome.added;
+    }
+    scaffold.merge(AutoPack::recorded_scaffold(&runtime.dir()));
+
+    let mut autopack = AutoPack::new(Some(runtime));
+    autopack.client_project.path = project_dir.canonicalize().unwrap();
+    autopack.client_project.image_name = "autopack-deinit-test".to_string();
+    autopack.runtime.set_scaffold(scaffold);
+    autopack.save(None).unwrap();
+}
+
+#[test]
+fn reverts_the_scripts_of_earlier_inits() {
+    let project = TempDir::new().unwrap();
+    let package_json = project.path().join("package.json");
+    fs::write(&package_json, PACKAGE_JSON).unwrap();
+    init(project.path(), true);
+    init(project.path(), true);
+    init(project.path(), false);
+
+    let autopack = AutoPack::load(Some(&project.path().join(".autopack"))).unwrap();
+    // Docker may not be around, the images are kept anyway
+    let resources = resources(&autopack, true).unwrap();
+    let edits = resources
+        .iter()
+        .filter(|r| matches!(r, Resource::Edit(_)))
+        .collect::<Vec<_>>();
+    assert_eq!(edits.len(), 1);
+    remove(&autopack, edits[0]).unwrap();
+    assert_eq!(fs::read_to_string(&package_json).unwrap(), PACKAGE_JSON);
+    assert!(matches!(resources.last(), Some(Resource::RuntimeDir(_))));
+}
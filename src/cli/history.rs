@@ -0,0 +1,146 @@
+use crate::{
+    error::AppError,
+    history::{format_utc, BuildRecord, History},
+    log::{diff, file::log_path, instruct, is_json, print, record},
+    runtime,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+fn dir(runtime_dir: Option<&Path>) -> PathBuf {
+    runtime_dir
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| runtime::locate(Path::new(".")))
+}
+
+fn history(runtime_dir: Option<&Path>) -> History {
+    History::new(&dir(runtime_dir))
+}
+
+fn result(record: &BuildRecord) -> &'static str {
+    match (record.finished_at, record.success) {
+        (None, _) => "running",
+        (_, true) => "ok",
+        (_, false) => "failed",
+    }
+}
+
+/// Prints the recorded builds, oldest first
+pub(crate) fn list(runtime_dir: Option<&Path>) -> Result<(), AppError> {
+    let records = history(runtime_dir)
+        .list()
+        .map_err(|e| AppError::RunError("Failed reading the build history", e))?;
+    if records.is_empty() {
+        instruct("No builds recorded yet. Run `auto-pack build` first.");
+        return Ok(());
+    }
+
+    if !is_json() {
+        print(&format!(
+            "{:>4}  {:<19}  {:<7}  {:>8}  {:<18}  DIGEST",
+            "ID", "STARTED (UTC)", "RESULT", "DURATION", "COMMIT"
+        ));
+    }
+    for r in records {
+        let row = format!(
+            "{:>4}  {:<19}  {:<7}  {:>8}  {:<18}  {}",
+            r.id,
+            format_utc(r.started_at),
+            result(&r),
+            r.duration()
+                .map(|d| format!("{}s", d.as_secs()))
+                .unwrap_or_default(),
+            r.git_commit.as_deref().unwrap_or("-"),
+            r.digest.as_deref().unwrap_or("-")
+        );
+        record(&r, &row);
+    }
+    Ok(())
+}
+
+/// Prints what changed between two builds: outcome, commit, digest, phase timings and project.toml
+pub(crate) fn compare(runtime_dir: Option<&Path>, a: u64, b: u64) -> Result<(), AppError> {
+    let history = history(runtime_dir);
+    let get = |id| {
+        history
+            .get(id)
+            .map_err(|e| AppError::RunError("Failed reading the build history", e))
+    };
+    let (a, b) = (get(a)?, get(b)?);
+
+    print(&format!("Build {} -> {}", a.id, b.id));
+    let field = |name: &str, x: &str, y: &str| {
+        if x == y {
+            print(&format!("  {:<10} {}", name, x));
+        } else {
+            print(&format!("  {:<10} {} -> {}", name, x, y));
+        }
+    };
+    field("result", result(&a), result(&b));
+    field(
+        "commit",
+        a.git_commit.as_deref().unwrap_or("-"),
+        b.git_commit.as_deref().unwrap_or("-"),
+    );
+    field(
+        "digest",
+        a.digest.as_deref().unwrap_or("-"),
+        b.digest.as_deref().unwrap_or("-"),
+    );
+
+    for timing in b.phases.iter() {
+        let before = a.phases.iter().find(|p| p.phase == timing.phase);
+        match before {
+            Some(before) => print(&format!(
+                "  {:<10} {:.1}s -> {:.1}s ({:+.1}s)",
+                timing.phase,
+                before.secs,
+                timing.secs,
+                timing.secs - before.secs
+            )),
+            None => print(&format!("  {:<10} - -> {:.1}s", timing.phase, timing.secs)),
+        }
+    }
+
+    let read = |r: &BuildRecord| fs::read_to_string(r.project_toml_path()).unwrap_or_default();
+    let (x, y) = (read(&a), read(&b));
+    if x == y {
+        print("  project.toml unchanged");
+    } else {
+        print("  project.toml changed");
+        diff(&x, &y);
+    }
+    Ok(())
+}
+
+/// Replays the pack output of a build, the latest one by default
+pub(crate) fn logs(runtime_dir: Option<&Path>, build: Option<u64>) -> Result<(), AppError> {
+    let history = history(runtime_dir);
+    let record = match build {
+        Some(id) => history.get(id),
+        None => history
+            .latest()
+            .and_then(|r| r.ok_or_else(|| anyhow::anyhow!("No builds recorded yet"))),
+    }
+    .map_err(|e| AppError::RunError("Failed reading the build history", e))?;
+
+    let log = fs::read_to_string(record.log_path())
+        .map_err(|e| AppError::IOError("Failed reading the build log", e))?;
+    print(&log);
+    Ok(())
+}
+
+/// Prints the debug log autopack keeps of its runs, rotated logs aside
+pub(crate) fn autopack_logs(runtime_dir: Option<&Path>) -> Result<(), AppError> {
+    let path = log_path(&dir(runtime_dir));
+    if !path.exists() {
+        instruct("No autopack log yet. It's written once the project is initialized.");
+        return Ok(());
+    }
+    let log = fs::read_to_string(&path)
+        .map_err(|e| AppError::IOError("Failed reading the autopack log", e))?;
+    print(log.trim_end());
+    Ok(())
+}
@@ -1,13 +1,19 @@
 use crate::{
-    autopack::AutoPack,
+    autopack::{AutoPack, StateFormat},
     buildpack::BuildPackProject,
     docker::Docker,
     error::AppError,
-    pack::Pack,
+    lock::{FileLock, LockMode, LOCK_FILE},
+    log::{self, diff, print, success, trying},
+    network::Network,
+    pack::{Pack, PackSource},
     package_json::{CreatePackageJson, Project},
-    runtime::Runtime,
+    prompt::Interaction,
+    runtime::{self, NpmrcSource, Runtime, RuntimeLocation, DEFAULT_REGISTRY},
+    scaffold::{self, Scaffold},
+    tools::ToolCache,
 };
-use std::{env, io, path::PathBuf};
+use std::{env, fs, io, mem, path::PathBuf};
 use tracing::{debug, warn};
 
 /// Builder for [`crate::app::autopack::AutoPack`]
@@ -17,10 +23,27 @@ pub struct Init {
     buildpack: Option<BuildPackProject>,
     runtime: Option<Runtime>,
     pack_cli: Option<Pack>,
+    npmrc: Option<NpmrcSource>,
+    network: Network,
+    pack_source: PackSource,
+    pack_version: Option<String>,
+    keep_builds: Option<usize>,
+    state_format: Option<StateFormat>,
+    runtime_location: Option<RuntimeLocation>,
+    wait: bool,
+    interaction: Interaction,
+    /// held from creating the runtime directory until the state is saved
+    lock: Option<FileLock>,
+    /// project edits recorded by the state this init replaces
+    recorded: Scaffold,
 }
 
 impl Init {
-    pub(crate) fn pre_configure(client_project_path: Option<PathBuf>) -> Result<Self, AppError> {
+    pub(crate) fn pre_configure(
+        client_project_path: Option<PathBuf>,
+        app: Option<String>,
+        interaction: Interaction,
+    ) -> Result<Self, AppError> {
         debug!("Begin pre-configure");
         let path = client_project_path
             .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
@@ -31,8 +54,8 @@ impl Init {
                 AppError::PreconfigureError(msg, anyhow::anyhow!(e))
             })?;
 
-        let docker = Docker::check()?;
-        let package_json = CreatePackageJson::new(&path).build()?;
+        let docker = Docker::check(interaction)?;
+        let package_json = CreatePackageJson::new(&path).app(app).build()?;
 
         debug!("End pre-configure");
         Ok(Init {
@@ -41,12 +64,129 @@ impl Init {
             buildpack: None,
             runtime: None,
             pack_cli: None,
+            npmrc: None,
+            network: Network::default(),
+            pack_source: PackSource::default(),
+            pack_version: None,
+            keep_builds: None,
+            state_format: None,
+            runtime_location: None,
+            wait: false,
+            interaction,
+            lock: None,
+            recorded: Scaffold::default(),
         })
     }
 
+    /// Extra CA certificates trusted when downloading tools and inside the build containers
+    pub(crate) fn ca_certs(&mut self, ca_certs: Vec<PathBuf>) -> Result<&mut Self, AppError> {
+        self.network = Network::new(ca_certs)
+            .map_err(|e| AppError::PreconfigureError("Failed loading CA certificates", e))?;
+        if !self.network.ca_certs.is_empty() {
+            success("Loaded extra CA certificates");
+        }
+        Ok(self)
+    }
+
+    /// Where to get the pack cli from when it isn't cached yet, and which version
+    pub(crate) fn pack_source(&mut self, source: PackSource, version: Option<String>) -> &mut Self {
+        self.pack_source = source;
+        self.pack_version = version;
+        self
+    }
+
+    /// Number of builds kept in the history
+    pub(crate) fn keep_builds(&mut self, keep_builds: Option<usize>) -> &mut Self {
+        self.keep_builds = keep_builds;
+        self
+    }
+
+    /// Encoding of the saved state
+    pub(crate) fn state_format(&mut self, state_format: Option<StateFormat>) -> &mut Self {
+        self.state_format = state_format;
+        self
+    }
+
+    /// Where to keep the runtime directory
+    pub(crate) fn runtime_location(&mut self, location: Option<RuntimeLocation>) -> &mut Self {
+        self.runtime_location = location;
+        self
+    }
+
+    /// Wait for other autopack processes holding the lock instead of failing
+    pub(crate) fn wait(&mut self, wait: bool) -> &mut Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Picks the private registry credentials to bind into the build: an explicit `.npmrc`,
+    /// a token from the environment, or an `.npmrc` with credentials found in the project.
+    pub(crate) fn npmrc(
+        &mut self,
+        npmrc_file: Option<PathBuf>,
+        token_env: Option<String>,
+        registry: Option<String>,
+    ) -> Result<&mut Self, AppError> {
+        let project = &self.package_json;
+        let npmrc = match (npmrc_file, token_env) {
+            (Some(file), _) => {
+                Some(NpmrcSource::File(file.canonicalize().map_err(|e| {
+                    AppError::IOError("Failed to locate the given .npmrc", e)
+                })?))
+            }
+            (None, Some(env_var)) => Some(NpmrcSource::Token {
+                env_var,
+                registry: registry.unwrap_or_else(|| DEFAULT_REGISTRY.to_string()),
+            }),
+            (None, None) => [project.path.clone(), project.build_context()]
+                .iter()
+                .map(|d| d.join(".npmrc"))
+                .find(|p| NpmrcSource::has_credentials(p))
+                .and_then(|p| p.canonicalize().ok())
+                .map(NpmrcSource::File),
+        };
+
+        if npmrc.is_some() {
+            debug!("Binding npm registry credentials from {:?}", npmrc);
+            success("Private registry credentials will be bound into the build");
+        }
+        self.npmrc = npmrc;
+        Ok(self)
+    }
+
     pub(crate) fn configure(&mut self, live_reload: bool) -> Result<&mut Self, AppError> {
         debug!("Begin configure");
-        let buildpack = BuildPackProject::setup(&self.package_json.package_json, live_reload);
+        let mut buildpack = BuildPackProject::setup(&self.package_json.package_json, live_reload);
+        if let Some(ws) = self.package_json.workspace.as_ref() {
+            buildpack.with_workspace(ws);
+        }
+        buildpack.with_package_manager(&self.package_json.package_manager);
+        if let Some(npmrc) = self.npmrc.as_ref() {
+            // the credentials only reach the build through the binding, never through an
+            // `.npmrc` sitting in the build context
+            if let Ok(context) = self.package_json.build_context().canonicalize() {
+                let mut npmrcs = vec![
+                    context.join(".npmrc"),
+                    self.package_json.path.join(".npmrc"),
+                ];
+                if let NpmrcSource::File(path) = npmrc {
+                    npmrcs.push(path.clone());
+                }
+                let mut excluded: Vec<String> = npmrcs
+                    .iter()
+                    .filter_map(|p| {
+                        p.canonicalize()
+                            .unwrap_or_else(|_| p.clone())
+                            .strip_prefix(&context)
+                            .ok()
+                            .map(|rel| rel.to_string_lossy().replace('\\', "/"))
+                    })
+                    .collect();
+                excluded.sort();
+                excluded.dedup();
+                buildpack.leave_out(&excluded);
+            }
+        }
         self.buildpack = Some(buildpack);
 
         debug!("End configure");
@@ -56,13 +196,39 @@ impl Init {
     pub(crate) async fn post_configure(
         &mut self,
         force_create_runtime: bool,
-        live_reload: bool
+        live_reload: bool,
     ) -> Result<&mut Self, AppError> {
         debug!("Begin post-configure");
+        let runtime_dir = runtime::runtime_dir(
+            &self.package_json.path,
+            self.runtime_location.unwrap_or_default(),
+        )
+        .map_err(|e| AppError::ToolCacheError("Failed to locate the user cache directory", e))?;
+        fs::create_dir_all(&runtime_dir)
+            .map_err(|e| AppError::IOError("Failed to create runtime dir", e))?;
+        self.lock = Some(FileLock::acquire(
+            &runtime_dir.join(LOCK_FILE),
+            LockMode::Exclusive,
+            self.wait,
+        )?);
+        // `deinit` still has to revert the edits of earlier runs, even when this one makes none
+        self.recorded = AutoPack::recorded_scaffold(&runtime_dir);
+
         // create runtime
         let runtime = Runtime::builder(self.package_json.path.clone().as_path())
+            .location(self.runtime_location)?
             .dir(force_create_runtime)?
-            .proc_file(live_reload)?
+            .proc_file(
+                live_reload,
+                self.package_json
+                    .workspace
+                    .as_ref()
+                    .map(|w| w.container_app_path()),
+            )?
+            .npmrc(self.npmrc.clone())
+            .network(self.network.clone())
+            .keep_builds(self.keep_builds)
+            .state_format(self.state_format)
             .build();
 
         let filename = "project.toml";
@@ -81,8 +247,18 @@ impl Init {
             .and_then(|bp| bp.export_toml(&path))?;
 
         // Install pack cli
-        let mut pack = Pack::builder(&runtime.dir()).build();
-        let pack = pack.install().await?;
+        let cache = ToolCache::new()
+            .map_err(|e| AppError::ToolCacheError("Failed to locate the tool cache", e))?;
+        let mut pack = Pack::builder(&cache)
+            .version(self.pack_version.as_deref())
+            .map_err(|e| AppError::PostConfigureError("Unsupported pack cli version", e))?
+            .build();
+        let pack = pack
+            .install(&cache, runtime.network(), &self.pack_source)
+            .await?;
+        if let Err(e) = pack.add_ref(&cache, &self.package_json.path) {
+            log::warn(&format!("Failed recording the pack cli ref :: {}", e));
+        }
 
         self.runtime = Some(runtime);
         self.pack_cli = Some(pack.to_owned());
@@ -90,6 +266,63 @@ impl Init {
         Ok(self)
     }
 
+    /// Adds the npm scripts when `scripts` is set, and ignores an in-project runtime folder
+    /// in git, once the edits are previewed and confirmed; without a terminal only `--yes`
+    /// confirms them. The edits of earlier inits stay recorded.
+    pub(crate) fn scaffold(&mut self, scripts: bool) -> Result<&mut Self, AppError> {
+        let project_dir = self.package_json.path.clone();
+        let mut scaffold = Scaffold::default();
+        let mut edits = vec![];
+        if scripts {
+            let (edit, outcome) = scaffold::scripts_edit(&project_dir)
+                .map_err(|e| AppError::PostConfigureError("Failed preparing the npm scripts", e))?;
+            for name in outcome.conflicts {
+                log::warn(&format!(
+                    "package.json already has a `{}` script running something else; leaving it",
+                    name
+                ));
+            }
+            scaffold.scripts = outcome.added;
+            edits.extend(edit);
+        }
+        let in_project = self
+            .runtime
+            .as_ref()
+            .map(|r| r.location() == RuntimeLocation::Project)
+            .unwrap_or(false);
+        if in_project && scaffold::in_git_repo(&project_dir) {
+            let (edit, managed) = scaffold::gitignore_edit(&project_dir)
+                .map_err(|e| AppError::IOError("Failed reading .gitignore", e))?;
+            scaffold.gitignore = managed;
+            edits.extend(edit);
+        }
+
+        if !edits.is_empty() {
+            trying("Wiring autopack into the project");
+            for edit in edits.iter() {
+                print(&format!("  {}", edit.path.display()));
+                diff(&edit.before, &edit.after);
+            }
+            let apply = self.interaction.confirm("Apply these changes?", false)?;
+            if apply {
+                for edit in edits.iter() {
+                    edit.write()
+                        .map_err(|e| AppError::IOError("Failed editing the project files", e))?;
+                }
+                success("Added the npm scripts and .gitignore entries");
+            } else {
+                log::instruct("Left the project files as they are");
+                scaffold = Scaffold::default();
+            }
+        }
+
+        scaffold.merge(mem::take(&mut self.recorded));
+        if let Some(runtime) = self.runtime.as_mut() {
+            runtime.set_scaffold(scaffold);
+        }
+        Ok(self)
+    }
+
     pub(crate) fn install(&mut self) -> AutoPack {
         AutoPack {
             runtime: self.runtime.clone().unwrap_or_default(),
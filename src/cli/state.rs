@@ -0,0 +1,103 @@
+use crate::{
+    autopack::{Keyring, StateFiles, Verification, SCHEMA_VERSION},
+    error::AppError,
+    log::{instruct, success, trying},
+    prompt::Interaction,
+};
+use std::path::Path;
+
+fn keyring() -> Result<Keyring, AppError> {
+    Keyring::new().map_err(|e| AppError::StateIntegrityError("Failed to locate the state key", e))
+}
+
+fn report(verification: Verification) {
+    match verification {
+        Verification::Current(key) => success(&format!("State is intact (key {})", key)),
+        Verification::Resigned { from, to } => success(&format!(
+            "State is intact; re-signed from retired key {} with key {}",
+            from, to
+        )),
+    }
+}
+
+fn verify_state(files: &StateFiles, keyring: &Keyring) -> Result<Verification, AppError> {
+    files
+        .verify(keyring)
+        .map_err(|e| AppError::StateIntegrityError("State failed verification", e))
+}
+
+fn state_files(runtime_dir: Option<&Path>) -> Result<StateFiles, AppError> {
+    StateFiles::new(runtime_dir)
+        .map_err(|e| AppError::StateIntegrityError("Failed locating autopack state", e))
+}
+
+/// Checks the project state against its MAC and that it decodes
+pub(crate) fn verify(runtime_dir: Option<&Path>) -> Result<(), AppError> {
+    trying("Verifying autopack state");
+    let files = state_files(runtime_dir)?;
+    report(verify_state(&files, &keyring()?)?);
+    files
+        .decode()
+        .map_err(|e| AppError::StateIntegrityError("State failed decoding", e))?;
+    match files.format() {
+        Some(format) => success(&format!(
+            "State decodes ({}, schema {})",
+            format, SCHEMA_VERSION
+        )),
+        None => instruct("State predates versioned schemas; it's rewritten on the next save"),
+    }
+    Ok(())
+}
+
+/// Shows the decoded project state and signs it with the current key once confirmed
+pub(crate) fn trust(runtime_dir: Option<&Path>, interaction: Interaction) -> Result<(), AppError> {
+    let files = state_files(runtime_dir)?;
+    files
+        .review()
+        .map_err(|e| AppError::StateIntegrityError("State failed decoding", e))?;
+
+    if !interaction.confirm("Sign this state with your key?", false)? {
+        instruct("Left the state unsigned");
+        return Ok(());
+    }
+    let key = files
+        .trust(&keyring()?)
+        .map_err(|e| AppError::StateIntegrityError("Failed trusting the state", e))?;
+    success(&format!("Signed the state with key {}", key));
+    Ok(())
+}
+
+/// Makes a new state key current and re-signs the project state with it
+pub(crate) fn rotate_key(runtime_dir: Option<&Path>, drop_old: bool) -> Result<(), AppError> {
+    let keyring = keyring()?;
+    // verified before the rotation so that a tampered state isn't re-signed
+    let files = StateFiles::new(runtime_dir).ok();
+    if let Some(files) = files.as_ref() {
+        verify_state(files, &keyring)?;
+    }
+
+    let key = keyring
+        .rotate()
+        .map_err(|e| AppError::StateIntegrityError("Failed rotating the state key", e))?;
+    success(&format!("Rotated the state key, now key {}", key.id));
+    if let Some(files) = files.as_ref() {
+        report(verify_state(files, &keyring)?);
+    }
+
+    if drop_old {
+        let dropped = keyring
+            .drop_retired()
+            .map_err(|e| AppError::StateIntegrityError("Failed dropping retired keys", e))?;
+        instruct(&format!(
+            "Dropped {} retired key(s). States of other projects signed with them need `auto-pack init`.",
+            dropped
+        ));
+    } else {
+        instruct(&format!(
+            "Retired keys stay in {} so that other projects' states verify and get re-signed on \
+            their next use. Pass --drop-old to remove them.",
+            keyring.path().display()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use crate::{
+    autopack::AutoPack,
+    error::AppError,
+    log::{instruct, record, success, trying, warn},
+    pack::{Pack, PackSource, PACK_VERSION},
+    runtime,
+    tools::{CachedTool, ToolCache},
+};
+use serde_json::json;
+use std::path::Path;
+use tracing::debug;
+
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
+
+fn cache() -> Result<ToolCache, AppError> {
+    ToolCache::new().map_err(|e| AppError::ToolCacheError("Failed to locate the tool cache", e))
+}
+
+/// Prints the tools installed in the user level cache
+pub(crate) fn list() -> Result<(), AppError> {
+    let tools = cache()?
+        .list()
+        .map_err(|e| AppError::ToolCacheError("Failed listing the tool cache", e))?;
+    if tools.is_empty() {
+        instruct("No tools cached yet. They are installed on `auto-pack init`.");
+    }
+    for t in tools {
+        let row = format!(
+            "{:<8} {:<10} {:>10}  {}",
+            t.name,
+            t.version,
+            human_size(t.size),
+            t.path.display()
+        );
+        let data = json!({ "name": t.name, "version": t.version, "size": t.size, "path": t.path });
+        record(&data, &row);
+    }
+    Ok(())
+}
+
+/// pack cli version of the project in the current directory, if initialized
+fn project_pack_version() -> Option<String> {
+    let runtime_dir = runtime::locate(Path::new("."));
+    if !runtime_dir.join(".state").exists() {
+        return None;
+    }
+    AutoPack::load(Some(&runtime_dir))
+        .map(|ap| ap.pack_cli.release_version)
+        .map_err(|e| debug!("Not keeping the project pack cli :: {}", e))
+        .ok()
+}
+
+/// Removes cached tools, keeping the pack cli versions this autopack installs, the current
+/// project uses and other projects hold refs to unless `all` is set
+pub(crate) fn prune(all: bool) -> Result<(), AppError> {
+    let project_version = project_pack_version();
+    let cache = cache()?;
+    let keep = |t: &CachedTool| {
+        let current = t.name == "pack"
+            && (t.version == PACK_VERSION || Some(&t.version) == project_version.as_ref());
+        !all && (current || !cache.refs(&t.name, &t.version).is_empty())
+    };
+    let removed = cache
+        .prune(keep)
+        .map_err(|e| AppError::ToolCacheError("Failed pruning the tool cache", e))?;
+    let freed = removed.iter().map(|t| t.size).sum::<u64>();
+    removed
+        .iter()
+        .for_each(|t| success(&format!("Removed {} {}", t.name, t.version)));
+    success(&format!(
+        "Pruned {} tool(s), freed {}",
+        removed.len(),
+        human_size(freed)
+    ));
+    Ok(())
+}
+
+/// Installs pack cli `version` side by side with the one in use and only switches the
+/// project state over once the new binary checks out. Older versions need `allow_downgrade`.
+pub(crate) async fn upgrade(
+    version: Option<&str>,
+    allow_downgrade: bool,
+    runtime_dir: Option<&Path>,
+) -> Result<(), AppError> {
+    let mut autopack = AutoPack::load(runtime_dir).map_err(|e| {
+        crate::log::error("Please run `auto-pack init` to initialize autopack.");
+        AppError::RunError("Failed loading autopack state", e)
+    })?;
+    let cache = cache()?;
+    let mut pack = Pack::builder(&cache)
+        .version(version)
+        .map_err(|e| AppError::PostConfigureError("Unsupported pack cli version", e))?
+        .build();
+
+    let current = autopack.pack_cli.installed_version().ok();
+    if current.map(|v| v.to_string()).as_ref() == Some(&pack.release_version) {
+        success(&format!("pack cli is already at {}", pack.release_version));
+        return Ok(());
+    }
+
+    if let (Some(current), Ok(target)) = (current, pack.version()) {
+        if target < current && !allow_downgrade {
+            return Err(AppError::PostConfigureError(
+                "Refusing to downgrade the pack cli",
+                anyhow::anyhow!(
+                    "{} is older than the current {}; pass --allow-downgrade to switch anyway",
+                    target,
+                    current
+                ),
+            ));
+        }
+    }
+
+    trying(&format!(
+        "Switching pack cli from {} to {}",
+        current
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "an unusable binary".to_string()),
+        pack.release_version
+    ));
+    let source = PackSource {
+        offline: autopack.pack_cli.offline,
+        ..Default::default()
+    };
+    pack.install(&cache, autopack.runtime.network(), &source)
+        .await?;
+
+    let previous = std::mem::replace(&mut autopack.pack_cli, pack);
+    autopack
+        .save(None)
+        .map_err(|e| AppError::PostConfigureError("Failed to serialize autopack state", e))?;
+    let project_root = &autopack.client_project.path;
+    if let Err(e) = cache
+        .remove_ref("pack", &previous.release_version, project_root)
+        .and_then(|_| autopack.pack_cli.add_ref(&cache, project_root))
+    {
+        warn(&format!("Failed updating the tool cache refs :: {}", e));
+    }
+    success(&format!(
+        "Switched to pack cli {}; `auto-pack tools prune` removes the previous version",
+        autopack.pack_cli.release_version
+    ));
+    Ok(())
+}
@@ -1,11 +1,12 @@
 use crate::{
     error::AppError,
     log::{command_err, command_out, error, instruct, success, trying},
+    prompt::Interaction,
 };
 use futures_util::TryFutureExt;
 use serde::{Deserialize, Serialize};
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
 };
 use tokio::io::AsyncBufReadExt;
@@ -27,19 +28,32 @@ fn docker_install_path() -> &'static str {
     "https://docs.docker.com/desktop/install/windows-install/"
 }
 
-fn ask_install_docker() -> Result<(), AppError> {
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+fn docker_install_path() -> &'static str {
+    "https://docs.docker.com/engine/install/"
+}
+
+fn ask_install_docker(interaction: Interaction) -> Result<(), AppError> {
+    if !interaction.is_interactive() {
+        // no browser to open
+        instruct(&format!(
+            "Please install Docker ({}) and run init again.",
+            docker_install_path()
+        ));
+        return Ok(());
+    }
     debug!("asking user to open docker install page");
     instruct("Please install Docker and run init again.");
-    let do_open = dialoguer::Confirm::new()
-        .with_prompt("Press y or enter to open the webpage to install Docker.")
-        .default(true)
-        .interact()
-        .map_err(|e| {
+    let do_open = interaction
+        .confirm(
+            "Press y or enter to open the webpage to install Docker.",
+            true,
+        )
+        .inspect_err(|e| {
             error!(
                 message = "Error in ask to install docker prompt",
                 error = format!("{}", e)
             );
-            AppError::IOError("Failed to launch the docker install page", e)
         })?;
     if do_open {
         debug!(message = "User gave consent to open docker install webpage");
@@ -63,6 +77,40 @@ fn ask_install_docker() -> Result<(), AppError> {
     Ok(())
 }
 
+/// Label of the containers autopack runs, holding the project dir
+const PROJECT_LABEL: &str = "autopack.project";
+
+fn project_label(project_dir: &Path) -> String {
+    format!("{}={}", PROJECT_LABEL, project_dir.display())
+}
+
+/// Runs a docker command to completion, returning its stdout
+fn docker(args: &[&str]) -> anyhow::Result<String> {
+    let out = Command::new("docker").args(args).output()?;
+    debug!(
+        "`docker {}` exited with {} :: {}",
+        args.join(" "),
+        out.status,
+        String::from_utf8_lossy(&out.stdout).trim()
+    );
+    if !out.status.success() {
+        anyhow::bail!(
+            "`docker {}` failed :: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+}
+
+/// Absolute project dir for bind mounts
+pub(crate) fn canonical_project_dir(project_dir: &Path) -> anyhow::Result<PathBuf> {
+    project_dir
+        .canonicalize()
+        .or_else(|_| std::env::current_dir())
+        .map_err(|e| anyhow::anyhow!("Failed getting the project dir :: {:?}", e))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 pub(crate) struct Docker {
     version: String,
@@ -79,7 +127,7 @@ impl Docker {
     //     self.version.clone()
     // }
 
-    pub(crate) fn check() -> Result<Docker, AppError> {
+    pub(crate) fn check(interaction: Interaction) -> Result<Docker, AppError> {
         debug!("Locating docker...");
         trying("Locating docker");
 
@@ -105,7 +153,7 @@ impl Docker {
                 }
             })
             .or_else(|_| {
-                ask_install_docker()?;
+                ask_install_docker(interaction)?;
                 Err(AppError::PreconfigureError(
                     "Docker not found",
                     anyhow::anyhow!(""),
@@ -130,6 +178,116 @@ impl Docker {
             })
     }
 
+    /// Id of the image in the local daemon, without the `sha256:` prefix
+    pub(crate) fn image_id(&self, image_name: &str) -> anyhow::Result<Option<String>> {
+        let cmd = Command::new("docker")
+            .args(["image", "inspect", "--format", "{{.Id}}", image_name])
+            .output()?;
+        Ok(cmd.status.success().then(|| {
+            String::from_utf8_lossy(&cmd.stdout)
+                .trim()
+                .trim_start_matches("sha256:")
+                .to_string()
+        }))
+    }
+
+    /// Ids of the containers autopack ran for the project: those labelled for it, and
+    /// unlabelled ones of its image named after it
+    pub(crate) fn project_containers(
+        &self,
+        project_dir: &Path,
+        image_name: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        let project_dir = canonical_project_dir(project_dir)?;
+        let labelled = docker(&[
+            "ps",
+            "--all",
+            "--quiet",
+            "--filter",
+            &format!("label={}", project_label(&project_dir)),
+        ])?;
+        let named = docker(&[
+            "ps",
+            "--all",
+            "--quiet",
+            "--filter",
+            &format!("name=^/?{}$", image_name.replace('.', r"\.")),
+            "--filter",
+            &format!("ancestor={}", image_name),
+        ])?;
+        let mut ids = labelled
+            .lines()
+            .chain(named.lines())
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    pub(crate) fn remove_container(&self, id: &str) -> anyhow::Result<()> {
+        docker(&["container", "rm", "--force", id]).map(|_| ())
+    }
+
+    pub(crate) fn remove_image(&self, image: &str) -> anyhow::Result<()> {
+        docker(&["image", "rm", image]).map(|_| ())
+    }
+
+    pub(crate) fn volume_exists(&self, name: &str) -> bool {
+        docker(&["volume", "inspect", name]).is_ok()
+    }
+
+    pub(crate) fn remove_volume(&self, name: &str) -> anyhow::Result<()> {
+        docker(&["volume", "rm", name]).map(|_| ())
+    }
+
+    /// Runs a command in a running container, returning its stdout
+    pub(crate) async fn exec(&self, container: &str, args: &[&str]) -> anyhow::Result<String> {
+        let out = tokio::process::Command::new("docker")
+            .arg("exec")
+            .arg(container)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .await?;
+        if !out.status.success() {
+            anyhow::bail!(
+                "`{}` failed in {} :: {}",
+                args.join(" "),
+                container,
+                String::from_utf8_lossy(&out.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&out.stdout).to_string())
+    }
+
+    /// Copies a host file into a running container
+    pub(crate) async fn copy_into(
+        &self,
+        container: &str,
+        src: &Path,
+        dest: &str,
+    ) -> anyhow::Result<()> {
+        let out = tokio::process::Command::new("docker")
+            .arg("cp")
+            .arg(src)
+            .arg(format!("{}:{}", container, dest))
+            .stdin(Stdio::null())
+            .output()
+            .await?;
+        if !out.status.success() {
+            anyhow::bail!(
+                "Failed copying {:?} into {} :: {}",
+                src,
+                container,
+                String::from_utf8_lossy(&out.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
     pub(crate) async fn stop_container(&self, image_name: &str) -> anyhow::Result<()> {
         let mut search_container_cmd = tokio::process::Command::new("docker");
 
@@ -174,7 +332,7 @@ impl Docker {
                                     "error stopping, retrying... :: {}",
                                     std::str::from_utf8(&out.stderr).expect("could not get stderr")
                                 );
-                                Err(std::io::Error::new(std::io::ErrorKind::Other, ""))
+                                Err(std::io::Error::other(""))
                             }
                         })
                         .await;
@@ -199,16 +357,10 @@ impl Docker {
         self,
         image_name: String,
         project_dir: &Path,
+        container_src: &str,
         port: usize,
     ) -> anyhow::Result<()> {
-        if let Err(err) = self.stop_container(&image_name).await {
-            debug!("Failed stopping docker container :: {:?}", err)
-        }
-
-        let src_dir = project_dir
-            .canonicalize()
-            .or_else(|_| std::env::current_dir())
-            .map_err(|e| anyhow::anyhow!("Failed getting the project dir :: {:?}", e))?;
+        let src_dir = canonical_project_dir(project_dir)?;
 
         let mut docker_run_cmd = tokio::process::Command::new("docker");
 
@@ -220,14 +372,69 @@ impl Docker {
             .args([
                 "--mount",
                 &format!(
-                    r"type=bind,source={:#}/src,target=/workspace/src",
-                    src_dir.display()
+                    r"type=bind,source={:#}/src,target={}",
+                    src_dir.display(),
+                    container_src
                 ),
             ])
             .args(["-p", &format!("{}:8080", port)])
+            .args(["--label", &project_label(&src_dir)])
             .args(["--name", &image_name])
             .arg(image_name.clone());
 
+        self.attach(image_name, docker_run_cmd).await
+    }
+
+    /// Runs `command` in the image through the CNB launcher, with the buildpack provided
+    /// environment, the given host directories bind-mounted and env vars set
+    pub(crate) async fn run_command(
+        self,
+        image_name: String,
+        project_dir: &Path,
+        command: &str,
+        mounts: &[(PathBuf, String)],
+        env: &[(&str, String)],
+        ports: &[(usize, usize)],
+    ) -> anyhow::Result<()> {
+        let mut docker_run_cmd = tokio::process::Command::new("docker");
+
+        docker_run_cmd
+            .arg("run")
+            .arg("--interactive")
+            .arg("--init")
+            .arg("--rm")
+            .args(["--entrypoint", "launcher"]);
+        for (source, target) in mounts {
+            docker_run_cmd.args([
+                "--mount",
+                &format!(r"type=bind,source={:#},target={}", source.display(), target),
+            ]);
+        }
+        for (name, value) in env {
+            docker_run_cmd.args(["--env", &format!("{}={}", name, value)]);
+        }
+        for (host, container) in ports {
+            docker_run_cmd.args(["-p", &format!("{}:{}", host, container)]);
+        }
+        docker_run_cmd
+            .args(["--label", &project_label(project_dir)])
+            .args(["--name", &image_name])
+            .arg(image_name.clone())
+            .arg(command);
+
+        self.attach(image_name, docker_run_cmd).await
+    }
+
+    /// Runs the container in the foreground until it exits or ctrl-c stops it
+    async fn attach(
+        self,
+        image_name: String,
+        mut docker_run_cmd: tokio::process::Command,
+    ) -> anyhow::Result<()> {
+        if let Err(err) = self.stop_container(&image_name).await {
+            debug!("Failed stopping docker container :: {:?}", err)
+        }
+
         debug!("docker command :: {:?}", docker_run_cmd);
 
         let mut child = docker_run_cmd
@@ -262,8 +469,8 @@ impl Docker {
             tokio::select! {
                 st = child.wait() => {
                     debug!("Failed running docker container");
-                    if st.is_err() {
-                        anyhow::bail!(st.unwrap_err())
+                    if let Err(e) = st {
+                        anyhow::bail!(e)
                     }
                     Ok::<(), anyhow::Error>(())
                 }
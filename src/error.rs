@@ -1,4 +1,5 @@
-use std::ffi::OsString;
+use crate::log::{self, instruct};
+use std::{ffi::OsString, path::PathBuf};
 use thiserror::Error;
 use tracing::error;
 
@@ -8,8 +9,12 @@ pub enum AppError {
     PreconfigureError(&'static str, #[source] anyhow::Error),
     #[error("Package JSON not found")]
     PackageJSONNotFound,
-    #[error("React scripts not found")]
-    ReactScriptsNotFound,
+    #[error("Create React App not detected :: {0}")]
+    ReactScriptsNotFound(String),
+    #[error("No workspace found at or above {0:?}")]
+    WorkspaceNotFound(PathBuf),
+    #[error("App `{0}` not found in workspace. Available packages :: {1}")]
+    WorkspaceAppNotFound(String, String),
     #[error("Some OS specific error :: {:?}", .0.to_str().unwrap_or(""))]
     OSPathError(OsString),
     #[error("Some IO error :: {0} :: {:?}", .1)]
@@ -20,9 +25,71 @@ pub enum AppError {
     BuildError(&'static str, #[source] anyhow::Error),
     #[error("Run error :: {0} :: {:?}", .1)]
     RunError(&'static str, #[source] anyhow::Error),
+    #[error("Unsupported platform :: {0}")]
+    UnsupportedPlatform(String),
+    #[error("Not available offline :: {0}")]
+    OfflineError(String),
+    #[error("Dependency conflict while installing packages, see {0:?}")]
+    DependencyConflict(PathBuf),
+    #[error("Node version {0} is not available in the buildpack, see {1:?}")]
+    UnsupportedNodeVersion(String, PathBuf),
+    #[error("JavaScript heap out of memory during the build, see {0:?}")]
+    OutOfMemory(PathBuf),
+    #[error("package.json has no `build` script, see {0:?}")]
+    MissingBuildScript(PathBuf),
+    #[error("Create React App treated warnings as errors, see {0:?}")]
+    WarningsAsErrors(PathBuf),
+    #[error("Docker ran out of disk space, see {0:?}")]
+    DiskFull(PathBuf),
+    #[error("Package registry rejected the credentials, see {0:?}")]
+    RegistryAuth(PathBuf),
+    #[error("Tool cache error :: {0} :: {:?}", .1)]
+    ToolCacheError(&'static str, #[source] anyhow::Error),
+    #[error("State integrity error :: {0} :: {:?}", .1)]
+    StateIntegrityError(&'static str, #[source] anyhow::Error),
+    #[error("The lock {1:?} is held by {0}")]
+    Locked(String, PathBuf),
 }
 
 impl AppError {
+    /// Remediation for build failures autopack recognises
+    pub fn hint(&self) -> Option<String> {
+        let hint = match self {
+            AppError::DependencyConflict(_) => {
+                "Align the conflicting peer dependencies in package.json, \
+                or add `legacy-peer-deps=true` to the project .npmrc to keep npm 6 behaviour."
+                    .to_string()
+            }
+            AppError::UnsupportedNodeVersion(version, _) => format!(
+                "Node {} has no release in the Node Engine buildpack. The buildpack reads \
+                `BP_NODE_VERSION` in .autopack/project.toml first, which `init` takes from \
+                `NODE_VERSION`, then .nvmrc, then `engines.node` in package.json. Change it to a \
+                supported version, e.g. an active LTS line, with \
+                `auto-pack config set node-version <version>`.",
+                version
+            ),
+            AppError::OutOfMemory(_) => {
+                "Allow node more memory with `NODE_OPTIONS=--max-old-space-size=4096` \
+                in the build env of .autopack/project.toml, or give Docker more memory."
+                    .to_string()
+            }
+            AppError::MissingBuildScript(_) => "Add a `build` script to package.json, \
+                e.g. `\"build\": \"react-scripts build\"`."
+                .to_string(),
+            AppError::WarningsAsErrors(_) => "Fix the reported lint warnings, or set `CI=false` \
+                in the build env of .autopack/project.toml."
+                .to_string(),
+            AppError::DiskFull(_) => "Free Docker disk space with `docker system prune`, \
+                then retry with `auto-pack build --clear-cache`."
+                .to_string(),
+            AppError::RegistryAuth(_) => "Check the registry token, or pass credentials with \
+                `auto-pack init --npmrc <path>` or `--npm-token-env <VAR>`."
+                .to_string(),
+            _ => return None,
+        };
+        Some(hint)
+    }
+
     pub fn handle(&self) {
         match &self {
             AppError::IOError(context, e) => {
@@ -40,8 +107,17 @@ impl AppError {
             AppError::PreconfigureError(context, e) => {
                 error!("Pre configure error :: {} :: {:?}", context, e);
             }
-            AppError::ReactScriptsNotFound => {
-                error!("React scripts not found");
+            AppError::ReactScriptsNotFound(reason) => {
+                error!("Create React App not detected :: {}", reason);
+            }
+            AppError::WorkspaceNotFound(path) => {
+                error!("No workspace found at or above {:?}", path);
+            }
+            AppError::WorkspaceAppNotFound(app, names) => {
+                error!(
+                    "App `{}` not found in workspace. Available packages :: {}",
+                    app, names
+                );
             }
             AppError::BuildError(context, e) => {
                 error!("Build failure :: {} :: {:?}", context, e)
@@ -49,6 +125,38 @@ impl AppError {
             AppError::RunError(context, e) => {
                 error!("Run failure :: {} :: {:?}", context, e)
             }
+            AppError::UnsupportedPlatform(reason) => {
+                error!("Unsupported platform :: {}", reason)
+            }
+            AppError::OfflineError(reason) => {
+                error!("Not available offline :: {}", reason)
+            }
+            AppError::DependencyConflict(_)
+            | AppError::UnsupportedNodeVersion(..)
+            | AppError::OutOfMemory(_)
+            | AppError::MissingBuildScript(_)
+            | AppError::WarningsAsErrors(_)
+            | AppError::DiskFull(_)
+            | AppError::RegistryAuth(_) => {
+                error!("Build failure :: {}", self);
+                if let Some(hint) = self.hint() {
+                    instruct(&hint);
+                }
+            }
+            AppError::ToolCacheError(context, e) => {
+                error!("Tool cache error :: {} :: {:?}", context, e)
+            }
+            AppError::StateIntegrityError(context, e) => {
+                error!("State integrity error :: {} :: {:?}", context, e);
+                instruct(
+                    "If the state wasn't tampered with, run `auto-pack init` to re-initialize autopack.",
+                );
+            }
+            AppError::Locked(holder, path) => {
+                error!("The lock {:?} is held by {}", path, holder);
+                instruct("Pass `--wait` to wait until it's done.");
+            }
         };
+        log::result(Some(self));
     }
 }
@@ -0,0 +1,253 @@
+#[cfg(test)]
+mod tests;
+
+use crate::log::instruct;
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Directories never part of the build inputs: version control and autopack's own state
+const ALWAYS_SKIPPED: [&str; 2] = [".git", ".autopack"];
+
+/// Gitignore style match of a single pattern against a `/` separated path relative to the
+/// build context. Patterns containing a `/` are anchored to the context root, others match
+/// any path component; a pattern matching a directory matches everything below it.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let trimmed = pattern.trim_end_matches('/');
+    let anchored = trimmed.contains('/');
+    let glob = match Pattern::new(trimmed.trim_start_matches('/')) {
+        Ok(glob) => glob,
+        Err(_) => return false,
+    };
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    let components = path.split('/').collect::<Vec<_>>();
+    (1..=components.len()).any(|n| {
+        if anchored {
+            glob.matches_with(&components[..n].join("/"), options)
+        } else {
+            glob.matches_with(components[n - 1], options)
+        }
+    })
+}
+
+/// Outcome of the last pattern matching the path, `!` patterns negating
+fn last_match(patterns: &[String], path: &str) -> Option<bool> {
+    patterns
+        .iter()
+        .rev()
+        .find_map(|p| match p.strip_prefix('!') {
+            Some(negated) => pattern_matches(negated, path).then_some(false),
+            None => pattern_matches(p, path).then_some(true),
+        })
+}
+
+/// Selects the build context files the way pack applies the project.toml file list
+#[derive(Debug, Default)]
+pub(crate) struct FileFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl FileFilter {
+    /// An empty include list includes everything
+    pub(crate) fn new(include: Vec<String>, exclude: Vec<String>) -> FileFilter {
+        FileFilter { include, exclude }
+    }
+
+    pub(crate) fn selects(&self, path: &str) -> bool {
+        let included = self.include.is_empty() || last_match(&self.include, path) == Some(true);
+        included && last_match(&self.exclude, path) != Some(true)
+    }
+
+    /// Whether nothing below the directory can be selected, so that e.g. `node_modules`
+    /// isn't walked
+    fn prunes(&self, dir: &str) -> bool {
+        if ALWAYS_SKIPPED.iter().any(|s| pattern_matches(s, dir))
+            || last_match(&self.exclude, dir) == Some(true)
+        {
+            return true;
+        }
+        if self.include.is_empty() {
+            return false;
+        }
+        match last_match(&self.include, dir) {
+            Some(included) => !included,
+            // only descend towards included paths
+            None => !self
+                .include
+                .iter()
+                .filter(|p| !p.starts_with('!'))
+                .any(|p| {
+                    let anchored = p.trim_end_matches('/').contains('/');
+                    !anchored || p.trim_start_matches('/').starts_with(&format!("{}/", dir))
+                }),
+        }
+    }
+}
+
+/// How an input differs from the previous build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Change {
+    Added(String),
+    Removed(String),
+    Modified(String),
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added(i) => write!(f, "+ {}", i),
+            Change::Removed(i) => write!(f, "- {}", i),
+            Change::Modified(i) => write!(f, "~ {}", i),
+        }
+    }
+}
+
+/// Prints the first `limit` changes
+pub(crate) fn list_changes(changes: &[Change], limit: usize) {
+    for change in changes.iter().take(limit) {
+        instruct(&format!("  {}", change));
+    }
+    if changes.len() > limit {
+        instruct(&format!("  ... and {} more", changes.len() - limit));
+    }
+}
+
+/// Content hashes of the build inputs: the selected build context files, keyed by their
+/// path relative to the context, and the settings autopack passes to pack besides them
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub(crate) struct Fingerprint {
+    /// hash over all inputs
+    pub(crate) digest: String,
+    pub(crate) inputs: BTreeMap<String, String>,
+}
+
+impl Fingerprint {
+    pub(crate) fn builder() -> FingerprintBuilder {
+        FingerprintBuilder::default()
+    }
+
+    /// Inputs added, removed or modified since `previous`
+    pub(crate) fn changes(&self, previous: &Fingerprint) -> Vec<Change> {
+        let mut changes = self
+            .inputs
+            .iter()
+            .filter_map(|(input, hash)| match previous.inputs.get(input) {
+                None => Some(Change::Added(input.clone())),
+                Some(h) if h != hash => Some(Change::Modified(input.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        changes.extend(
+            previous
+                .inputs
+                .keys()
+                .filter(|i| !self.inputs.contains_key(*i))
+                .map(|i| Change::Removed(i.clone())),
+        );
+        changes
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct FingerprintBuilder {
+    inputs: BTreeMap<String, String>,
+}
+
+impl FingerprintBuilder {
+    /// Hashes the files of the build context selected by the filter
+    pub(crate) fn context(
+        &mut self,
+        context: &Path,
+        filter: &FileFilter,
+    ) -> anyhow::Result<&mut Self> {
+        self.walk(context, "", filter)?;
+        Ok(self)
+    }
+
+    fn walk(&mut self, dir: &Path, rel: &str, filter: &FileFilter) -> anyhow::Result<()> {
+        let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = if rel.is_empty() {
+                name
+            } else {
+                format!("{}/{}", rel, name)
+            };
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if !filter.prunes(&path) {
+                    self.walk(&entry.path(), &path, filter)?;
+                }
+            } else if filter.selects(&path) {
+                // symlinks aren't followed; their target is what pack copies
+                let content = if file_type.is_symlink() {
+                    fs::read_link(entry.path())?
+                        .to_string_lossy()
+                        .as_bytes()
+                        .to_vec()
+                } else {
+                    fs::read(entry.path())?
+                };
+                self.inputs
+                    .insert(path, hex::encode(Sha256::digest(&content)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Hashes a file, or all files of a directory, outside of the build context as one input
+    pub(crate) fn path(&mut self, input: &str, path: &Path) -> anyhow::Result<&mut Self> {
+        let mut hasher = Sha256::new();
+        if path.is_dir() {
+            let mut files = fs::read_dir(path)?
+                .filter_map(Result::ok)
+                .filter(|e| e.path().is_file())
+                .collect::<Vec<_>>();
+            files.sort_by_key(|e| e.file_name());
+            for file in files {
+                hasher.update(file.file_name().to_string_lossy().as_bytes());
+                hasher.update(fs::read(file.path())?);
+            }
+        } else if path.is_file() {
+            hasher.update(fs::read(path)?);
+        }
+        self.inputs
+            .insert(input.to_string(), hex::encode(hasher.finalize()));
+        Ok(self)
+    }
+
+    /// Records an input hashed elsewhere, e.g. by `sha256sum` in a container
+    pub(crate) fn hash(&mut self, input: &str, hash: &str) -> &mut Self {
+        self.inputs.insert(input.to_string(), hash.to_string());
+        self
+    }
+
+    /// Hashes a setting, e.g. the node version
+    pub(crate) fn value(&mut self, input: &str, value: &str) -> &mut Self {
+        self.inputs.insert(
+            input.to_string(),
+            hex::encode(Sha256::digest(value.as_bytes())),
+        );
+        self
+    }
+
+    pub(crate) fn build(&self) -> Fingerprint {
+        let mut hasher = Sha256::new();
+        for (input, hash) in self.inputs.iter() {
+            hasher.update(input.as_bytes());
+            hasher.update([0]);
+            hasher.update(hash.as_bytes());
+            hasher.update([0]);
+        }
+        Fingerprint {
+            digest: hex::encode(hasher.finalize()),
+            inputs: self.inputs.clone(),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use super::{Change, FileFilter, Fingerprint};
+use std::fs;
+use tempfile::TempDir;
+
+fn strings(s: &[&str]) -> Vec<String> {
+    s.iter().map(|s| s.to_string()).collect()
+}
+
+fn write(root: &TempDir, files: &[(&str, &str)]) {
+    for (path, content) in files {
+        let path = root.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
+#[test]
+fn filters_like_the_file_list() {
+    let exclude = FileFilter::new(vec![], strings(&["node_modules", "README.md", "/build"]));
+    assert!(exclude.selects("src/App.js"));
+    assert!(!exclude.selects("node_modules/react/index.js"));
+    assert!(!exclude.selects("packages/ui/node_modules/x.js"));
+    assert!(!exclude.selects("README.md"));
+    assert!(!exclude.selects("build/index.html"));
+    assert!(exclude.selects("src/build/index.js"));
+
+    let include = FileFilter::new(
+        strings(&[
+            "/package.json",
+            "apps/web/",
+            "!apps/web/node_modules",
+            "!apps/web/build",
+        ]),
+        vec![],
+    );
+    assert!(include.selects("package.json"));
+    assert!(include.selects("apps/web/src/index.js"));
+    assert!(!include.selects("apps/web/node_modules/x.js"));
+    assert!(!include.selects("apps/api/index.js"));
+    assert!(!include.prunes("apps"));
+    assert!(include.prunes("apps/api"));
+    assert!(include.prunes("node_modules"));
+    assert!(include.prunes(".git"));
+
+    // unanchored patterns may match at any depth
+    let unanchored = FileFilter::new(strings(&["package.json", "apps/web/"]), vec![]);
+    assert!(unanchored.selects("apps/api/package.json"));
+    assert!(!unanchored.prunes("apps/api"));
+}
+
+#[test]
+fn fingerprints_inputs_and_lists_changes() {
+    let context = TempDir::new().unwrap();
+    write(
+        &context,
+        &[
+            ("package.json", "{}"),
+            ("package-lock.json", "{}"),
+            ("src/App.js", "app"),
+            ("node_modules/react/index.js", "react"),
+            (".autopack/builds/1/pack.log", "log"),
+        ],
+    );
+    let filter = FileFilter::new(vec![], strings(&["node_modules"]));
+    let fingerprint = |node: &str| {
+        Fingerprint::builder()
+            .context(context.path(), &filter)
+            .unwrap()
+            .value("node version", node)
+            .build()
+    };
+
+    let before = fingerprint("^16.0.0");
+    assert_eq!(
+        before.inputs.keys().collect::<Vec<_>>(),
+        vec![
+            "node version",
+            "package-lock.json",
+            "package.json",
+            "src/App.js"
+        ]
+    );
+    assert_eq!(fingerprint("^16.0.0"), before);
+
+    // excluded and autopack state files don't count
+    write(
+        &context,
+        &[
+            ("node_modules/react/index.js", "react 18"),
+            (".autopack/builds/2/pack.log", "log"),
+        ],
+    );
+    assert_eq!(fingerprint("^16.0.0").digest, before.digest);
+
+    write(&context, &[("src/App.js", "app 2"), ("src/new.js", "new")]);
+    fs::remove_file(context.path().join("package-lock.json")).unwrap();
+    let after = fingerprint("^18.0.0");
+    assert_ne!(after.digest, before.digest);
+    assert_eq!(
+        after.changes(&before),
+        vec![
+            Change::Modified("node version".to_string()),
+            Change::Modified("src/App.js".to_string()),
+            Change::Added("src/new.js".to_string()),
+            Change::Removed("package-lock.json".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,227 @@
+#[cfg(test)]
+mod tests;
+
+use crate::{fingerprint::Fingerprint, lock::write_atomic};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::debug;
+
+const BUILDS_DIR: &str = "builds";
+const RECORD: &str = "build.json";
+const LOG: &str = "pack.log";
+const PROJECT_TOML: &str = "project.toml";
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats unix seconds as `YYYY-MM-DD HH:MM:SS` UTC
+pub(crate) fn format_utc(secs: u64) -> String {
+    // days to civil date, after Howard Hinnant's algorithm
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+/// Current commit of the project, suffixed with `-dirty` for uncommitted changes
+fn git_commit(project_dir: &Path) -> Option<String> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(project_dir)
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+    };
+    let commit = git(&["rev-parse", "--short=12", "HEAD"])?;
+    let dirty = git(&["status", "--porcelain"])
+        .map(|s| !s.is_empty())
+        .unwrap_or(false);
+    Some(if dirty {
+        format!("{}-dirty", commit)
+    } else {
+        commit
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub(crate) struct PhaseTiming {
+    pub(crate) phase: String,
+    pub(crate) secs: f64,
+}
+
+/// A build as recorded in `builds/<id>/build.json`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub(crate) struct BuildRecord {
+    pub(crate) id: u64,
+    /// unix seconds
+    pub(crate) started_at: u64,
+    pub(crate) finished_at: Option<u64>,
+    pub(crate) success: bool,
+    pub(crate) image: String,
+    /// image digest, or the short image id pack reports for images kept in the daemon
+    pub(crate) digest: Option<String>,
+    pub(crate) git_commit: Option<String>,
+    pub(crate) phases: Vec<PhaseTiming>,
+    /// error the build failed with
+    pub(crate) error: Option<String>,
+    /// build inputs the image was built from
+    #[serde(default)]
+    pub(crate) fingerprint: Option<Fingerprint>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl BuildRecord {
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn log_path(&self) -> PathBuf {
+        self.dir.join(LOG)
+    }
+
+    pub(crate) fn project_toml_path(&self) -> PathBuf {
+        self.dir.join(PROJECT_TOML)
+    }
+
+    pub(crate) fn duration(&self) -> Option<Duration> {
+        self.finished_at
+            .map(|f| Duration::from_secs(f.saturating_sub(self.started_at)))
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        write_atomic(&self.dir.join(RECORD), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Builds recorded under `<runtime dir>/builds`, numbered from 1
+pub(crate) struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    pub(crate) fn new(runtime_dir: &Path) -> History {
+        History {
+            dir: runtime_dir.join(BUILDS_DIR),
+        }
+    }
+
+    /// Recorded builds, oldest first; unreadable records are skipped
+    pub(crate) fn list(&self) -> anyhow::Result<Vec<BuildRecord>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut records = fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .filter_map(|e| {
+                let record = fs::read(e.path().join(RECORD))
+                    .map_err(anyhow::Error::from)
+                    .and_then(|r| Ok(serde_json::from_slice::<BuildRecord>(&r)?))
+                    .map_err(|err| debug!("Skipping build record {:?} :: {}", e.path(), err))
+                    .ok()?;
+                Some(BuildRecord {
+                    dir: e.path(),
+                    ..record
+                })
+            })
+            .collect::<Vec<_>>();
+        records.sort_by_key(|r| r.id);
+        Ok(records)
+    }
+
+    pub(crate) fn get(&self, id: u64) -> anyhow::Result<BuildRecord> {
+        self.list()?
+            .into_iter()
+            .find(|r| r.id == id)
+            .ok_or_else(|| anyhow::anyhow!("No build {} recorded", id))
+    }
+
+    pub(crate) fn latest(&self) -> anyhow::Result<Option<BuildRecord>> {
+        Ok(self.list()?.pop())
+    }
+
+    /// Latest build which produced an image
+    pub(crate) fn latest_success(&self) -> anyhow::Result<Option<BuildRecord>> {
+        Ok(self.list()?.into_iter().rev().find(|r| r.success))
+    }
+
+    /// Records the start of a build along with the effective project.toml and the git commit
+    pub(crate) fn start(
+        &self,
+        image: &str,
+        project_toml: &Path,
+        project_dir: &Path,
+    ) -> anyhow::Result<BuildRecord> {
+        let id = self.list()?.last().map(|r| r.id).unwrap_or_default() + 1;
+        let dir = self.dir.join(id.to_string());
+        fs::create_dir_all(&dir)?;
+
+        let record = BuildRecord {
+            id,
+            started_at: now(),
+            image: image.to_string(),
+            git_commit: git_commit(project_dir),
+            dir,
+            ..Default::default()
+        };
+        if let Err(e) = fs::copy(project_toml, record.project_toml_path()) {
+            debug!("Failed recording {:?} :: {}", project_toml, e);
+        }
+        record.save()?;
+        Ok(record)
+    }
+
+    /// Saves the outcome of the build and drops the oldest builds beyond `keep`
+    pub(crate) fn finish(
+        &self,
+        record: &mut BuildRecord,
+        error: Option<String>,
+        keep: usize,
+    ) -> anyhow::Result<()> {
+        record.finished_at = Some(now());
+        record.success = error.is_none();
+        record.error = error;
+        record.save()?;
+        self.prune(keep)
+    }
+
+    /// Keeps the `keep` latest builds; `0` keeps all of them
+    pub(crate) fn prune(&self, keep: usize) -> anyhow::Result<()> {
+        if keep == 0 {
+            return Ok(());
+        }
+        let records = self.list()?;
+        for old in records.iter().take(records.len().saturating_sub(keep)) {
+            debug!("Removing build {} beyond the retention limit", old.id);
+            fs::remove_dir_all(old.dir())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use super::{format_utc, History};
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn formats_utc_timestamps() {
+    assert_eq!(format_utc(0), "1970-01-01 00:00:00");
+    assert_eq!(format_utc(951_782_400), "2000-02-29 00:00:00");
+    assert_eq!(format_utc(1_792_412_345), "2026-10-19 12:19:05");
+}
+
+#[test]
+fn records_builds_and_applies_retention() {
+    let runtime = TempDir::new().unwrap();
+    let project = TempDir::new().unwrap();
+    let toml = runtime.path().join("project.toml");
+    fs::write(&toml, "[build]\n").unwrap();
+    let history = History::new(runtime.path());
+
+    for i in 0..4 {
+        let mut record = history.start("my-app", &toml, project.path()).unwrap();
+        assert_eq!(record.id, i + 1);
+        // not a git repository
+        assert_eq!(record.git_commit, None);
+        fs::write(record.log_path(), format!("build {}", record.id)).unwrap();
+
+        let error = (i == 2).then(|| "Failed auto packing project".to_string());
+        history.finish(&mut record, error, 3).unwrap();
+    }
+
+    let records = history.list().unwrap();
+    assert_eq!(
+        records.iter().map(|r| r.id).collect::<Vec<_>>(),
+        vec![2, 3, 4]
+    );
+    assert!(!records[1].success);
+    assert_eq!(
+        records[1].error.as_deref(),
+        Some("Failed auto packing project")
+    );
+    assert!(records[2].success);
+    assert_eq!(
+        fs::read_to_string(records[2].project_toml_path()).unwrap(),
+        "[build]\n"
+    );
+    assert_eq!(
+        fs::read_to_string(history.get(3).unwrap().log_path()).unwrap(),
+        "build 3"
+    );
+    assert_eq!(history.latest().unwrap().unwrap().id, 4);
+    assert!(history.get(1).is_err());
+
+    // ids keep increasing after pruning
+    history.prune(1).unwrap();
+    assert_eq!(
+        history.start("my-app", &toml, project.path()).unwrap().id,
+        5
+    );
+}
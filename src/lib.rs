@@ -3,7 +3,17 @@ pub(crate) mod buildpack;
 pub mod cli;
 mod docker;
 mod error;
+mod fingerprint;
+mod history;
+mod lock;
 pub(crate) mod log;
+mod network;
 pub(crate) mod pack;
 mod package_json;
+mod package_manager;
+mod prompt;
 pub(crate) mod runtime;
+mod scaffold;
+mod sync;
+mod tools;
+mod workspace;
@@ -0,0 +1,173 @@
+#[cfg(test)]
+mod tests;
+
+use crate::{error::AppError, log::trying};
+use std::{
+    env,
+    fs::{self, File, OpenOptions, TryLockError},
+    io,
+    path::{Path, PathBuf},
+    process,
+};
+use tracing::debug;
+
+/// Lock file within the runtime directory
+pub(crate) const LOCK_FILE: &str = ".lock";
+
+/// Next to the lock file, naming the process holding it exclusively. The lock file itself
+/// can't be read while locked on Windows.
+const HOLDER_SUFFIX: &str = "holder";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LockMode {
+    /// commands which only read, any number at once
+    Shared,
+    /// commands which write the state or generated files
+    Exclusive,
+}
+
+/// Command line of this process, as shown to processes waiting for the lock
+fn command_line() -> String {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    format!("auto-pack {}", args.join(" "))
+        .trim_end()
+        .to_string()
+}
+
+/// Advisory lock on a file, released when dropped
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl FileLock {
+    pub(crate) fn holder_path(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".");
+        name.push(HOLDER_SUFFIX);
+        PathBuf::from(name)
+    }
+
+    /// Whether `name` is the lock file or its holder, which outlive the files they guard
+    pub(crate) fn is_lock_file(name: &str) -> bool {
+        name == LOCK_FILE || name == format!("{}.{}", LOCK_FILE, HOLDER_SUFFIX)
+    }
+
+    /// Who holds the lock: the process holding it exclusively, or else the ones sharing it
+    fn holder(path: &Path) -> String {
+        match fs::read_to_string(FileLock::holder_path(path)) {
+            Ok(holder) => match holder.split_once('\n') {
+                Some((pid, command)) => format!(
+                    "another autopack process (pid {}, command `{}`)",
+                    pid,
+                    command.trim()
+                ),
+                None => "another autopack process".to_string(),
+            },
+            Err(_) => "other autopack processes sharing it".to_string(),
+        }
+    }
+
+    fn try_lock(file: &File, mode: LockMode) -> Result<(), TryLockError> {
+        match mode {
+            LockMode::Shared => file.try_lock_shared(),
+            LockMode::Exclusive => file.try_lock(),
+        }
+    }
+
+    /// Takes the lock, failing with the holding process when it's taken unless `wait` is set
+    pub(crate) fn acquire(path: &Path, mode: LockMode, wait: bool) -> Result<FileLock, AppError> {
+        let io_error = |e| AppError::IOError("Failed taking the autopack lock", e);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(io_error)?;
+
+        match FileLock::try_lock(&file, mode) {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) if wait => {
+                trying(&format!(
+                    "Waiting for {} to release the lock",
+                    FileLock::holder(path)
+                ));
+                match mode {
+                    LockMode::Shared => file.lock_shared(),
+                    LockMode::Exclusive => file.lock(),
+                }
+                .map_err(io_error)?;
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(AppError::Locked(FileLock::holder(path), path.to_path_buf()))
+            }
+            Err(TryLockError::Error(e)) => return Err(io_error(e)),
+        }
+        debug!("Took the {:?} lock on {:?}", mode, path);
+
+        // best effort, it only names the holder in messages. Any number of processes share
+        // the lock, so only an exclusive holder is named; one sharing it knows there's none.
+        let recorded = match mode {
+            LockMode::Exclusive => fs::write(
+                FileLock::holder_path(path),
+                format!("{}\n{}\n", process::id(), command_line()),
+            ),
+            LockMode::Shared => match fs::remove_file(FileLock::holder_path(path)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+        };
+        if let Err(e) = recorded {
+            debug!("Failed recording the lock holder :: {}", e);
+        }
+        Ok(FileLock {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Locks the runtime directory; there's nothing to lock before `init` creates it
+    pub(crate) fn runtime(
+        runtime_dir: &Path,
+        mode: LockMode,
+        wait: bool,
+    ) -> Result<Option<FileLock>, AppError> {
+        if !runtime_dir.is_dir() {
+            return Ok(None);
+        }
+        FileLock::acquire(&runtime_dir.join(LOCK_FILE), mode, wait).map(Some)
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if let Err(e) = self.file.unlock() {
+            debug!("Failed releasing the lock :: {}", e);
+        }
+        debug!("Released the lock on {:?}", self.path);
+    }
+}
+
+/// Writes through a temporary file renamed over `path`, so that readers see either the
+/// previous or the new content
+pub(crate) fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let tmp = tempfile::Builder::new().prefix(".tmp-").tempfile_in(dir)?;
+    fs::write(tmp.path(), contents)?;
+    // temporary files are private, keep the permissions of a regular file
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let permissions = fs::metadata(path)
+            .map(|m| m.permissions())
+            .unwrap_or_else(|_| fs::Permissions::from_mode(0o644));
+        fs::set_permissions(tmp.path(), permissions)?;
+    }
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use super::{write_atomic, FileLock, LockMode, LOCK_FILE};
+use crate::error::AppError;
+use std::{fs, process, thread, time::Duration};
+use tempfile::TempDir;
+
+#[test]
+fn exclusive_lock_names_the_holder() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join(LOCK_FILE);
+    let _lock = FileLock::acquire(&path, LockMode::Exclusive, false).unwrap();
+
+    for mode in [LockMode::Shared, LockMode::Exclusive] {
+        match FileLock::acquire(&path, mode, false) {
+            Err(AppError::Locked(holder, _)) => {
+                assert!(
+                    holder.contains(&format!("pid {}, command `auto-pack", process::id())),
+                    "{}",
+                    holder
+                )
+            }
+            other => panic!("expected the lock to be taken, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn shared_locks_exclude_writers() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join(LOCK_FILE);
+    // the last exclusive holder isn't named once others share the lock
+    drop(FileLock::acquire(&path, LockMode::Exclusive, false).unwrap());
+    let first = FileLock::acquire(&path, LockMode::Shared, false).unwrap();
+    let second = FileLock::acquire(&path, LockMode::Shared, false).unwrap();
+    match FileLock::acquire(&path, LockMode::Exclusive, false) {
+        Err(AppError::Locked(holder, _)) => assert!(holder.contains("sharing"), "{}", holder),
+        other => panic!("expected the lock to be taken, got {:?}", other),
+    }
+
+    drop(first);
+    drop(second);
+    assert!(FileLock::acquire(&path, LockMode::Exclusive, false).is_ok());
+}
+
+#[test]
+fn waits_for_the_lock() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join(LOCK_FILE);
+    let lock = FileLock::acquire(&path, LockMode::Exclusive, false).unwrap();
+    let holder = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        drop(lock);
+    });
+
+    assert!(FileLock::acquire(&path, LockMode::Exclusive, true).is_ok());
+    holder.join().unwrap();
+}
+
+#[test]
+fn nothing_to_lock_before_init() {
+    let dir = TempDir::new().unwrap();
+    let lock = FileLock::runtime(&dir.path().join(".autopack"), LockMode::Exclusive, false);
+    assert!(lock.unwrap().is_none());
+}
+
+#[test]
+fn writes_atomically() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("project.toml");
+    write_atomic(&path, "first").unwrap();
+    write_atomic(&path, "second").unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+    // no temporary files left behind
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+    }
+}
@@ -1,6 +1,125 @@
-use dialoguer::console::{Emoji, Style, Term};
+pub(crate) mod file;
+#[cfg(test)]
+mod tests;
+
+use crate::error::AppError;
+use dialoguer::console::{self, Emoji, Style, Term};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    env, fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
 use tracing::error;
 
+/// Output format picked with `--output`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum OutputFormat {
+    #[default]
+    Text,
+    /// newline delimited JSON events, for scripts and editors
+    Json,
+}
+
+impl OutputFormat {
+    pub(crate) const NAMES: [&'static str; 2] = ["text", "json"];
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+        })
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => anyhow::bail!(
+                "Unknown output format {:?}, expected one of {}",
+                s,
+                OutputFormat::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// How the helpers below write, set once per process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// emoji and colours on a terminal
+    Pretty,
+    /// neither emoji nor colours, when piped or with `NO_COLOR`
+    Plain,
+    /// an event per line on stdout
+    Json,
+}
+
+static MODE: OnceLock<Mode> = OnceLock::new();
+/// `-q` leaves out steps, banners and progress
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+impl Mode {
+    fn resolve(format: OutputFormat, terminal: bool, no_color: bool) -> Mode {
+        match format {
+            OutputFormat::Json => Mode::Json,
+            OutputFormat::Text if !terminal || no_color => Mode::Plain,
+            OutputFormat::Text => Mode::Pretty,
+        }
+    }
+}
+
+fn mode() -> Mode {
+    MODE.get().copied().unwrap_or(Mode::Pretty)
+}
+
+/// `NO_COLOR` set to anything but an empty string, see https://no-color.org
+pub(crate) fn no_color() -> bool {
+    env::var_os("NO_COLOR")
+        .map(|v| !v.is_empty())
+        .unwrap_or(false)
+}
+
+/// Picks how everything is written for the rest of the process
+pub(crate) fn set_output(format: OutputFormat, quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+    let mode = Mode::resolve(format, Term::stdout().is_term(), no_color());
+    if mode == Mode::Pretty {
+        Term::stdout().set_title("auto-pack");
+    } else {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    tracing::debug!("Writing output as {:?}", mode);
+    // the first pick wins
+    let _ = MODE.set(mode);
+}
+
+/// Whether output is JSON events, which progress bars would interleave with
+pub(crate) fn is_json() -> bool {
+    mode() == Mode::Json
+}
+
+/// Quiet text output, as JSON events are only read by programs
+fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed) && !is_json()
+}
+
+/// Whether progress bars are drawn
+pub(crate) fn shows_progress() -> bool {
+    !is_json() && !is_quiet()
+}
+
 fn handle_err(r: std::io::Result<()>, original_message: &str) {
     match r {
         Ok(_) => (),
@@ -13,50 +132,203 @@ fn handle_err(r: std::io::Result<()>, original_message: &str) {
         }
     }
 }
+
+/// A line of `--output json`, e.g. `{"event":"step","message":"Building","status":"started"}`;
+/// `event` comes first so that readers can dispatch on the line prefix
+fn event_line(event: &str, fields: Value) -> String {
+    let event = Value::from(event);
+    match fields {
+        Value::Object(fields) if !fields.is_empty() => {
+            let fields = Value::Object(fields).to_string();
+            format!("{{\"event\":{},{}", event, &fields[1..])
+        }
+        _ => format!("{{\"event\":{}}}", event),
+    }
+}
+
+fn emit(event: &str, fields: Value) {
+    let str = event_line(event, fields);
+    handle_err(Term::stdout().write_line(&str), &str)
+}
+
+/// `msg` after its emoji, or after the fallback in plain text
+fn line(emoji: Emoji, msg: impl fmt::Display) -> String {
+    match mode() {
+        Mode::Pretty => format!("{} {}", emoji, msg),
+        _ => format!("{} {}", emoji.1, msg).trim_start().to_string(),
+    }
+}
+
 pub fn success(msg: &str) {
-    let str = format!("{} {}", Emoji("👍", ""), Style::new().blue().apply_to(msg));
+    if is_json() {
+        return emit("step", json!({ "status": "succeeded", "message": msg }));
+    }
+    if is_quiet() {
+        return;
+    }
+    let str = line(Emoji("👍", ""), Style::new().blue().apply_to(msg));
     handle_err(Term::stdout().write_line(&str), &str)
 }
 
 pub fn trying(msg: &str) {
-    let str = format!(
-        "{} {}...",
+    if is_json() {
+        return emit("step", json!({ "status": "started", "message": msg }));
+    }
+    if is_quiet() {
+        return;
+    }
+    let str = line(
         Emoji("🕛", ">>"),
-        Style::new().yellow().apply_to(msg)
+        Style::new().yellow().apply_to(format!("{}...", msg)),
     );
     handle_err(Term::stderr().write_line(&str), &str)
 }
 
 pub fn error(msg: &str) {
-    let str = format!("{} {}", Emoji("💣", "!!"), Style::new().red().apply_to(msg));
+    if is_json() {
+        return emit("step", json!({ "status": "failed", "message": msg }));
+    }
+    let str = line(Emoji("💣", "!!"), Style::new().red().apply_to(msg));
+    handle_err(Term::stderr().write_line(&str), &str)
+}
+
+pub fn warn(msg: &str) {
+    if is_json() {
+        return emit("warning", json!({ "message": msg }));
+    }
+    let str = line(Emoji("⚠️", "!"), Style::new().yellow().apply_to(msg));
     handle_err(Term::stderr().write_line(&str), &str)
 }
 
 pub fn instruct(msg: &str) {
-    let str = format!("{} {}", Emoji("ℹ️", ""), Style::new().cyan().apply_to(msg));
+    if is_json() {
+        return emit("info", json!({ "message": msg }));
+    }
+    let str = line(Emoji("ℹ️", ""), Style::new().cyan().apply_to(msg));
     handle_err(Term::stdout().write_line(&str), &str)
 }
 
-pub fn banner(msg: &str) {
-    let width = Term::stdout().size().1.into();
-    let wd = width - 10;
-    let str = format!(
-        "{:-^width$}\n{:^wd$}\n{:-^width$}",
+/// Title centered between two rules, `width` columns wide
+fn banner_text(msg: &str, width: usize) -> String {
+    let title = format!("✨ {} ✨", msg);
+    format!(
+        "{:-^width$}\n{}\n{:-^width$}",
         "-",
-        Style::new().green().apply_to(format!("✨ {} ✨", msg)),
+        // padded before styling, the escape codes would count towards the width
+        Style::new().green().apply_to(format!("{:^width$}", title)),
         "-",
-        width = width,
-        wd = wd
-    );
+        width = width
+    )
+}
+
+pub fn banner(msg: &str) {
+    let str = match mode() {
+        Mode::Json => return emit("stage", json!({ "message": msg })),
+        _ if is_quiet() => return,
+        Mode::Plain => format!("== {} ==", msg),
+        Mode::Pretty => {
+            let width = Term::stdout()
+                .size_checked()
+                .map(|(_, columns)| columns.into())
+                .unwrap_or(80);
+            banner_text(msg, width)
+        }
+    };
     handle_err(Term::stdout().write_line(&str), &str)
 }
 
 pub(crate) fn command_out(msg: &str) {
-    let str = format!("{:<2}{:>2}", ">", Style::new().green().for_stdout().apply_to(msg));
+    tracing::debug!(target: "autopack::command", "{}", msg);
+    if is_json() {
+        return emit("command_output", json!({ "stream": "stdout", "line": msg }));
+    }
+    let str = format!(
+        "{:<2}{:>2}",
+        ">",
+        Style::new().green().for_stdout().apply_to(msg)
+    );
     handle_err(Term::stderr().write_line(&str), &str)
 }
 
 pub(crate) fn command_err(msg: &str) {
-    let str = format!("{:<2}{:>2}", ">", Style::new().red().for_stdout().apply_to(msg));
+    tracing::debug!(target: "autopack::command", stream = "stderr", "{}", msg);
+    if is_json() {
+        return emit("command_output", json!({ "stream": "stderr", "line": msg }));
+    }
+    let str = format!(
+        "{:<2}{:>2}",
+        ">",
+        Style::new().red().for_stdout().apply_to(msg)
+    );
     handle_err(Term::stderr().write_line(&str), &str)
 }
+
+/// Prints the lines removed from `before` and added in `after`
+pub(crate) fn diff(before: &str, after: &str) {
+    let lines = |x: &str, y: &str| {
+        x.lines()
+            .filter(|l| !y.lines().any(|m| m == *l))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let (removed, added) = (lines(before, after), lines(after, before));
+    if is_json() {
+        return emit("diff", json!({ "removed": removed, "added": added }));
+    }
+    let styled = |lines: Vec<String>, sign: &str, style: Style| {
+        lines
+            .into_iter()
+            .map(|l| format!("    {}", style.apply_to(format!("{} {}", sign, l))))
+            .collect::<Vec<_>>()
+    };
+    let removed = styled(removed, "-", Style::new().red().for_stdout());
+    let added = styled(added, "+", Style::new().green().for_stdout());
+    for str in removed.iter().chain(added.iter()) {
+        handle_err(Term::stdout().write_line(str), str)
+    }
+}
+
+/// Prints a line of command output as is
+pub(crate) fn print(msg: &str) {
+    if is_json() {
+        return emit("output", json!({ "line": msg }));
+    }
+    handle_err(Term::stdout().write_line(msg), msg)
+}
+
+/// Prints `text`, or `value` as a `record` event, e.g. a row of a listing
+pub(crate) fn record<T: Serialize>(value: &T, text: &str) {
+    if is_json() {
+        return emit("record", json!({ "data": value }));
+    }
+    print(text)
+}
+
+/// Build phase `phase` starting, or finishing after `secs`; only reported as events since
+/// the progress bars show them otherwise
+pub(crate) fn progress(phase: &str, secs: Option<f64>) {
+    if !is_json() {
+        return;
+    }
+    match secs {
+        None => emit("progress", json!({ "phase": phase, "status": "started" })),
+        Some(secs) => emit(
+            "progress",
+            json!({ "phase": phase, "status": "succeeded", "secs": secs }),
+        ),
+    }
+}
+
+/// Last event of a command with `--output json`
+pub(crate) fn result(error: Option<&AppError>) {
+    if !is_json() {
+        return;
+    }
+    match error {
+        None => emit("result", json!({ "ok": true })),
+        Some(e) => emit(
+            "result",
+            json!({ "ok": false, "error": e.to_string(), "hint": e.hint() }),
+        ),
+    }
+}
@@ -0,0 +1,176 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing::{debug, Level};
+use tracing_subscriber::{
+    filter::Targets, fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
+
+/// Folder of the runtime directory holding the autopack logs
+pub(crate) const LOGS_DIR: &str = "logs";
+pub(crate) const LOG_FILE: &str = "autopack.log";
+/// size past which the log is rotated when a run starts
+const MAX_SIZE: u64 = 1 << 20;
+/// rotated logs kept next to the current one, `autopack.1.log` being the newest
+const KEEP: usize = 3;
+/// events held while the runtime directory doesn't exist yet
+const MAX_PENDING: usize = 1 << 20;
+
+/// Terminal verbosity picked with `-q`, `-v` or `-vv`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Verbosity {
+    /// warnings, errors and results only
+    Quiet,
+    Normal,
+    /// debug events of autopack, including the output of pack and docker
+    Verbose,
+    /// debug events of the libraries too
+    Trace,
+}
+
+impl Verbosity {
+    pub(crate) fn new(verbose: u8, quiet: bool) -> Verbosity {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (_, 0) => Verbosity::Normal,
+            (_, 1) => Verbosity::Verbose,
+            _ => Verbosity::Trace,
+        }
+    }
+
+    /// Filter of the events written to the terminal, unless `RUST_LOG` is set
+    fn directives(&self) -> &'static str {
+        match self {
+            Verbosity::Quiet | Verbosity::Normal => "error",
+            Verbosity::Verbose => "warn,autopack=debug",
+            Verbosity::Trace => "debug,autopack=trace",
+        }
+    }
+}
+
+pub(crate) fn log_path(runtime_dir: &Path) -> PathBuf {
+    runtime_dir.join(LOGS_DIR).join(LOG_FILE)
+}
+
+fn rotated_path(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("autopack.{}.log", n))
+}
+
+/// Shifts `autopack.log` to `autopack.1.log` and so on once it's grown past `max_size`,
+/// dropping the oldest
+fn rotate(dir: &Path, max_size: u64) -> io::Result<()> {
+    let current = dir.join(LOG_FILE);
+    match fs::metadata(&current) {
+        Ok(meta) if meta.len() > max_size => (),
+        _ => return Ok(()),
+    }
+    for n in (1..KEEP).rev() {
+        let from = rotated_path(dir, n);
+        if from.exists() {
+            fs::rename(from, rotated_path(dir, n + 1))?;
+        }
+    }
+    fs::rename(current, rotated_path(dir, 1))
+}
+
+/// Appends to the log of the runtime directory. Events are held until the runtime
+/// directory exists, e.g. while `init` creates it, and logging failures never fail the run.
+pub(crate) struct LogFile {
+    runtime_dir: PathBuf,
+    file: Option<File>,
+    pending: Vec<u8>,
+    failed: bool,
+}
+
+impl LogFile {
+    pub(crate) fn new(runtime_dir: PathBuf) -> LogFile {
+        LogFile {
+            runtime_dir,
+            file: None,
+            pending: vec![],
+            failed: false,
+        }
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let dir = self.runtime_dir.join(LOGS_DIR);
+        fs::create_dir_all(&dir)?;
+        rotate(&dir, MAX_SIZE)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE))?;
+        file.write_all(&std::mem::take(&mut self.pending))?;
+        self.file = Some(file);
+        Ok(())
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() && !self.failed && self.runtime_dir.exists() {
+            // stops trying, e.g. on a read-only project
+            self.failed = self.open().is_err();
+        }
+        match self.file.as_mut() {
+            Some(file) => file.write(buf),
+            None => {
+                if !self.failed && self.pending.len() + buf.len() <= MAX_PENDING {
+                    self.pending.extend_from_slice(buf);
+                }
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes events to the terminal per `verbosity` or `RUST_LOG`, and debug events of autopack
+/// to the log of `runtime_dir`, if any
+pub(crate) fn init(verbosity: Verbosity, runtime_dir: Option<PathBuf>) {
+    let no_color = super::no_color();
+    let terminal_filter = match env::var("RUST_LOG") {
+        Ok(directives) => EnvFilter::new(directives),
+        Err(_) => EnvFilter::new(verbosity.directives()),
+    };
+    // diagnostics stay off stdout, which `--output json` reserves for events
+    let terminal = fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal() && !no_color)
+        .with_filter(terminal_filter);
+    let file = runtime_dir.as_ref().map(|dir| {
+        fmt::layer()
+            .with_ansi(false)
+            .with_writer(Mutex::new(LogFile::new(dir.clone())))
+            .with_filter(
+                Targets::new()
+                    .with_target("autopack", Level::DEBUG)
+                    .with_default(Level::INFO),
+            )
+    });
+    if let Err(e) = tracing_subscriber::registry()
+        .with(terminal)
+        .with(file)
+        .try_init()
+    {
+        eprintln!("Failed setting up logging :: {}", e);
+    }
+    debug!(
+        "auto-pack {} logging to {:?}",
+        env!("CARGO_PKG_VERSION"),
+        runtime_dir.map(|dir| log_path(&dir))
+    );
+}
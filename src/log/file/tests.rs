@@ -0,0 +1,46 @@
+use super::{log_path, rotate, rotated_path, LogFile, Verbosity, LOG_FILE};
+use std::{fs, io::Write};
+use tempfile::TempDir;
+
+#[test]
+fn picks_the_verbosity() {
+    assert_eq!(Verbosity::new(0, false), Verbosity::Normal);
+    assert_eq!(Verbosity::new(1, false), Verbosity::Verbose);
+    assert_eq!(Verbosity::new(3, false), Verbosity::Trace);
+    assert_eq!(Verbosity::new(2, true), Verbosity::Quiet);
+}
+
+#[test]
+fn holds_events_until_the_runtime_dir_exists() {
+    let tmp = TempDir::new().unwrap();
+    let runtime_dir = tmp.path().join(".autopack");
+    let mut log = LogFile::new(runtime_dir.clone());
+    log.write_all(b"before init\n").unwrap();
+    assert!(!runtime_dir.exists());
+
+    fs::create_dir(&runtime_dir).unwrap();
+    log.write_all(b"after init\n").unwrap();
+    assert_eq!(
+        fs::read_to_string(log_path(&runtime_dir)).unwrap(),
+        "before init\nafter init\n"
+    );
+}
+
+#[test]
+fn rotates_large_logs() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    for run in 1..=5 {
+        fs::write(dir.join(LOG_FILE), format!("run {}", run)).unwrap();
+        rotate(dir, 2).unwrap();
+    }
+    assert!(!dir.join(LOG_FILE).exists());
+    assert_eq!(fs::read_to_string(rotated_path(dir, 1)).unwrap(), "run 5");
+    assert_eq!(fs::read_to_string(rotated_path(dir, 3)).unwrap(), "run 3");
+    assert!(!rotated_path(dir, 4).exists());
+
+    // small logs stay
+    fs::write(dir.join(LOG_FILE), "x").unwrap();
+    rotate(dir, 2).unwrap();
+    assert!(dir.join(LOG_FILE).exists());
+}
@@ -0,0 +1,43 @@
+use super::{banner_text, event_line, Mode, OutputFormat};
+use serde_json::{json, Value};
+
+#[test]
+fn resolves_the_output_mode() {
+    assert_eq!(Mode::resolve(OutputFormat::Text, true, false), Mode::Pretty);
+    assert_eq!(Mode::resolve(OutputFormat::Text, false, false), Mode::Plain);
+    assert_eq!(Mode::resolve(OutputFormat::Text, true, true), Mode::Plain);
+    assert_eq!(Mode::resolve(OutputFormat::Json, true, false), Mode::Json);
+    assert_eq!(Mode::resolve(OutputFormat::Json, false, true), Mode::Json);
+
+    assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+    assert!("yaml".parse::<OutputFormat>().is_err());
+}
+
+#[test]
+fn writes_an_event_per_line() {
+    let line = event_line(
+        "step",
+        json!({ "status": "started", "message": "Building\nimage \"web\"" }),
+    );
+    assert!(!line.contains('\n'));
+    assert!(line.starts_with(r#"{"event":"step","#));
+    let event: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(event["status"], "started");
+    assert_eq!(event["message"], "Building\nimage \"web\"");
+
+    let line = event_line("result", json!({ "ok": false, "error": "Build failure" }));
+    assert_eq!(
+        line,
+        r#"{"event":"result","error":"Build failure","ok":false}"#
+    );
+    assert_eq!(event_line("stage", Value::Null), r#"{"event":"stage"}"#);
+}
+
+#[test]
+fn fits_the_banner_to_narrow_terminals() {
+    for width in [0, 1, 5, 9, 10, 80] {
+        let banner = banner_text("Initializing autopack", width);
+        assert_eq!(banner.lines().count(), 3);
+    }
+    assert!(banner_text("Built", 20).starts_with(&"-".repeat(20)));
+}
@@ -0,0 +1,181 @@
+use crate::runtime::{container_binding_path, BindingMount, Relocation};
+use serde::{Deserialize, Serialize};
+use std::{env, fs, path::PathBuf};
+use tracing::debug;
+
+/// Proxy variables passed on to the build containers when set on the host
+const PROXY_VARS: [&str; 3] = ["HTTPS_PROXY", "HTTP_PROXY", "NO_PROXY"];
+
+const CA_BUNDLE: &str = "ca-bundle.pem";
+
+/// Reads a proxy variable the way curl does, preferring the upper case name
+fn proxy_var(name: &str) -> Option<String> {
+    env::var(name)
+        .or_else(|_| env::var(name.to_lowercase()))
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+}
+
+/// Hosts listed in `NO_PROXY`; `*` disables the proxy and `example.com` also covers its subdomains
+#[derive(Debug, Default)]
+struct NoProxy(Vec<String>);
+
+impl NoProxy {
+    fn from_env() -> NoProxy {
+        NoProxy(
+            proxy_var("NO_PROXY")
+                .map(|v| {
+                    v.split(',')
+                        .map(|h| h.trim().trim_start_matches('.').to_lowercase())
+                        .filter(|h| !h.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        )
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        let host = host.to_lowercase();
+        self.0
+            .iter()
+            .any(|h| h == "*" || host == *h || host.ends_with(&format!(".{}", h)))
+    }
+}
+
+/// Network settings for environments behind TLS-inspecting or authenticating proxies
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub(crate) struct Network {
+    /// PEM encoded root certificates trusted in addition to the system ones,
+    /// both for downloads and inside the build containers
+    pub(crate) ca_certs: Vec<PathBuf>,
+}
+
+impl Network {
+    pub(crate) fn new(ca_certs: Vec<PathBuf>) -> anyhow::Result<Network> {
+        let ca_certs = ca_certs
+            .into_iter()
+            .map(|c| {
+                c.canonicalize()
+                    .map_err(|e| anyhow::anyhow!("CA certificate {:?} not found :: {}", c, e))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let network = Network { ca_certs };
+        // fail early on anything reqwest can't parse
+        network.root_certificates()?;
+        Ok(network)
+    }
+
+    pub(crate) fn relocate(&mut self, paths: &Relocation) {
+        self.ca_certs = self.ca_certs.iter().map(|c| paths.project(c)).collect();
+    }
+
+    fn root_certificates(&self) -> anyhow::Result<Vec<reqwest::Certificate>> {
+        self.ca_certs
+            .iter()
+            .map(|c| {
+                fs::read(c)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|pem| reqwest::Certificate::from_pem(&pem).map_err(Into::into))
+                    .map_err(|e| anyhow::anyhow!("Invalid CA certificate {:?} :: {}", c, e))
+            })
+            .collect()
+    }
+
+    /// HTTP client trusting the extra roots and honouring `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY`
+    pub(crate) fn http_client(&self) -> anyhow::Result<reqwest::Client> {
+        let no_proxy = NoProxy::from_env();
+        let (https, http) = (proxy_var("HTTPS_PROXY"), proxy_var("HTTP_PROXY"));
+        debug!(
+            "HTTP client with {} extra CA(s), https proxy {}, http proxy {}, no proxy {:?}",
+            self.ca_certs.len(),
+            https.is_some(),
+            http.is_some(),
+            no_proxy
+        );
+
+        let mut builder = reqwest::Client::builder().no_proxy();
+        for cert in self.root_certificates()? {
+            builder = builder.add_root_certificate(cert);
+        }
+
+        if https.is_some() || http.is_some() {
+            let proxy = reqwest::Proxy::custom(move |url| {
+                let bypass = url.host_str().map(|h| no_proxy.matches(h)).unwrap_or(false);
+                match url.scheme() {
+                    _ if bypass => None,
+                    "https" => https.clone().or_else(|| http.clone()),
+                    _ => http.clone(),
+                }
+            });
+            builder = builder.proxy(proxy);
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// `ca-certificates` binding for the [Paketo CA Certificates Buildpack](https://github.com/paketo-buildpacks/ca-certificates)
+    pub(crate) fn ca_mount(&self) -> anyhow::Result<Option<BindingMount>> {
+        if self.ca_certs.is_empty() {
+            return Ok(None);
+        }
+        let bundle = self
+            .ca_certs
+            .iter()
+            .map(fs::read)
+            .collect::<Result<Vec<_>, _>>()?
+            .join(&b'\n');
+        BindingMount::ephemeral(
+            "ca-certificates",
+            "ca-certificates",
+            &[(CA_BUNDLE, bundle.as_slice())],
+        )
+        .map(Some)
+    }
+
+    /// `--env` arguments for `pack build`. Proxy variables are passed by name only so that
+    /// pack reads them from its environment and credentials in proxy URLs stay out of the logs.
+    pub(crate) fn build_env(&self) -> Vec<String> {
+        let mut env = PROXY_VARS
+            .iter()
+            .flat_map(|v| [v.to_string(), v.to_lowercase()])
+            .filter(|v| env::var(v).map(|v| !v.trim().is_empty()).unwrap_or(false))
+            .collect::<Vec<_>>();
+
+        if !self.ca_certs.is_empty() {
+            // node bundles its own roots and ignores the system trust store
+            env.push(format!(
+                "NODE_EXTRA_CA_CERTS={}/{}",
+                container_binding_path("ca-certificates"),
+                CA_BUNDLE
+            ));
+        }
+        env
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_proxy_matching() {
+        let no_proxy = NoProxy(vec!["example.com".into(), "10.0.0.1".into()]);
+
+        assert!(no_proxy.matches("example.com"));
+        assert!(no_proxy.matches("artifacts.EXAMPLE.com"));
+        assert!(no_proxy.matches("10.0.0.1"));
+        assert!(!no_proxy.matches("github.com"));
+        assert!(!no_proxy.matches("notexample.com"));
+        assert!(NoProxy(vec!["*".into()]).matches("github.com"));
+    }
+
+    #[test]
+    fn rejects_invalid_certificates() {
+        let cert = tempfile::NamedTempFile::new().unwrap();
+        fs::write(cert.path(), "not a certificate").unwrap();
+
+        assert!(Network::new(vec![cert.path().to_path_buf()]).is_err());
+        assert!(Network::new(vec![PathBuf::from("/does/not/exist.pem")]).is_err());
+        assert!(Network::default().http_client().is_ok());
+    }
+}
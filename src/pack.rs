@@ -1,62 +1,92 @@
+mod diagnosis;
 mod image;
+mod lifecycle;
+mod release;
+mod version;
 
-use super::log::{success, trying};
-use crate::error::AppError;
+use super::log::{success, trying, warn};
+use crate::{error::AppError, network::Network, runtime::Relocation, tools::ToolCache};
 use anyhow::anyhow;
-use futures_util::StreamExt;
-use indicatif::{ProgressBar, ProgressStyle};
+use release::{ArchiveFormat, Release, Target};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
+    env,
     fs::File,
-    io::Write,
     path::{Path, PathBuf},
 };
 use tracing::debug;
-
-#[cfg(target_os = "macos")]
-fn release_url(pack_version: &str) -> String {
-    format!(
-        "https://github.com/buildpacks/pack/releases/download/v{}/pack-v{}-macos.tgz",
-        pack_version, pack_version
-    )
+use version::PackVersion;
+
+/// pack cli version installed by autopack unless another one is configured
+pub(crate) const PACK_VERSION: &str = "0.27.0";
+
+/// Expands the `{version}` and `{artifact}` placeholders of an artifact mirror URL template,
+/// `{artifact}` being the file name of the GitHub release download
+fn mirror_url(template: &str, version: &str, release_url: &str) -> String {
+    let artifact = release_url.rsplit('/').next().unwrap_or_default();
+    template
+        .replace("{version}", version)
+        .replace("{artifact}", artifact)
 }
 
-#[cfg(all(target_os = "linux", target_arch = "arm"))]
-fn release_url(pack_version: &str) -> String {
-    format!(
-        "https://github.com/buildpacks/pack/releases/download/v{}/pack-v{}-linux-arm64.tgz",
-        pack_version, pack_version
-    )
+/// Build and launch cache volumes pack creates for `image_name`, named the way pack names
+/// them: `pack-cache-<repository>_<tag>-<first 6 bytes of sha256(reference)>.<kind>`
+pub(crate) fn cache_volumes(image_name: &str) -> Vec<String> {
+    let (repository, tag) = image_name.split_once(':').unwrap_or((image_name, "latest"));
+    let reference = format!("index.docker.io/library/{}:{}", repository, tag);
+    let hash = hex::encode(&Sha256::digest(reference.as_bytes())[..6]);
+    ["build", "launch"]
+        .iter()
+        .map(|kind| {
+            format!(
+                "pack-cache-library_{}_{}-{}.{}",
+                repository, tag, hash, kind
+            )
+        })
+        .collect()
 }
 
-#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-fn release_url(pack_version: &str) -> String {
-    format!(
-        "https://github.com/buildpacks/pack/releases/download/v{}/pack-v{}-linux.tgz",
-        pack_version, pack_version
-    )
+fn find_on_path(bin_name: &std::ffi::OsStr) -> Option<PathBuf> {
+    env::var_os("PATH")
+        .iter()
+        .flat_map(env::split_paths)
+        .map(|dir| dir.join(bin_name))
+        .find(|p| p.is_file())
 }
 
-#[cfg(target_os = "windows")]
-fn release_url(pack_version: &str) -> String {
-    format!(
-        "https://github.com/buildpacks/pack/releases/download/v{}/pack-v{}-windows.zip",
-        pack_version, pack_version
-    )
+/// Where the pack cli comes from when it isn't in the tool cache yet
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PackSource {
+    /// existing binary used as is
+    pub(crate) binary: Option<PathBuf>,
+    /// local release archive, e.g. for air-gapped machines
+    pub(crate) archive: Option<PathBuf>,
+    /// artifact mirror URL template with `{version}` and `{artifact}` placeholders
+    pub(crate) mirror: Option<String>,
+    /// fail instead of accessing the network
+    pub(crate) offline: bool,
 }
 
 /// Internal binary to be used to create the CNBs
 /// https://buildpacks.io/docs/tools/pack/
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub(crate) struct Pack {
-    /// directory within `.autopack` holding the binary. default to `.bin`
+    /// directory within the user level tool cache holding the binary, i.e. `<cache>/pack/<version>`
     pub(crate) bin_dir_path: PathBuf,
-    /// OS specific full file path to the pack cli binary. default to `.autopack/.bin/pack` in unix and `.autopack/.bin/pack.exe` in windows
+    /// OS specific full file path to the pack cli binary, i.e. `pack` in unix and `pack.exe` in windows
     pub(crate) bin_file_path: PathBuf,
-    /// OS specific download URL for pack cli
+    /// download URL for pack cli on this OS and architecture, empty when there is no release for it
     pub(crate) release_url: String,
     /// pack cli version
     pub(crate) release_version: String,
+    /// builds don't pull images, which have to be present in the local docker daemon
+    #[serde(default)]
+    pub(crate) offline: bool,
+    /// `release_version` was asked for explicitly, so a pack cli on PATH only does when it
+    /// matches and builds don't nag about newer releases
+    #[serde(default)]
+    pub(crate) pinned: bool,
 }
 
 impl Default for Pack {
@@ -66,13 +96,20 @@ impl Default for Pack {
             bin_file_path: PathBuf::new().join(".autopack").join(".bin").join("pack"),
             release_url: "".to_string(),
             release_version: "".into(),
+            offline: false,
+            pinned: false,
         }
     }
 }
 
 impl Pack {
-    pub(crate) fn builder(runtime_dir: &Path) -> PackBuilder {
-        PackBuilder::new(runtime_dir)
+    pub(crate) fn builder(cache: &ToolCache) -> PackBuilder {
+        PackBuilder::new(cache)
+    }
+
+    pub(crate) fn relocate(&mut self, paths: &Relocation) {
+        self.bin_dir_path = paths.tools(&self.bin_dir_path);
+        self.bin_file_path = paths.tools(&self.bin_file_path);
     }
 
     pub(crate) fn is_present(&self) -> anyhow::Result<bool> {
@@ -80,98 +117,219 @@ impl Pack {
 
         self.bin_file_path
             .exists()
-            .then(|| true)
+            .then_some(true)
             .ok_or_else(|| anyhow::anyhow!("Pack cli not available"))
     }
 
-    pub(crate) async fn install(&mut self) -> Result<&Self, AppError> {
+    /// Records in the tool cache that the project uses this pack cli, if installed there
+    pub(crate) fn add_ref(&self, cache: &ToolCache, project_root: &Path) -> anyhow::Result<()> {
+        if self.bin_dir_path == cache.tool_dir("pack", &self.release_version) {
+            cache.add_ref("pack", &self.release_version, project_root)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn install(
+        &mut self,
+        cache: &ToolCache,
+        network: &Network,
+        source: &PackSource,
+    ) -> Result<&Self, AppError> {
         trying("Installing pack cli");
-        let x = self.is_present().unwrap_or(false);
-        if x {
-            debug!("Pack cli present, skipping download and install");
-            success("Pack cli already present");
-            Ok(self)
-        } else {
-            // download release binary
-            self.download_and_extract().await.map_err(|e| {
-                AppError::PostConfigureError("Failed to download and extract Pack cli", anyhow!(e))
-            })?;
+        self.offline = source.offline;
 
-            // check if the installation is fine
-            let version = self.cli_version().map_err(|e| {
-                AppError::PostConfigureError("Failed to get the pack cli version", e)
-            })?;
+        if let Some(binary) = source.binary.as_ref() {
+            self.use_binary(binary)
+                .map_err(|e| AppError::PostConfigureError("Unusable pack cli binary", e))?;
+            success(&format!("Using pack cli at {:?}", self.bin_file_path));
+            return Ok(self);
+        }
 
-            debug!("Installed pack cli version {}", version);
-            success("Installed pack cli");
-            Ok(self)
+        if let Some(archive) = source.archive.as_ref() {
+            cache
+                .verify_local(archive)
+                .and_then(|_| ArchiveFormat::from_file_name(archive))
+                .and_then(|format| self.extract(cache, archive, format))
+                .map_err(|e| {
+                    AppError::PostConfigureError("Failed to install pack cli from archive", e)
+                })?;
+            self.check_installed()?;
+            success("Installed pack cli from archive");
+            return Ok(self);
         }
-    }
 
-    #[cfg(any(target_os = "macos", target_os = "linux"))]
-    fn decompress<P: AsRef<Path>>(&self, path: P, dest: P) -> anyhow::Result<File> {
-        use flate2::read::GzDecoder;
-        use tar::Archive;
-
-        let tgz = File::open(path)?;
-        let tar = GzDecoder::new(tgz);
-        let mut archive = Archive::new(tar);
-        archive.unpack(dest.as_ref())?;
-        File::open(&self.bin_file_path).map_err(|e| anyhow!("Error during decompress. Error {}", e))
-    }
+        if self.is_present().unwrap_or(false) {
+            match self.installed_version() {
+                Ok(v) if v.to_string() == self.release_version => {
+                    debug!(
+                        "Pack cli present at {:?}, skipping download and install",
+                        self.bin_file_path
+                    );
+                    success("Pack cli already present");
+                    return Ok(self);
+                }
+                other => {
+                    debug!("Cached pack cli reports {:?}, re-installing", other);
+                    warn(&format!(
+                        "Cached pack cli {} is broken; re-installing it",
+                        self.release_version
+                    ));
+                    cache.remove("pack", &self.release_version).map_err(|e| {
+                        AppError::ToolCacheError("Failed removing the broken pack cli", e)
+                    })?;
+                }
+            }
+        }
+
+        if let Some(system) = find_on_path(self.bin_name()) {
+            let cached = self.clone();
+            match self.use_binary(&system) {
+                Ok(_) if self.pinned && self.release_version != cached.release_version => {
+                    debug!(
+                        "Skipping pack cli {} at {:?}, {} is pinned",
+                        self.release_version, system, cached.release_version
+                    );
+                    *self = cached;
+                }
+                Ok(_) => {
+                    success(&format!("Using pack cli at {:?}", self.bin_file_path));
+                    return Ok(self);
+                }
+                Err(e) => {
+                    debug!("Skipping pack cli at {:?} :: {}", system, e);
+                    *self = cached;
+                }
+            }
+        }
+
+        if source.offline {
+            return Err(AppError::OfflineError(format!(
+                "pack cli {} is neither cached nor on PATH; pass --pack-binary or --pack-archive",
+                self.release_version
+            )));
+        }
 
-    #[cfg(target_os = "windows")]
-    fn decompress<P: AsRef<Path>>(&self, path: P, dest: P) -> anyhow::Result<File> {
-        let mut archive = zip::ZipArchive::new(File::open(path)?)?;
-        archive.extract(dest.as_ref())?;
-        File::open(&self.bin_file_path).map_err(|e| anyhow!("Error during decompress. Error {}", e))
+        let release = Release::resolve(&Target::host(), &self.release_version).map_err(|e| {
+            AppError::UnsupportedPlatform(format!("{}; pass --pack-binary or --pack-archive", e))
+        })?;
+
+        // download release binary
+        let url = source
+            .mirror
+            .as_deref()
+            .map(|m| mirror_url(m, &self.release_version, &self.release_url))
+            .unwrap_or_else(|| self.release_url.clone());
+        self.download_and_extract(cache, network, &url, release.format)
+            .await
+            .map_err(|e| {
+                AppError::PostConfigureError("Failed to download and extract Pack cli", anyhow!(e))
+            })?;
+        self.check_installed()?;
+        success("Installed pack cli");
+        Ok(self)
     }
 
-    async fn download_and_extract(&mut self) -> anyhow::Result<&Self> {
-        std::fs::create_dir(&self.bin_dir_path)?;
+    /// Points at an existing binary, provided its version is compatible
+    fn use_binary(&mut self, binary: &Path) -> anyhow::Result<()> {
+        self.bin_file_path = binary
+            .canonicalize()
+            .map_err(|e| anyhow!("pack cli {:?} not found :: {}", binary, e))?;
+        self.bin_dir_path = self
+            .bin_file_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let version = self.installed_version()?;
+        version
+            .check_supported()
+            .map_err(|e| anyhow!("{:?} :: {}", self.bin_file_path, e))?;
+        debug!(
+            "Using pack cli {:?} version {}",
+            self.bin_file_path, version
+        );
+        self.release_version = version.to_string();
+        Ok(())
+    }
 
-        let mut temp_file = tempfile::Builder::new().tempfile_in(&self.bin_dir_path)?;
+    fn check_installed(&self) -> Result<(), AppError> {
+        let version = self
+            .installed_version()
+            .map_err(|e| AppError::PostConfigureError("Failed to get the pack cli version", e))?;
+        if version.to_string() != self.release_version {
+            return Err(AppError::PostConfigureError(
+                "Installed pack cli has an unexpected version",
+                anyhow!("expected {}, got {}", self.release_version, version),
+            ));
+        }
+        debug!("Installed pack cli version {}", version);
+        Ok(())
+    }
 
-        let client = reqwest::Client::new();
-        let response = client.get(self.release_url.as_str()).send().await?;
-        let bin_size = response
-            .content_length()
-            .ok_or_else(|| anyhow::Error::msg("Error getting content length"))?;
+    pub(crate) fn installed_version(&self) -> anyhow::Result<PackVersion> {
+        self.cli_version()?.parse()
+    }
 
-        let mut start = 0_u64;
-        let end = bin_size - 1;
+    /// Parsed `release_version`
+    pub(crate) fn version(&self) -> anyhow::Result<PackVersion> {
+        self.release_version.parse()
+    }
 
-        let bar = ProgressBar::new(end);
-        bar.set_style(
-            ProgressStyle::default_bar()
-                .template(
-                    "[{elapsed_precise}] |{bar:40.cyan/blue}| {bytes:>7}/{total_bytes:7} {msg} [{eta}]",
-                )
-                .unwrap()
-                .progress_chars("█▛▌▖  "),
-        );
-        bar.set_message("Downloading pack cli");
-
-        let mut bin_stream = response.bytes_stream();
-        while let Some(bs) = bin_stream.next().await {
-            let chunk = bs?;
-            let chunk_size: u64 = chunk.len() as u64;
-            temp_file.write(&chunk).map_err(|e| {
-                anyhow::Error::msg(format!("Error while writing content to file. Error {}", e))
-            })?;
-            let progress = std::cmp::min(chunk_size, end - start + 1);
-            start += progress;
-            bar.inc(progress);
+    /// Fails for binaries outside of the supported range and warns about ones older than
+    /// the version this autopack installs, unless an older version was pinned
+    pub(crate) fn check_outdated(&self) -> anyhow::Result<()> {
+        let installed = self.installed_version()?;
+        installed.check_supported()?;
+        if !self.pinned && installed < PACK_VERSION.parse()? {
+            warn(&format!(
+                "pack cli {} is outdated; run `auto-pack tools upgrade` to switch to {}",
+                installed, PACK_VERSION
+            ));
         }
+        Ok(())
+    }
 
-        bar.finish_with_message("Downloaded pack cli");
-        bar.finish_and_clear();
+    fn bin_name(&self) -> &std::ffi::OsStr {
+        self.bin_file_path.file_name().unwrap_or_default()
+    }
 
-        let f = self.decompress(temp_file.path(), &self.bin_dir_path)?;
-        set_permissions(f)?;
+    async fn download_and_extract(
+        &mut self,
+        cache: &ToolCache,
+        network: &Network,
+        url: &str,
+        format: ArchiveFormat,
+    ) -> anyhow::Result<&Self> {
+        let client = network.http_client()?;
+        let archive = cache.download_verified(&client, url).await?;
+        self.extract(cache, &archive, format)?;
+        cache.remove_download(&archive);
         Ok(self)
     }
 
+    /// Installs the binary of a release archive into the tool cache
+    fn extract(
+        &mut self,
+        cache: &ToolCache,
+        archive: &Path,
+        format: ArchiveFormat,
+    ) -> anyhow::Result<()> {
+        let bin_name = self.bin_name().to_owned();
+        if bin_name.is_empty() {
+            anyhow::bail!("Invalid pack cli path {:?}", self.bin_file_path);
+        }
+        let dir = cache.install_atomically("pack", &self.release_version, |staging| {
+            decompress(archive, staging, format)?;
+            let f = File::open(staging.join(&bin_name))
+                .map_err(|e| anyhow!("Error during decompress. Error {}", e))?;
+            set_permissions(f)?;
+            Ok(())
+        })?;
+
+        self.bin_dir_path = dir.clone();
+        self.bin_file_path = dir.join(bin_name);
+        Ok(())
+    }
+
     pub(crate) fn cli_version(&self) -> anyhow::Result<String> {
         let mut cmd = std::process::Command::new(&self.bin_file_path);
         let out = cmd.arg("--version").output()?;
@@ -185,44 +343,87 @@ impl Pack {
     }
 }
 pub(crate) struct PackBuilder {
-    /// directory within `.autopack` holding the binary. default to `.bin`
-    pub(crate) bin_dir_path: PathBuf,
-    /// OS specific full file path to the pack cli binary. default to `.autopack/.bin/pack` in unix and `.autopack/.bin/pack.exe` in windows
-    pub(crate) bin_file_path: PathBuf,
-    /// OS specific download URL for pack cli
-    pub(crate) release_url: String,
+    /// user level tool cache holding the pack cli versions
+    cache: ToolCache,
+    /// OS and architecture to install pack cli for
+    target: Target,
     /// pack cli version
     pub(crate) release_version: String,
+    /// whether `release_version` was asked for instead of defaulting to [`PACK_VERSION`]
+    pinned: bool,
 }
 
 impl PackBuilder {
-    pub(crate) fn new(runtime_dir: &Path) -> Self {
-        let release_version = "0.27.0".to_string();
-        let bin_dir_path = PathBuf::new().join(runtime_dir).join(".bin");
+    pub(crate) fn new(cache: &ToolCache) -> Self {
         PackBuilder {
-            bin_dir_path: bin_dir_path.clone(),
-            release_version: release_version.clone(),
-            release_url: release_url(&release_version),
-
-            #[cfg(any(target_os = "linux", target_os = "macos"))]
-            bin_file_path: bin_dir_path.join("pack"),
+            cache: cache.clone(),
+            target: Target::host(),
+            release_version: PACK_VERSION.to_string(),
+            pinned: false,
+        }
+    }
 
-            #[cfg(target_os = "windows")]
-            bin_file_path: bin_dir_path.join("pack.exe"),
+    /// Pins the pack cli version, which has to be within the supported range
+    pub(crate) fn version(&mut self, version: Option<&str>) -> anyhow::Result<&mut Self> {
+        if let Some(version) = version {
+            let version = version.parse::<PackVersion>()?;
+            version.check_supported()?;
+            self.release_version = version.to_string();
+            self.pinned = true;
         }
+        Ok(self)
     }
 
     pub(crate) fn build(&mut self) -> Pack {
+        let bin_dir_path = self.cache.tool_dir("pack", &self.release_version);
         Pack {
-            bin_dir_path: self.bin_dir_path.clone(),
-            bin_file_path: self.bin_file_path.clone(),
-            release_url: self.release_url.clone(),
+            bin_file_path: bin_dir_path.join(self.target.bin_name()),
+            bin_dir_path,
+            // unknown targets can still use a system pack cli; downloads fail in `install`
+            release_url: Release::resolve(&self.target, &self.release_version)
+                .map(|r| r.url)
+                .unwrap_or_default(),
             release_version: self.release_version.clone(),
+            offline: false,
+            pinned: self.pinned,
         }
     }
 }
 
-#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn decompress(path: &Path, dest: &Path, format: ArchiveFormat) -> anyhow::Result<()> {
+    match format {
+        ArchiveFormat::Tgz => untar(path, dest),
+        ArchiveFormat::Zip => unzip(path, dest),
+    }
+}
+
+fn untar(path: &Path, dest: &Path) -> anyhow::Result<()> {
+    use flate2::read::GzDecoder;
+    use tar::Archive;
+
+    let tgz = File::open(path)?;
+    let tar = GzDecoder::new(tgz);
+    let mut archive = Archive::new(tar);
+    archive.unpack(dest)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn unzip(path: &Path, dest: &Path) -> anyhow::Result<()> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    archive.extract(dest)?;
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn unzip(path: &Path, _dest: &Path) -> anyhow::Result<()> {
+    anyhow::bail!(
+        "{:?} is a zip archive, which only holds the Windows pack cli",
+        path
+    )
+}
+
+#[cfg(unix)]
 fn set_permissions(f: File) -> anyhow::Result<File> {
     use std::os::unix::prelude::PermissionsExt;
 
@@ -232,29 +433,100 @@ fn set_permissions(f: File) -> anyhow::Result<File> {
     Ok(f)
 }
 
-#[cfg(target_os = "windows")]
+#[cfg(not(unix))]
 fn set_permissions(f: File) -> anyhow::Result<File> {
     Ok(f)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::env;
-
-    use crate::runtime::Runtime;
-
     use super::*;
 
     #[tokio::test]
     async fn pack_version_test() {
-        let rt = Runtime::builder(env::current_dir().unwrap().as_path())
-            .dir(false)
-            .unwrap()
-            .build();
-        let mut pack_builder = Pack::builder(rt.dir().as_path());
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ToolCache::at(dir.path());
+        let mut pack_builder = Pack::builder(&cache);
         let mut pack = pack_builder.build();
-        let pack = pack.install().await.unwrap();
+        let pack = pack
+            .install(&cache, &Network::default(), &PackSource::default())
+            .await
+            .unwrap();
 
         assert!(pack.cli_version().unwrap().contains(&pack.release_version));
     }
+
+    #[test]
+    fn names_cache_volumes_like_pack() {
+        assert_eq!(
+            cache_volumes("my-app"),
+            [
+                "pack-cache-library_my-app_latest-cbd6ecf04229.build",
+                "pack-cache-library_my-app_latest-cbd6ecf04229.launch",
+            ]
+        );
+    }
+
+    #[test]
+    fn mirror_url_template() {
+        assert_eq!(
+            mirror_url(
+                "https://artifacts.internal/pack/{version}/{artifact}",
+                "0.27.0",
+                "https://github.com/buildpacks/pack/releases/download/v0.27.0/pack-v0.27.0-linux.tgz"
+            ),
+            "https://artifacts.internal/pack/0.27.0/pack-v0.27.0-linux.tgz"
+        );
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    #[tokio::test]
+    async fn explicit_binary_and_offline() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ToolCache::at(&dir.path().join("cache"));
+        let binary = |version: &str| {
+            let path = dir.path().join(format!("pack-{}", version));
+            std::fs::write(&path, format!("#!/bin/sh\necho {}\n", version)).unwrap();
+            set_permissions(File::open(&path).unwrap()).unwrap();
+            path
+        };
+
+        let source = PackSource {
+            binary: Some(binary(PACK_VERSION)),
+            offline: true,
+            ..Default::default()
+        };
+        let mut pack = Pack::builder(&cache).build();
+        let pack = pack
+            .install(&cache, &Network::default(), &source)
+            .await
+            .unwrap();
+        assert_eq!(
+            pack.bin_file_path,
+            source.binary.unwrap().canonicalize().unwrap()
+        );
+        assert!(pack.offline);
+
+        let source = PackSource {
+            binary: Some(binary("0.1.0")),
+            ..Default::default()
+        };
+        let mut pack = Pack::builder(&cache).build();
+        assert!(pack
+            .install(&cache, &Network::default(), &source)
+            .await
+            .is_err());
+
+        let source = PackSource {
+            mirror: Some("http://unreachable.invalid/{artifact}".into()),
+            offline: true,
+            ..Default::default()
+        };
+        let mut pack = Pack::builder(&cache).build();
+        pack.bin_file_path = dir.path().join("not-on-path-pack");
+        assert!(matches!(
+            pack.install(&cache, &Network::default(), &source).await,
+            Err(AppError::OfflineError(_))
+        ));
+    }
 }
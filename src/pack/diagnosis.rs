@@ -0,0 +1,150 @@
+use crate::error::AppError;
+use std::path::PathBuf;
+
+/// Known causes of failed builds, recognised from the build log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Failure {
+    DiskFull,
+    OutOfMemory,
+    RegistryAuth,
+    DependencyConflict,
+    MissingBuildScript,
+    WarningsAsErrors,
+    /// node version constraint the buildpack has no release for
+    UnsupportedNodeVersion(String),
+}
+
+impl Failure {
+    pub(crate) fn into_error(self, log: PathBuf) -> AppError {
+        match self {
+            Failure::DiskFull => AppError::DiskFull(log),
+            Failure::OutOfMemory => AppError::OutOfMemory(log),
+            Failure::RegistryAuth => AppError::RegistryAuth(log),
+            Failure::DependencyConflict => AppError::DependencyConflict(log),
+            Failure::MissingBuildScript => AppError::MissingBuildScript(log),
+            Failure::WarningsAsErrors => AppError::WarningsAsErrors(log),
+            Failure::UnsupportedNodeVersion(v) => AppError::UnsupportedNodeVersion(v, log),
+        }
+    }
+}
+
+/// Patterns per failure, checked in order: environment problems first, as they
+/// tend to cause follow-up errors that look like project problems
+const PATTERNS: [(&[&str], Failure); 6] = [
+    (&["no space left on device"], Failure::DiskFull),
+    (
+        &[
+            "JavaScript heap out of memory",
+            "Reached heap limit Allocation failed",
+        ],
+        Failure::OutOfMemory,
+    ),
+    (
+        &[
+            "npm ERR! code E401",
+            "npm ERR! code E403",
+            "Unable to authenticate",
+            "ERR_PNPM_FETCH_401",
+            "ERR_PNPM_FETCH_403",
+            "Request failed \"401 Unauthorized\"",
+            "Response Code: 401",
+        ],
+        Failure::RegistryAuth,
+    ),
+    (
+        &[
+            "npm ERR! code ERESOLVE",
+            "ERESOLVE unable to resolve dependency tree",
+            "ERR_PNPM_PEER_DEP_ISSUES",
+        ],
+        Failure::DependencyConflict,
+    ),
+    (
+        &[
+            "Missing script: \"build\"",
+            "missing script: build",
+            "Command \"build\" not found",
+            "ERR_PNPM_NO_SCRIPT",
+        ],
+        Failure::MissingBuildScript,
+    ),
+    (
+        &["Treating warnings as errors because process.env.CI = true"],
+        Failure::WarningsAsErrors,
+    ),
+];
+
+/// Node version constraint from the node-engine buildpack failing to resolve it, e.g.
+/// `failed to satisfy "node" dependency version constraint "^12.0.0": no compatible versions`
+fn node_version_constraint(line: &str) -> Option<String> {
+    if !line.contains("\"node\" dependency") {
+        return None;
+    }
+    line.split("version constraint \"")
+        .nth(1)
+        .and_then(|c| c.split('"').next())
+        .map(String::from)
+}
+
+/// Classifies a failed build from its log
+pub(crate) fn diagnose<S: AsRef<str>>(log: &[S]) -> Option<Failure> {
+    PATTERNS
+        .iter()
+        .find_map(|(patterns, failure)| {
+            log.iter()
+                .map(AsRef::as_ref)
+                .find(|l| patterns.iter().any(|p| l.contains(p)))
+                .map(|_| failure.clone())
+        })
+        .or_else(|| {
+            log.iter()
+                .find_map(|l| node_version_constraint(l.as_ref()))
+                .map(Failure::UnsupportedNodeVersion)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_build_logs() {
+        let cases: [(&[&str], Failure); 8] = [
+            (
+                &["[builder] npm ERR! code ERESOLVE", "npm ERR! While resolving: a@1.0.0"],
+                Failure::DependencyConflict,
+            ),
+            (
+                &["FATAL ERROR: Reached heap limit Allocation failed - JavaScript heap out of memory"],
+                Failure::OutOfMemory,
+            ),
+            (&["npm ERR! Missing script: \"build\""], Failure::MissingBuildScript),
+            (
+                &["", "Treating warnings as errors because process.env.CI = true.", "Most CI servers set it automatically."],
+                Failure::WarningsAsErrors,
+            ),
+            (
+                &["ERROR: failed to export: write /var/lib/docker/tmp/x: no space left on device"],
+                Failure::DiskFull,
+            ),
+            (
+                &["npm ERR! code E401", "npm ERR! Unable to authenticate, need: Basic realm=\"GitHub Package Registry\""],
+                Failure::RegistryAuth,
+            ),
+            (
+                &["failed to satisfy \"node\" dependency version constraint \"^12.0.0\": no compatible versions. Supported versions are: [14.20.0, 16.17.0]"],
+                Failure::UnsupportedNodeVersion("^12.0.0".to_string()),
+            ),
+            // the disk filling up is what made npm fail
+            (
+                &["npm ERR! code ERESOLVE", "ENOSPC: no space left on device"],
+                Failure::DiskFull,
+            ),
+        ];
+
+        for (log, failure) in cases {
+            assert_eq!(diagnose(log), Some(failure), "{:?}", log);
+        }
+        assert_eq!(diagnose(&["ERROR: failed to build: exit status 1"]), None);
+    }
+}
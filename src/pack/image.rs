@@ -1,37 +1,53 @@
-use super::Pack;
+use super::{diagnosis::diagnose, lifecycle::BuildProgress, Pack};
 use crate::log::error;
-use crate::log::{success, trying};
+use crate::log::{instruct, success};
+use crate::{
+    history::{BuildRecord, PhaseTiming},
+    package_json::Project,
+    runtime::Runtime,
+};
 use std::{
-    io::{BufRead, BufReader},
+    fs,
+    io::{BufRead, BufReader, Read},
     process::{Command, Stdio},
+    sync::mpsc,
+    thread,
 };
 use tracing::{debug, error};
 
 impl Pack {
     pub(crate) fn build_image(
         &self,
-        project_toml: &str,
-        start_cmd: &str,
-        proc_file_path: &str,
-        container_bindings_path: &str,
-        image_name: &str,
+        runtime: &Runtime,
+        project: &Project,
         clear_cache: bool,
+        record: &mut BuildRecord,
     ) -> anyhow::Result<()> {
+        let image_name = project.image_name.as_str();
+        // kept alive until the build finishes; secrets are removed on drop
+        let mounts = runtime.binding_mounts()?;
         let mut cmd = Command::new(self.bin_file_path.clone());
         let cmd = cmd
             .arg("build")
-            .args(["-d", project_toml])
-            .args(["-D", start_cmd])
-            .arg("--volume")
-            .arg(&format!(
-                r#"{}:{}"#,
-                proc_file_path, container_bindings_path
-            ))
-            .arg(image_name);
+            .arg("--path")
+            .arg(project.build_context())
+            .args(["-d", runtime.project_toml().as_str()])
+            .args(["-D", &runtime.proc_file().proc_default_command()]);
+
+        for mount in mounts.iter() {
+            cmd.arg("--volume").arg(mount.volume());
+        }
+        for env in runtime.network().build_env() {
+            cmd.arg("--env").arg(env);
+        }
+        cmd.arg(image_name);
 
         if clear_cache {
             cmd.arg("--clear-cache");
         }
+        if self.offline {
+            cmd.args(["--pull-policy", "never"]);
+        }
 
         debug!("Executing {:?}", cmd);
 
@@ -43,66 +59,91 @@ impl Pack {
         let stdout = child
             .stdout
             .take()
-            .ok_or("Cound not capture stdout")
-            .unwrap();
+            .ok_or_else(|| anyhow::anyhow!("Could not capture stdout"))?;
         let stderr = child
             .stderr
             .take()
-            .ok_or("Cound not capture stderr")
-            .unwrap();
-
-        let out_reader = BufReader::new(stdout);
-        let err_reader = BufReader::new(stderr);
-
-        let mut p_install_start_marker = false;
-
-        out_reader.lines().filter_map(|l| l.ok()).for_each(|l| {
-            let container_installing = l.contains("Pulling from paketobuildpacks/builder");
-            let container_installed =
-                l.contains("Status: Image is up to date for paketobuildpacks/run:base-cnb");
-
-            let packages_installing = l.contains("Executing build environment install process");
-            let completed = l.contains("Completed in");
-            let image_built = l.contains("Successfully built image");
-
-            if container_installing {
-                trying("Downloading docker images");
-            }
-            if container_installed {
-                success("Docker images downloaded");
-                trying("Setting up buildpacks");
-            }
+            .ok_or_else(|| anyhow::anyhow!("Could not capture stderr"))?;
 
-            if packages_installing {
-                success("Buildpacks setup done");
-                trying("Installing NPM packages");
-                p_install_start_marker = true;
-            }
-
-            if completed && p_install_start_marker {
-                p_install_start_marker = false;
-                success("Installed NPM packages");
-                trying("Finalizing image");
-            }
+        // both pipes are drained concurrently so that neither fills up and blocks pack
+        let (tx, rx) = mpsc::channel();
+        let readers = [
+            forward_lines(stdout, Stream::Stdout, tx.clone()),
+            forward_lines(stderr, Stream::Stderr, tx),
+        ];
 
-            if image_built {
-                success(&format!("Image {} built successfully", image_name));
+        let mut progress = BuildProgress::new();
+        let mut transcript = vec![];
+        for (stream, line) in rx {
+            match stream {
+                Stream::Stdout => {
+                    debug!("{}", line);
+                    progress.line(&line);
+                }
+                Stream::Stderr => error!("{}", line),
             }
-
-            debug!("{}", l);
+            transcript.push(line);
+        }
+        readers.into_iter().for_each(|r| {
+            let _ = r.join();
         });
-        err_reader.lines().for_each(|l| error!("{:?}", l));
+        progress.finish();
 
-        let out = child.wait_with_output()?;
-        if out.status.success() {
+        let status = child.wait()?;
+        let log = record.log_path();
+        if let Err(e) = fs::write(&log, transcript.join("\n")) {
+            debug!("Failed saving the build log to {:?} :: {}", log, e);
+        }
+        record.digest = progress.digest.clone();
+        record.phases = progress
+            .timings
+            .phases
+            .iter()
+            .map(|(phase, d)| PhaseTiming {
+                phase: phase.to_string(),
+                secs: d.as_secs_f64(),
+            })
+            .collect();
+        instruct(&format!("Build phases\n{}", progress.timings.summary()));
+        if status.success() {
+            if let Some(image) = progress.image.as_ref() {
+                success(&format!("Image {} built successfully", image));
+            }
             debug!("Auto packing project complete");
             success("Auto packing project complete");
         } else {
-            error!("Auto packing project failed");
-            error("Auto packing project complete");
-            anyhow::bail!("Failed auto packing project")
+            error!("Auto packing project failed during {}", progress.phase());
+            error(&format!(
+                "Auto packing project failed during {}",
+                progress.phase()
+            ));
+            if let Some(failure) = diagnose(&transcript) {
+                return Err(failure.into_error(log).into());
+            }
+            anyhow::bail!("Failed auto packing project, see {:?}", log)
         }
 
         Ok(())
     }
 }
+
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+fn forward_lines<R: Read + Send + 'static>(
+    reader: R,
+    stream: Stream,
+    tx: mpsc::Sender<(Stream, String)>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        BufReader::new(reader)
+            .lines()
+            .map_while(Result::ok)
+            .for_each(|l| {
+                let _ = tx.send((stream, l));
+            })
+    })
+}
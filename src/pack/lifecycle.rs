@@ -0,0 +1,258 @@
+#[cfg(test)]
+mod tests;
+
+use crate::log;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Phases of the CNB lifecycle as reported by `pack build`, plus the image pulls before them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Phase {
+    Preparing,
+    Analyzing,
+    Detecting,
+    Restoring,
+    Building,
+    Exporting,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::Preparing => "PREPARING",
+            Phase::Analyzing => "ANALYZING",
+            Phase::Detecting => "DETECTING",
+            Phase::Restoring => "RESTORING",
+            Phase::Building => "BUILDING",
+            Phase::Exporting => "EXPORTING",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Phase {
+    /// Parses the `===> DETECTING` phase markers
+    fn from_marker(line: &str) -> Option<Phase> {
+        match line.strip_prefix("===>")?.trim() {
+            "ANALYZING" => Some(Phase::Analyzing),
+            "DETECTING" => Some(Phase::Detecting),
+            "RESTORING" => Some(Phase::Restoring),
+            "BUILDING" => Some(Phase::Building),
+            "EXPORTING" => Some(Phase::Exporting),
+            _ => None,
+        }
+    }
+}
+
+/// What a line of `pack build` output means for the build progress
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Event {
+    Phase(Phase),
+    /// a buildpack or builder step starting within the current phase
+    Step(String),
+    ImageBuilt(String),
+    /// digest of a published image, or the id of one exported to the daemon
+    Digest(String),
+    Output,
+}
+
+/// Strips the `[detector] ` style prefixes pack adds when running the phases separately
+fn strip_prefix(line: &str) -> &str {
+    line.strip_prefix('[')
+        .and_then(|l| l.split_once("] "))
+        .filter(|(p, _)| !p.contains(' '))
+        .map(|(_, l)| l)
+        .unwrap_or(line)
+}
+
+fn ends_with_version(line: &str) -> bool {
+    line.rsplit(' ')
+        .next()
+        .map(|v| {
+            let v = v.trim_start_matches('v');
+            v.split('.').count() >= 2
+                && v.split('.')
+                    .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+        })
+        .unwrap_or(false)
+}
+
+/// Turns `pack build` output lines into [`Event`]s
+#[derive(Debug)]
+pub(crate) struct LifecycleParser {
+    phase: Phase,
+}
+
+impl Default for LifecycleParser {
+    fn default() -> Self {
+        LifecycleParser {
+            phase: Phase::Preparing,
+        }
+    }
+}
+
+impl LifecycleParser {
+    pub(crate) fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub(crate) fn parse(&mut self, line: &str) -> Event {
+        let line = strip_prefix(line.trim_end());
+        if let Some(phase) = Phase::from_marker(line) {
+            self.phase = phase;
+            return Event::Phase(phase);
+        }
+        if let Some(image) = line.strip_prefix("Successfully built image") {
+            return Event::ImageBuilt(image.trim().trim_matches('\'').to_string());
+        }
+        // `*** Digest: sha256:...` or `*** Images (1c2d3e4f5a6b):`
+        if let Some(digest) = line.strip_prefix("*** Digest: ").or_else(|| {
+            line.strip_prefix("*** Images (")
+                .and_then(|l| l.strip_suffix("):"))
+        }) {
+            return Event::Digest(digest.trim().to_string());
+        }
+
+        let indented = line.starts_with(char::is_whitespace);
+        match self.phase {
+            // e.g. `base: Pulling from paketobuildpacks/builder`
+            Phase::Preparing => line
+                .split_once(": Pulling from ")
+                .map(|(_, image)| Event::Step(format!("Pulling {}", image)))
+                .unwrap_or(Event::Output),
+            // e.g. `Paketo Buildpack for Node Engine 1.2.3`, or `-----> Installing binaries`
+            Phase::Building if !indented && ends_with_version(line) => {
+                Event::Step(line.to_string())
+            }
+            Phase::Building => line
+                .strip_prefix("-----> ")
+                .map(|s| Event::Step(s.to_string()))
+                .unwrap_or(Event::Output),
+            _ => Event::Output,
+        }
+    }
+}
+
+/// Wall time spent in each phase, in the order the phases ran
+#[derive(Debug, Default)]
+pub(crate) struct PhaseTimings {
+    current: Option<(Phase, Instant)>,
+    pub(crate) phases: Vec<(Phase, Duration)>,
+}
+
+impl PhaseTimings {
+    pub(crate) fn start(&mut self, phase: Phase, at: Instant) {
+        self.finish(at);
+        self.current = Some((phase, at));
+    }
+
+    pub(crate) fn finish(&mut self, at: Instant) {
+        if let Some((phase, started)) = self.current.take() {
+            self.phases
+                .push((phase, at.saturating_duration_since(started)));
+        }
+    }
+
+    pub(crate) fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, d)| *d).sum()
+    }
+
+    pub(crate) fn summary(&self) -> String {
+        self.phases
+            .iter()
+            .map(|(p, d)| format!("  {:<10} {:>8.1}s", p.to_string(), d.as_secs_f64()))
+            .chain(std::iter::once(format!(
+                "  {:<10} {:>8.1}s",
+                "TOTAL",
+                self.total().as_secs_f64()
+            )))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Spinner per lifecycle phase, finished with the phase's duration once the next one starts
+pub(crate) struct BuildProgress {
+    multi: MultiProgress,
+    bar: Option<ProgressBar>,
+    parser: LifecycleParser,
+    pub(crate) timings: PhaseTimings,
+    pub(crate) image: Option<String>,
+    pub(crate) digest: Option<String>,
+}
+
+impl BuildProgress {
+    pub(crate) fn new() -> BuildProgress {
+        let mut progress = BuildProgress {
+            multi: if !log::shows_progress() {
+                MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+            } else {
+                MultiProgress::new()
+            },
+            bar: None,
+            parser: LifecycleParser::default(),
+            timings: PhaseTimings::default(),
+            image: None,
+            digest: None,
+        };
+        progress.start(Phase::Preparing);
+        progress
+    }
+
+    fn start(&mut self, phase: Phase) {
+        self.finish_bar();
+        self.timings.start(phase, Instant::now());
+        log::progress(&phase.to_string(), None);
+
+        let bar = self.multi.add(ProgressBar::new_spinner());
+        bar.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.yellow} {prefix:.bold} {wide_msg:.dim}")
+                .unwrap(),
+        );
+        bar.set_prefix(phase.to_string());
+        bar.enable_steady_tick(Duration::from_millis(120));
+        self.bar = Some(bar);
+    }
+
+    fn finish_bar(&mut self) {
+        if let (Some(bar), Some((phase, started))) = (self.bar.take(), self.timings.current) {
+            bar.set_style(
+                ProgressStyle::default_spinner()
+                    .template("✔ {msg}")
+                    .unwrap(),
+            );
+            let secs = started.elapsed().as_secs_f64();
+            bar.finish_with_message(format!("{} ({:.1}s)", phase, secs));
+            log::progress(&phase.to_string(), Some(secs));
+        }
+    }
+
+    /// Feeds a line of `pack build` stdout
+    pub(crate) fn line(&mut self, line: &str) {
+        match self.parser.parse(line) {
+            Event::Phase(phase) => self.start(phase),
+            Event::Step(step) => {
+                if let Some(bar) = self.bar.as_ref() {
+                    bar.set_message(step);
+                }
+            }
+            Event::ImageBuilt(image) => self.image = Some(image),
+            Event::Digest(digest) => self.digest = Some(digest),
+            Event::Output => (),
+        }
+    }
+
+    /// Phase the build was in, e.g. when it failed
+    pub(crate) fn phase(&self) -> Phase {
+        self.parser.phase()
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.finish_bar();
+        self.timings.finish(Instant::now());
+    }
+}
@@ -0,0 +1,92 @@
+use super::{Event, LifecycleParser, Phase, PhaseTimings};
+use std::time::{Duration, Instant};
+
+fn events(transcript: &str) -> Vec<Event> {
+    let mut parser = LifecycleParser::default();
+    transcript
+        .lines()
+        .map(|l| parser.parse(l))
+        .filter(|e| *e != Event::Output)
+        .collect()
+}
+
+#[test]
+fn parses_creator_transcript() {
+    let events = events(include_str!("fixtures/creator.log"));
+
+    assert_eq!(
+        events,
+        vec![
+            Event::Step("Pulling paketobuildpacks/builder".into()),
+            Event::Step("Pulling paketobuildpacks/run".into()),
+            Event::Phase(Phase::Analyzing),
+            Event::Phase(Phase::Detecting),
+            Event::Phase(Phase::Restoring),
+            Event::Phase(Phase::Building),
+            Event::Step("Paketo Buildpack for CA Certificates 3.4.0".into()),
+            Event::Step("Paketo Buildpack for Node Engine 0.15.0".into()),
+            Event::Step("Paketo Buildpack for NPM Install 0.10.3".into()),
+            Event::Step("Paketo Buildpack for Procfile 5.4.0".into()),
+            Event::Phase(Phase::Exporting),
+            Event::Digest("1c2d3e4f5a6b".into()),
+            Event::ImageBuilt("my-app".into()),
+        ]
+    );
+}
+
+#[test]
+fn parses_prefixed_transcript_of_other_builders() {
+    let events = events(include_str!("fixtures/untrusted.log"));
+
+    assert_eq!(
+        events,
+        vec![
+            Event::Step("Pulling heroku/builder".into()),
+            Event::Phase(Phase::Detecting),
+            Event::Phase(Phase::Analyzing),
+            Event::Phase(Phase::Restoring),
+            Event::Phase(Phase::Building),
+            Event::Step("Installing binaries".into()),
+            Event::Step("Downloading and extracting Node".into()),
+            Event::Phase(Phase::Exporting),
+            Event::Digest("5a6b7c8d9e0f".into()),
+            Event::ImageBuilt("my-app".into()),
+        ]
+    );
+}
+
+#[test]
+fn tracks_the_failing_phase() {
+    let mut parser = LifecycleParser::default();
+    [
+        "===> DETECTING",
+        "===> BUILDING",
+        "[builder] npm ERR! code ERESOLVE",
+    ]
+    .iter()
+    .for_each(|l| {
+        parser.parse(l);
+    });
+    assert_eq!(parser.phase(), Phase::Building);
+}
+
+#[test]
+fn sums_phase_timings() {
+    let t0 = Instant::now();
+    let mut timings = PhaseTimings::default();
+    timings.start(Phase::Detecting, t0);
+    timings.start(Phase::Building, t0 + Duration::from_secs(2));
+    timings.finish(t0 + Duration::from_millis(9500));
+
+    assert_eq!(
+        timings.phases,
+        vec![
+            (Phase::Detecting, Duration::from_secs(2)),
+            (Phase::Building, Duration::from_millis(7500)),
+        ]
+    );
+    assert_eq!(timings.total(), Duration::from_millis(9500));
+    let summary = timings.summary();
+    assert!(summary.contains("BUILDING        7.5s"));
+    assert!(summary.ends_with("TOTAL           9.5s"));
+}
@@ -0,0 +1,264 @@
+use super::version::PackVersion;
+use std::{env::consts, fmt, path::Path};
+
+const RELEASES_URL: &str = "https://github.com/buildpacks/pack/releases/download";
+
+/// Archive format of a pack cli release
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArchiveFormat {
+    Tgz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tgz => "tgz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    /// Format of a local archive, going by its file name
+    pub(crate) fn from_file_name(path: &Path) -> anyhow::Result<ArchiveFormat> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.ends_with(".tgz") || name.ends_with(".tar.gz") {
+            Ok(ArchiveFormat::Tgz)
+        } else if name.ends_with(".zip") {
+            Ok(ArchiveFormat::Zip)
+        } else {
+            anyhow::bail!("Unknown pack cli archive format of {:?}", path)
+        }
+    }
+}
+
+/// Operating system and architecture, named as in [`std::env::consts`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Target {
+    pub(crate) os: String,
+    pub(crate) arch: String,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.os, self.arch)
+    }
+}
+
+impl Target {
+    pub(crate) fn host() -> Target {
+        Target {
+            os: consts::OS.to_string(),
+            arch: consts::ARCH.to_string(),
+        }
+    }
+
+    #[cfg(test)]
+    /// Parses a rust target triple, e.g. `aarch64-apple-darwin` or `x86_64-unknown-linux-gnu`
+    pub(crate) fn from_triple(triple: &str) -> anyhow::Result<Target> {
+        let parts = triple.split('-').collect::<Vec<_>>();
+        let arch = parts
+            .first()
+            .filter(|a| !a.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Invalid target triple {}", triple))?;
+        let os = match parts.get(1..).unwrap_or_default() {
+            p if p.contains(&"darwin") => "macos",
+            p if p.contains(&"linux") => "linux",
+            p if p.contains(&"windows") => "windows",
+            _ => anyhow::bail!("Unsupported target triple {}", triple),
+        };
+        Ok(Target {
+            os: os.to_string(),
+            arch: arch.to_string(),
+        })
+    }
+
+    pub(crate) fn bin_name(&self) -> &'static str {
+        match self.os.as_str() {
+            "windows" => "pack.exe",
+            _ => "pack",
+        }
+    }
+}
+
+/// A row of the release table
+struct Platform {
+    os: &'static str,
+    arch: &'static str,
+    /// first pack version published for the platform
+    since: PackVersion,
+    /// artifact name suffix, e.g. `linux-arm64` in `pack-v0.27.0-linux-arm64.tgz`
+    suffix: &'static str,
+    format: ArchiveFormat,
+}
+
+const fn platform(
+    os: &'static str,
+    arch: &'static str,
+    since: PackVersion,
+    suffix: &'static str,
+    format: ArchiveFormat,
+) -> Platform {
+    Platform {
+        os,
+        arch,
+        since,
+        suffix,
+        format,
+    }
+}
+
+const RELEASES: [Platform; 6] = [
+    platform(
+        "linux",
+        "x86_64",
+        PackVersion(0, 0, 0),
+        "linux",
+        ArchiveFormat::Tgz,
+    ),
+    platform(
+        "linux",
+        "aarch64",
+        PackVersion(0, 24, 0),
+        "linux-arm64",
+        ArchiveFormat::Tgz,
+    ),
+    platform(
+        "linux",
+        "s390x",
+        PackVersion(0, 33, 0),
+        "linux-s390x",
+        ArchiveFormat::Tgz,
+    ),
+    platform(
+        "macos",
+        "x86_64",
+        PackVersion(0, 0, 0),
+        "macos",
+        ArchiveFormat::Tgz,
+    ),
+    platform(
+        "macos",
+        "aarch64",
+        PackVersion(0, 22, 0),
+        "macos-arm64",
+        ArchiveFormat::Tgz,
+    ),
+    platform(
+        "windows",
+        "x86_64",
+        PackVersion(0, 0, 0),
+        "windows",
+        ArchiveFormat::Zip,
+    ),
+];
+
+/// A pack cli release artifact on GitHub
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Release {
+    /// file name, e.g. `pack-v0.27.0-linux-arm64.tgz`
+    pub(crate) artifact: String,
+    pub(crate) format: ArchiveFormat,
+    pub(crate) url: String,
+}
+
+impl Release {
+    /// Resolves the artifact of pack cli `version` for `target`
+    pub(crate) fn resolve(target: &Target, version: &str) -> anyhow::Result<Release> {
+        let parsed = version.parse::<PackVersion>()?;
+        let Platform {
+            since,
+            suffix,
+            format,
+            ..
+        } = RELEASES
+            .iter()
+            .find(|p| p.os == target.os && p.arch == target.arch)
+            .ok_or_else(|| anyhow::anyhow!("pack cli has no release for {}", target))?;
+        if parsed < *since {
+            anyhow::bail!(
+                "pack cli {} has no release for {}; it is available from {}",
+                version,
+                target,
+                since
+            );
+        }
+
+        let version = version.trim_start_matches('v');
+        let artifact = format!("pack-v{}-{}.{}", version, suffix, format.extension());
+        Ok(Release {
+            url: format!("{}/v{}/{}", RELEASES_URL, version, artifact),
+            artifact,
+            format: *format,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(triple: &str, version: &str) -> anyhow::Result<Release> {
+        Release::resolve(&Target::from_triple(triple)?, version)
+    }
+
+    #[test]
+    fn resolves_artifacts_per_target() {
+        let cases = [
+            ("x86_64-unknown-linux-gnu", "pack-v0.27.0-linux.tgz"),
+            ("x86_64-unknown-linux-musl", "pack-v0.27.0-linux.tgz"),
+            ("aarch64-unknown-linux-gnu", "pack-v0.27.0-linux-arm64.tgz"),
+            ("x86_64-apple-darwin", "pack-v0.27.0-macos.tgz"),
+            ("aarch64-apple-darwin", "pack-v0.27.0-macos-arm64.tgz"),
+            ("x86_64-pc-windows-msvc", "pack-v0.27.0-windows.zip"),
+        ];
+        for (triple, artifact) in cases {
+            let release = resolve(triple, "0.27.0").unwrap();
+            assert_eq!(release.artifact, artifact, "{}", triple);
+        }
+
+        let release = resolve("aarch64-unknown-linux-gnu", "v0.27.0").unwrap();
+        assert_eq!(
+            release.url,
+            "https://github.com/buildpacks/pack/releases/download/v0.27.0/pack-v0.27.0-linux-arm64.tgz"
+        );
+        assert_eq!(
+            resolve("x86_64-pc-windows-gnu", "0.27.0").unwrap().format,
+            ArchiveFormat::Zip
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_targets_and_versions() {
+        assert!(resolve("armv7-unknown-linux-gnueabihf", "0.27.0").is_err());
+        assert!(resolve("aarch64-pc-windows-msvc", "0.27.0").is_err());
+        assert!(resolve("s390x-unknown-linux-gnu", "0.27.0").is_err());
+        assert!(resolve("s390x-unknown-linux-gnu", "0.33.0").is_ok());
+        assert!(resolve("x86_64-unknown-freebsd", "0.27.0").is_err());
+        assert!(resolve("x86_64-unknown-linux-gnu", "latest").is_err());
+    }
+
+    #[test]
+    fn archive_format_from_file_name() {
+        let format = |n: &str| ArchiveFormat::from_file_name(Path::new(n));
+        assert_eq!(
+            format("/tmp/pack-v0.27.0-linux.tgz").unwrap(),
+            ArchiveFormat::Tgz
+        );
+        assert_eq!(format("pack.tar.gz").unwrap(), ArchiveFormat::Tgz);
+        assert_eq!(
+            format("pack-v0.27.0-windows.ZIP").unwrap(),
+            ArchiveFormat::Zip
+        );
+        assert!(format("pack").is_err());
+    }
+
+    #[test]
+    fn host_bin_name() {
+        let target = Target::from_triple("x86_64-pc-windows-msvc").unwrap();
+        assert_eq!(target.bin_name(), "pack.exe");
+        assert_eq!(target.to_string(), "windows-x86_64");
+    }
+}
@@ -0,0 +1,117 @@
+use std::{fmt, str::FromStr};
+
+/// Semantic version of the pack cli, ignoring build metadata
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct PackVersion(pub(crate) u32, pub(crate) u32, pub(crate) u32);
+
+impl fmt::Display for PackVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+impl FromStr for PackVersion {
+    type Err = anyhow::Error;
+
+    /// Parses `0.27.0`, `v0.27.0` as well as `pack --version` output such as
+    /// `0.27.0+git-f4f5be1.build-3382`
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let parts = version
+            .trim()
+            .trim_start_matches('v')
+            .split(['+', '-', ' '])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(str::parse::<u32>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("Invalid pack version {:?} :: {}", version, e))?;
+        match parts[..] {
+            [major, minor, patch] => Ok(PackVersion(major, minor, patch)),
+            _ => anyhow::bail!("Invalid pack version {:?}", version),
+        }
+    }
+}
+
+/// Oldest pack cli autopack supports
+pub(crate) const MIN_PACK_VERSION: PackVersion = PackVersion(0, 24, 0);
+
+/// First pack cli release autopack doesn't support yet
+pub(crate) const MAX_PACK_VERSION: PackVersion = PackVersion(1, 0, 0);
+
+/// pack cli features autopack relies on, with the release introducing them
+const FEATURES: [(&str, PackVersion); 5] = [
+    ("`--volume` build mounts", PackVersion(0, 13, 0)),
+    ("`--pull-policy`", PackVersion(0, 14, 0)),
+    ("`-D`/`--default-process`", PackVersion(0, 15, 0)),
+    ("inline buildpacks in project.toml", PackVersion(0, 19, 0)),
+    ("project descriptor schema 0.1", PackVersion(0, 9, 0)),
+];
+
+impl PackVersion {
+    /// Checks that the version is within the supported range, listing the features
+    /// autopack relies on which it lacks
+    pub(crate) fn check_supported(&self) -> anyhow::Result<()> {
+        if *self >= MAX_PACK_VERSION {
+            anyhow::bail!(
+                "pack cli {} is not supported yet; use a version below {}",
+                self,
+                MAX_PACK_VERSION
+            );
+        }
+        if *self < MIN_PACK_VERSION {
+            let missing = FEATURES
+                .iter()
+                .filter(|(_, since)| self < since)
+                .map(|(feature, since)| format!("{} (from {})", feature, since))
+                .collect::<Vec<_>>();
+            anyhow::bail!(
+                "pack cli {} is older than the minimum supported {}{}",
+                self,
+                MIN_PACK_VERSION,
+                if missing.is_empty() {
+                    String::new()
+                } else {
+                    format!("; it lacks {}", missing.join(", "))
+                }
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cli_output() {
+        assert_eq!(
+            "0.27.0+git-f4f5be1.build-3382\n"
+                .parse::<PackVersion>()
+                .unwrap(),
+            PackVersion(0, 27, 0)
+        );
+        assert_eq!(
+            "v0.30.1".parse::<PackVersion>().unwrap(),
+            PackVersion(0, 30, 1)
+        );
+        assert!("latest".parse::<PackVersion>().is_err());
+        assert!("0.27".parse::<PackVersion>().is_err());
+    }
+
+    #[test]
+    fn checks_supported_range() {
+        assert!(PackVersion(0, 27, 0).check_supported().is_ok());
+        assert!(MIN_PACK_VERSION.check_supported().is_ok());
+        assert!(PackVersion(1, 0, 0).check_supported().is_err());
+
+        let err = PackVersion(0, 14, 2)
+            .check_supported()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("-D`/`--default-process` (from 0.15.0)"));
+        assert!(err.contains("inline buildpacks"));
+        assert!(!err.contains("--volume"));
+    }
+}
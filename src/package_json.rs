@@ -1,6 +1,9 @@
 use crate::{
     error::AppError,
     log::{error, success, trying},
+    package_manager::PackageManagerInfo,
+    runtime::Relocation,
+    workspace::{Workspace, WorkspaceApp},
 };
 use npm_package_json::Package;
 use serde::{Deserialize, Serialize};
@@ -35,53 +38,341 @@ fn check_package_json(mod_path: &Path) -> Result<String, AppError> {
 fn get_pkg_json(path: &str) -> Result<Package, AppError> {
     Package::from_path(path).map_err(|e| match e {
         npm_package_json::Error::Io(e) => AppError::IOError("Failed to parse package.json", e),
-        npm_package_json::Error::Parse(_) => AppError::ReactScriptsNotFound,
+        npm_package_json::Error::Parse(e) => {
+            AppError::ReactScriptsNotFound(format!("package.json could not be parsed :: {}", e))
+        }
     })
 }
 
-fn check_react_script(package_json_path: &str) -> Result<Package, AppError> {
+/// Flavours of Create React App projects which all produce the `build/` output
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Default, PartialEq, Eq)]
+pub(crate) enum CraFlavour {
+    #[default]
+    ReactScripts,
+    Craco,
+    ReactAppRewired,
+    /// `npm run eject`ed project with the webpack config under `config/`
+    Ejected,
+}
+
+impl CraFlavour {
+    const ALL: [CraFlavour; 4] = [
+        CraFlavour::Craco,
+        CraFlavour::ReactAppRewired,
+        CraFlavour::ReactScripts,
+        CraFlavour::Ejected,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            CraFlavour::ReactScripts => "react-scripts",
+            CraFlavour::Craco => "@craco/craco",
+            CraFlavour::ReactAppRewired => "react-app-rewired",
+            CraFlavour::Ejected => "ejected CRA",
+        }
+    }
+
+    /// What the `build` script is expected to invoke
+    fn build_command(&self) -> &'static str {
+        match self {
+            CraFlavour::ReactScripts => "react-scripts build",
+            CraFlavour::Craco => "craco build",
+            CraFlavour::ReactAppRewired => "react-app-rewired build",
+            CraFlavour::Ejected => "scripts/build.js",
+        }
+    }
+
+    fn is_present(&self, pkg: &Package, project_dir: &Path) -> bool {
+        match self {
+            CraFlavour::Ejected => {
+                project_dir.join("scripts").join("build.js").exists()
+                    && ["webpack.config.js", "webpack.config.ts"]
+                        .iter()
+                        .any(|f| project_dir.join("config").join(f).exists())
+            }
+            _ => {
+                pkg.dependencies.contains_key(self.name())
+                    || pkg.dev_dependencies.contains_key(self.name())
+            }
+        }
+    }
+}
+
+fn detection_failure(pkg: &Package, project_dir: &Path) -> String {
+    let present = CraFlavour::ALL
+        .iter()
+        .filter(|f| f.is_present(pkg, project_dir))
+        .map(|f| f.name())
+        .collect::<Vec<_>>();
+    let expected = CraFlavour::ALL
+        .iter()
+        .map(|f| format!("`{}`", f.build_command()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match pkg.scripts.get("build") {
+        None => {
+            "package.json has no `build` script; autopack runs it to produce the `build/` folder"
+                .to_string()
+        }
+        Some(build) if present.is_empty() => format!(
+            "looked for a `react-scripts`, `@craco/craco` or `react-app-rewired` dependency, \
+            or an ejected CRA (`config/webpack.config.js` and `scripts/build.js`), but found none. \
+            The `build` script runs `{}`",
+            build
+        ),
+        Some(build) => format!(
+            "found {} but the `build` script runs `{}` which invokes none of {}",
+            present.join(", "),
+            build,
+            expected
+        ),
+    }
+}
+
+fn check_react_script(package_json_path: &str) -> Result<(Package, CraFlavour), AppError> {
     trying("Checking react-scripts within the project");
     let pkg = get_pkg_json(package_json_path)?;
+    let project_dir = Path::new(package_json_path)
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
 
-    if pkg.dev_dependencies.contains_key("react-scripts")
-        || pkg.dependencies.contains_key("react-scripts")
-    {
-        success("react-scripts found");
-        Ok(pkg)
-    } else {
-        error("react-scripts not found");
-        Err(AppError::ReactScriptsNotFound)
+    let build_script = pkg.scripts.get("build").cloned().unwrap_or_default();
+    let flavour = CraFlavour::ALL
+        .into_iter()
+        .find(|f| f.is_present(&pkg, project_dir) && build_script.contains(f.build_command()));
+
+    match flavour {
+        Some(flavour) => {
+            info!(message = "CRA project detected", flavour = flavour.name(), build = ?build_script);
+            success(&format!("{} found", flavour.name()));
+            Ok((pkg, flavour))
+        }
+        None => {
+            let reason = detection_failure(&pkg, project_dir);
+            error(&format!("Create React App not detected :: {}", reason));
+            Err(AppError::ReactScriptsNotFound(reason))
+        }
     }
 }
 
+/// Docker image names can't contain npm scope markers, so `@acme/web app` becomes `acme-web_app`
+fn image_name(package_name: &str) -> String {
+    package_name
+        .trim()
+        .trim_start_matches('@')
+        .replace(' ', "_")
+        .replace('/', "-")
+        .to_lowercase()
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq)]
 pub(crate) struct Project {
     pub(crate) path: PathBuf,
     pub(crate) package_json: Package,
     pub(crate) image_name: String,
+    #[serde(default)]
+    pub(crate) flavour: CraFlavour,
+    /// set when the project is an app within a monorepo
+    #[serde(default)]
+    pub(crate) workspace: Option<WorkspaceApp>,
+    #[serde(default)]
+    pub(crate) package_manager: PackageManagerInfo,
+}
+
+impl Project {
+    /// Directory sent to pack as the build context
+    pub(crate) fn build_context(&self) -> PathBuf {
+        self.workspace
+            .as_ref()
+            .map(|w| w.root.clone())
+            .unwrap_or_else(|| self.path.clone())
+    }
+
+    pub(crate) fn relocate(&mut self, paths: &Relocation) {
+        self.path = paths.project(&self.path);
+        if let Some(ws) = self.workspace.as_mut() {
+            ws.root = paths.project(&ws.root);
+        }
+    }
+
+    /// App directory inside the image
+    pub(crate) fn container_app_dir(&self) -> String {
+        match self.workspace.as_ref() {
+            Some(ws) => format!("/workspace/{}", ws.container_app_path()),
+            None => "/workspace".to_string(),
+        }
+    }
+
+    /// Script starting the CRA dev server
+    pub(crate) fn dev_script(&self) -> Option<&'static str> {
+        Some("start").filter(|s| self.package_json.scripts.contains_key(*s))
+    }
 }
 
 pub(crate) struct CreatePackageJson {
     path: PathBuf,
+    app: Option<String>,
 }
 
 impl CreatePackageJson {
     pub(crate) fn new(p: &Path) -> CreatePackageJson {
         CreatePackageJson {
             path: p.to_path_buf(),
+            app: None,
+        }
+    }
+
+    /// Name of the app to pick when the project is a monorepo
+    pub(crate) fn app(&mut self, app: Option<String>) -> &mut Self {
+        self.app = app;
+        self
+    }
+
+    fn locate_workspace_app(&self) -> Result<Option<(PathBuf, WorkspaceApp)>, AppError> {
+        let workspace = Workspace::detect(&self.path)
+            .map_err(|e| AppError::PreconfigureError("Failed detecting workspace", e))?;
+
+        match workspace {
+            None if self.app.is_some() => Err(AppError::WorkspaceNotFound(self.path.clone())),
+            None => Ok(None),
+            Some(ws) => {
+                trying("Locating app within the workspace");
+                let target = match ws.target(&self.path, self.app.as_deref()) {
+                    Ok(t) => t,
+                    // not within any workspace package (e.g. the root itself); treat it as a standalone project
+                    Err(_) if self.app.is_none() => return Ok(None),
+                    Err(e) => return Err(e),
+                };
+                info!(message = "Workspace app found", app = ?target.name, root = ?ws.root);
+                success(&format!("Found app {} in the workspace", target.name));
+                Ok(Some((ws.root.join(&target.path), ws.app(target))))
+            }
         }
     }
 
     pub(crate) fn build(&self) -> Result<Project, AppError> {
-        check_package_json(&self.path)
-            .map(|s| check_react_script(&s))?
-            .map(|p| {
-                let image_name = p.name.trim().replace(' ', "_");
-                Project {
-                    package_json: p,
-                    path: self.path.clone(),
-                    image_name,
-                }
-            })
+        let (path, workspace) = match self.locate_workspace_app()? {
+            Some((path, app)) => (path, Some(app)),
+            None => (self.path.clone(), None),
+        };
+
+        let (package_json, flavour) =
+            check_package_json(&path).map(|s| check_react_script(&s))??;
+
+        // in a monorepo the package manager is pinned and locked at the workspace root
+        let package_manager = match workspace.as_ref() {
+            Some(ws) => get_pkg_json(&ws.root.join("package.json").to_string_lossy())
+                .map(|root| PackageManagerInfo::detect(&root, &ws.root))?,
+            None => PackageManagerInfo::detect(&package_json, &path),
+        };
+
+        Ok(Project {
+            image_name: image_name(&package_json.name),
+            package_json,
+            flavour,
+            path,
+            workspace,
+            package_manager,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::{Builder, TempDir};
+
+    /// Temporary project with the given `package.json` and extra files (relative path, contents)
+    pub(crate) fn project_with(package_json: &str, files: &[(&str, &str)]) -> TempDir {
+        let dir = Builder::new().tempdir().expect("failed creating temp dir");
+        fs::write(dir.path().join("package.json"), package_json).expect("failed writing");
+        files.iter().for_each(|(f, c)| {
+            let p = dir.path().join(f);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(p, c).unwrap();
+        });
+        dir
+    }
+
+    fn project(package_json: &str) -> TempDir {
+        project_with(package_json, &[])
+    }
+
+    fn check(dir: &Path) -> Result<CraFlavour, AppError> {
+        check_react_script(dir.join("package.json").to_str().unwrap()).map(|(_, f)| f)
+    }
+
+    #[test]
+    fn detects_craco_and_rewired() {
+        let dir = project(
+            r#"{"name": "a", "version": "1.0.0",
+                "scripts": {"build": "craco build"},
+                "dependencies": {"react-scripts": "5.0.1", "@craco/craco": "7.0.0"}}"#,
+        );
+        assert_eq!(check(dir.path()).unwrap(), CraFlavour::Craco);
+
+        let dir = project(
+            r#"{"name": "a", "version": "1.0.0",
+                "scripts": {"build": "react-app-rewired build"},
+                "devDependencies": {"react-app-rewired": "2.2.1"}}"#,
+        );
+        assert_eq!(check(dir.path()).unwrap(), CraFlavour::ReactAppRewired);
+    }
+
+    #[test]
+    fn detects_ejected_cra() {
+        let dir = project(
+            r#"{"name": "a", "version": "1.0.0", "scripts": {"build": "node scripts/build.js"}}"#,
+        );
+        fs::create_dir_all(dir.path().join("config")).unwrap();
+        fs::create_dir_all(dir.path().join("scripts")).unwrap();
+        fs::write(dir.path().join("config/webpack.config.js"), "").unwrap();
+        fs::write(dir.path().join("scripts/build.js"), "").unwrap();
+
+        assert_eq!(check(dir.path()).unwrap(), CraFlavour::Ejected);
+    }
+
+    #[test]
+    fn explains_detection_failures() {
+        let dir = project(
+            r#"{"name": "a", "version": "1.0.0", "dependencies": {"react-scripts": "5.0.1"}}"#,
+        );
+        assert!(matches!(
+            check(dir.path()),
+            Err(AppError::ReactScriptsNotFound(m)) if m.contains("no `build` script")
+        ));
+
+        let dir = project(
+            r#"{"name": "a", "version": "1.0.0", "scripts": {"build": "vite build"},
+                "dependencies": {"react-scripts": "5.0.1"}}"#,
+        );
+        assert!(matches!(
+            check(dir.path()),
+            Err(AppError::ReactScriptsNotFound(m)) if m.contains("found react-scripts")
+        ));
+    }
+
+    #[test]
+    fn finds_the_dev_server_script() {
+        let with_scripts = |scripts: &str| Project {
+            package_json: serde_json::from_str(&format!(
+                r#"{{"name": "a", "version": "1.0.0", "scripts": {}}}"#,
+                scripts
+            ))
+            .unwrap(),
+            ..Default::default()
+        };
+
+        let cra =
+            with_scripts(r#"{"start": "react-scripts start", "build": "react-scripts build"}"#);
+        assert_eq!(cra.dev_script(), Some("start"));
+
+        assert_eq!(
+            with_scripts(r#"{"build": "craco build"}"#).dev_script(),
+            None
+        );
+        assert_eq!(cra.container_app_dir(), "/workspace");
     }
 }
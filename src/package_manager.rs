@@ -0,0 +1,279 @@
+use crate::log::{success, trying, warn};
+use npm_package_json::Package;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+use tracing::debug;
+
+pub(crate) const LOCKFILES: [&str; 4] = [
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+];
+
+/// Package manager used to install the project's dependencies
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub(crate) enum PackageManager {
+    #[default]
+    Npm,
+    YarnClassic,
+    /// Yarn 2+; `pnp` is set when dependencies are resolved through Plug'n'Play
+    /// and `zero_install` when the offline cache is committed under `.yarn/cache`
+    YarnBerry {
+        pnp: bool,
+        zero_install: bool,
+    },
+    Pnpm,
+}
+
+impl PackageManager {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            PackageManager::Npm => "npm",
+            PackageManager::YarnClassic => "yarn (classic)",
+            PackageManager::YarnBerry { .. } => "yarn (berry)",
+            PackageManager::Pnpm => "pnpm",
+        }
+    }
+
+    /// Lockfiles this package manager reads
+    pub(crate) fn lockfiles(&self) -> &'static [&'static str] {
+        match self {
+            PackageManager::Npm => &LOCKFILES[..2],
+            PackageManager::YarnClassic | PackageManager::YarnBerry { .. } => &LOCKFILES[2..3],
+            PackageManager::Pnpm => &LOCKFILES[3..],
+        }
+    }
+
+    /// Package managers the Paketo Node.js buildpack doesn't install with, which are
+    /// instead provisioned through corepack
+    pub(crate) fn needs_corepack(&self) -> bool {
+        matches!(
+            self,
+            PackageManager::Pnpm | PackageManager::YarnBerry { .. }
+        )
+    }
+
+    /// Frozen-lockfile install followed by the `build` script
+    pub(crate) fn install_and_build(&self) -> (&'static str, &'static str) {
+        match self {
+            PackageManager::Npm => ("npm ci", "npm run build"),
+            PackageManager::YarnClassic => ("yarn install --frozen-lockfile", "yarn run build"),
+            PackageManager::YarnBerry {
+                zero_install: true, ..
+            } => (
+                "yarn install --immutable --immutable-cache",
+                "yarn run build",
+            ),
+            PackageManager::YarnBerry { .. } => ("yarn install --immutable", "yarn run build"),
+            PackageManager::Pnpm => ("pnpm install --frozen-lockfile", "pnpm run build"),
+        }
+    }
+}
+
+/// Outcome of the package manager detection
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub(crate) struct PackageManagerInfo {
+    pub(crate) manager: PackageManager,
+    /// version pinned through the `packageManager` field of package.json
+    pub(crate) version: Option<String>,
+    /// lockfile used for the install, relative to the build context
+    pub(crate) lockfile: Option<String>,
+}
+
+fn is_berry_lockfile(dir: &Path) -> bool {
+    dir.join(".yarnrc.yml").exists()
+        || fs::read_to_string(dir.join("yarn.lock"))
+            .map(|l| l.contains("__metadata:"))
+            .unwrap_or(false)
+}
+
+fn yarn_berry(dir: &Path) -> PackageManager {
+    let node_modules_linker = fs::read_to_string(dir.join(".yarnrc.yml"))
+        .map(|rc| {
+            rc.lines()
+                .map(|l| l.split('#').next().unwrap_or_default().replace(' ', ""))
+                .any(|l| l == "nodeLinker:node-modules" || l == "nodeLinker:\"node-modules\"")
+        })
+        .unwrap_or(false);
+    PackageManager::YarnBerry {
+        pnp: !node_modules_linker,
+        zero_install: dir.join(".yarn").join("cache").is_dir(),
+    }
+}
+
+/// Parses the corepack `packageManager` field, e.g. `pnpm@8.6.0+sha256.abc`
+fn from_package_manager_field(field: &str, dir: &Path) -> Option<(PackageManager, String)> {
+    let (name, version) = field.split_once('@')?;
+    let version = version.split('+').next().unwrap_or(version).to_string();
+    let major = version.split('.').next()?.parse::<u32>().ok()?;
+    let manager = match name {
+        "npm" => PackageManager::Npm,
+        "pnpm" => PackageManager::Pnpm,
+        "yarn" if major < 2 => PackageManager::YarnClassic,
+        "yarn" => yarn_berry(dir),
+        _ => return None,
+    };
+    Some((manager, version))
+}
+
+fn from_lockfile(lockfile: &str, dir: &Path) -> PackageManager {
+    match lockfile {
+        "yarn.lock" if is_berry_lockfile(dir) => yarn_berry(dir),
+        "yarn.lock" => PackageManager::YarnClassic,
+        "pnpm-lock.yaml" => PackageManager::Pnpm,
+        _ => PackageManager::Npm,
+    }
+}
+
+impl PackageManagerInfo {
+    /// Detects the package manager from the `packageManager` field of `pkg` and the lockfiles in `dir`.
+    ///
+    /// `dir` is the build context, i.e. the workspace root for apps within a monorepo.
+    pub(crate) fn detect(pkg: &Package, dir: &Path) -> PackageManagerInfo {
+        trying("Detecting package manager");
+        let lockfiles = LOCKFILES
+            .iter()
+            .filter(|l| dir.join(l).exists())
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>();
+        let declared = pkg
+            .others
+            .get("packageManager")
+            .and_then(|v| v.as_str())
+            .and_then(|f| from_package_manager_field(f, dir));
+
+        let info = match (declared, lockfiles.first()) {
+            (Some((manager, version)), _) => {
+                let lockfile = lockfiles
+                    .iter()
+                    .find(|l| manager.lockfiles().contains(&l.as_str()))
+                    .cloned();
+                if lockfile.is_none() && !lockfiles.is_empty() {
+                    warn(&format!(
+                        "package.json declares {} but only {} found; it is left out of the build",
+                        manager.name(),
+                        lockfiles.join(", ")
+                    ));
+                }
+                PackageManagerInfo {
+                    manager,
+                    version: Some(version),
+                    lockfile,
+                }
+            }
+            (None, Some(lockfile)) => PackageManagerInfo {
+                manager: from_lockfile(lockfile, dir),
+                version: None,
+                lockfile: Some(lockfile.clone()),
+            },
+            (None, None) => PackageManagerInfo::default(),
+        };
+
+        let conflicting = lockfiles
+            .iter()
+            .filter(|l| !info.manager.lockfiles().contains(&l.as_str()))
+            .collect::<Vec<_>>();
+        if !conflicting.is_empty() && info.lockfile.is_some() {
+            debug!("Conflicting lockfiles {:?}", conflicting);
+            warn(&format!(
+                "Conflicting lockfiles found; using {} and leaving out {:?} from the build",
+                info.lockfile.as_deref().unwrap_or_default(),
+                conflicting
+            ));
+        }
+        if info.lockfile.is_none() {
+            warn(&format!(
+                "No lockfile found for {}; installs inside the container won't be reproducible",
+                info.manager.name()
+            ));
+        }
+
+        debug!("Detected package manager {:?}", info);
+        success(&format!("Using {}", info.manager.name()));
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package_json::tests::project_with;
+    use tempfile::TempDir;
+
+    fn project(package_json: &str, files: &[(&str, &str)]) -> (TempDir, Package) {
+        let pkg = Package::from_slice(package_json.as_bytes()).expect("invalid package.json");
+        (project_with(package_json, files), pkg)
+    }
+
+    #[test]
+    fn detects_from_lockfiles() {
+        let (dir, pkg) = project(
+            r#"{"name": "a", "version": "1.0.0"}"#,
+            &[("yarn.lock", "# yarn lockfile v1\n")],
+        );
+        let info = PackageManagerInfo::detect(&pkg, dir.path());
+        assert_eq!(info.manager, PackageManager::YarnClassic);
+        assert_eq!(info.lockfile.as_deref(), Some("yarn.lock"));
+
+        let (dir, pkg) = project(
+            r#"{"name": "a", "version": "1.0.0"}"#,
+            &[("pnpm-lock.yaml", "")],
+        );
+        assert_eq!(
+            PackageManagerInfo::detect(&pkg, dir.path()).manager,
+            PackageManager::Pnpm
+        );
+
+        let (dir, pkg) = project(r#"{"name": "a", "version": "1.0.0"}"#, &[]);
+        let info = PackageManagerInfo::detect(&pkg, dir.path());
+        assert_eq!(info.manager, PackageManager::Npm);
+        assert_eq!(info.lockfile, None);
+    }
+
+    #[test]
+    fn package_manager_field_wins_over_lockfiles() {
+        let (dir, pkg) = project(
+            r#"{"name": "a", "version": "1.0.0", "packageManager": "pnpm@8.6.0+sha256.1234"}"#,
+            &[("package-lock.json", "{}"), ("pnpm-lock.yaml", "")],
+        );
+        let info = PackageManagerInfo::detect(&pkg, dir.path());
+        assert_eq!(info.manager, PackageManager::Pnpm);
+        assert_eq!(info.version.as_deref(), Some("8.6.0"));
+        assert_eq!(info.lockfile.as_deref(), Some("pnpm-lock.yaml"));
+    }
+
+    #[test]
+    fn detects_yarn_berry_pnp_zero_install() {
+        let (dir, pkg) = project(
+            r#"{"name": "a", "version": "1.0.0", "packageManager": "yarn@3.6.1"}"#,
+            &[
+                ("yarn.lock", "__metadata:\n  version: 6\n"),
+                (".yarnrc.yml", "enableGlobalCache: false\n"),
+                (".yarn/cache/react.zip", ""),
+            ],
+        );
+        assert_eq!(
+            PackageManagerInfo::detect(&pkg, dir.path()).manager,
+            PackageManager::YarnBerry {
+                pnp: true,
+                zero_install: true
+            }
+        );
+
+        let (dir, pkg) = project(
+            r#"{"name": "a", "version": "1.0.0"}"#,
+            &[
+                ("yarn.lock", "__metadata:\n  version: 6\n"),
+                (".yarnrc.yml", "nodeLinker: node-modules # no pnp\n"),
+            ],
+        );
+        assert_eq!(
+            PackageManagerInfo::detect(&pkg, dir.path()).manager,
+            PackageManager::YarnBerry {
+                pnp: false,
+                zero_install: false
+            }
+        );
+    }
+}
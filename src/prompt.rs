@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod tests;
+
+use crate::{error::AppError, log::instruct};
+use std::{
+    env,
+    ffi::OsStr,
+    io::{self, IsTerminal},
+    sync::OnceLock,
+};
+
+/// How prompts get answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Interaction {
+    /// asks on the terminal
+    #[default]
+    Interactive,
+    /// takes the default answer of every prompt, e.g. in CI
+    Defaults,
+    /// answers yes to every prompt
+    Yes,
+}
+
+/// Set once per run, for prompts outside of a command's own flow, e.g. while loading the state
+static INTERACTION: OnceLock<Interaction> = OnceLock::new();
+
+/// Whether the `CI` env var, set by most CI services, is set to something other than false
+fn ci(value: Option<&OsStr>) -> bool {
+    value
+        .map(|v| !v.is_empty() && v != "false" && v != "0")
+        .unwrap_or(false)
+}
+
+impl Interaction {
+    fn resolve(non_interactive: bool, yes: bool, terminal: bool, ci_env: Option<&OsStr>) -> Self {
+        if yes {
+            Interaction::Yes
+        } else if non_interactive || !terminal || ci(ci_env) {
+            Interaction::Defaults
+        } else {
+            Interaction::Interactive
+        }
+    }
+
+    /// `--yes` answers every prompt with yes; `--non-interactive` takes their defaults, as
+    /// happens when stdin isn't a terminal or `CI` is set
+    pub(crate) fn detect(non_interactive: bool, yes: bool) -> Self {
+        let interaction = Interaction::resolve(
+            non_interactive,
+            yes,
+            io::stdin().is_terminal(),
+            env::var_os("CI").as_deref(),
+        );
+        tracing::debug!("Prompts are answered {:?}", interaction);
+        interaction
+    }
+
+    /// Answers the prompts of this run this way; only the first call counts
+    pub(crate) fn set(self) {
+        let _ = INTERACTION.set(self);
+    }
+
+    /// How prompts of this run get answered; they take their defaults when it wasn't set
+    pub(crate) fn current() -> Self {
+        INTERACTION.get().copied().unwrap_or(Interaction::Defaults)
+    }
+
+    pub(crate) fn is_interactive(&self) -> bool {
+        *self == Interaction::Interactive
+    }
+
+    /// Asks `prompt` on the terminal, or answers it without asking
+    pub(crate) fn confirm(&self, prompt: &str, default: bool) -> Result<bool, AppError> {
+        let answer = |yes: bool| if yes { "yes" } else { "no" };
+        match self {
+            Interaction::Interactive => dialoguer::Confirm::new()
+                .with_prompt(prompt)
+                .default(default)
+                .interact()
+                .map_err(|e| AppError::IOError("Failed reading the answer to a prompt", e)),
+            Interaction::Defaults => {
+                instruct(&format!("{} {} (non-interactive)", prompt, answer(default)));
+                Ok(default)
+            }
+            Interaction::Yes => {
+                instruct(&format!("{} yes (--yes)", prompt));
+                Ok(true)
+            }
+        }
+    }
+}
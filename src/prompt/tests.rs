@@ -0,0 +1,36 @@
+use super::Interaction;
+use std::ffi::OsStr;
+
+#[test]
+fn detects_non_interactive_runs() {
+    let resolve = |non_interactive, yes, terminal, ci: Option<&str>| {
+        Interaction::resolve(non_interactive, yes, terminal, ci.map(OsStr::new))
+    };
+    assert_eq!(resolve(false, false, true, None), Interaction::Interactive);
+    assert_eq!(
+        resolve(false, false, true, Some("false")),
+        Interaction::Interactive
+    );
+    assert_eq!(
+        resolve(false, false, true, Some("")),
+        Interaction::Interactive
+    );
+    assert_eq!(resolve(true, false, true, None), Interaction::Defaults);
+    assert_eq!(resolve(false, false, false, None), Interaction::Defaults);
+    assert_eq!(
+        resolve(false, false, true, Some("true")),
+        Interaction::Defaults
+    );
+    assert_eq!(
+        resolve(false, false, true, Some("1")),
+        Interaction::Defaults
+    );
+    assert_eq!(resolve(true, true, false, Some("true")), Interaction::Yes);
+}
+
+#[test]
+fn answers_without_asking() {
+    assert!(Interaction::Defaults.confirm("Apply?", true).unwrap());
+    assert!(!Interaction::Defaults.confirm("Open?", false).unwrap());
+    assert!(Interaction::Yes.confirm("Open?", false).unwrap());
+}
@@ -1,7 +1,15 @@
+mod binding;
+mod location;
+mod npmrc;
 mod proc_file;
+#[cfg(test)]
+mod tests;
 
-use super::log::trying;
-use crate::{error::AppError, log::success};
+use super::log::{self, file::LOGS_DIR, trying};
+use crate::{
+    autopack::StateFormat, error::AppError, lock::FileLock, log::success, network::Network,
+    scaffold::Scaffold,
+};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -10,6 +18,9 @@ use std::{
 };
 use tracing::{debug, warn};
 
+pub(crate) use binding::{container_binding_path, BindingMount};
+pub(crate) use location::{locate, runtime_dir, Relocation, RuntimeLocation, RUNTIME_DIR};
+pub(crate) use npmrc::{NpmrcSource, DEFAULT_REGISTRY};
 pub(crate) use proc_file::ProcFile;
 
 /// Runtime information of autopack
@@ -17,20 +28,58 @@ pub(crate) use proc_file::ProcFile;
 pub(crate) struct Runtime {
     /// `.autopack` directory path
     dir: PathBuf,
+    /// whether `dir` is in the project or the user cache
+    #[serde(default)]
+    location: RuntimeLocation,
     /// proc file
     proc_file: ProcFile,
+    /// private registry credentials bound into the build
+    #[serde(default)]
+    npmrc: Option<NpmrcSource>,
+    /// extra CA certificates and proxies
+    #[serde(default)]
+    network: Network,
+    /// number of builds kept under `builds`
+    #[serde(default = "default_keep_builds")]
+    keep_builds: usize,
+    /// encoding of the saved state
+    #[serde(default)]
+    state_format: StateFormat,
+    /// host port `auto-pack run` serves on
+    #[serde(default)]
+    port: Option<usize>,
+    /// npm scripts and `.gitignore` entries added to the project
+    #[serde(default)]
+    scaffold: Scaffold,
+}
+
+pub(crate) fn default_keep_builds() -> usize {
+    20
 }
 
 pub(crate) struct RuntimeBuilder {
+    project_root: PathBuf,
     dir: PathBuf,
+    location: RuntimeLocation,
     proc_file: Option<ProcFile>,
+    npmrc: Option<NpmrcSource>,
+    network: Network,
+    keep_builds: usize,
+    state_format: StateFormat,
 }
 
 impl Default for Runtime {
     fn default() -> Self {
         Runtime {
-            dir: PathBuf::new().join(".autopack"),
+            dir: PathBuf::new().join(RUNTIME_DIR),
+            location: RuntimeLocation::default(),
             proc_file: ProcFile::default(),
+            npmrc: None,
+            network: Network::default(),
+            keep_builds: default_keep_builds(),
+            state_format: StateFormat::default(),
+            port: None,
+            scaffold: Scaffold::default(),
         }
     }
 }
@@ -41,7 +90,7 @@ fn create_runtime_dir(path: &Path, force_create: bool) -> Result<PathBuf, io::Er
         if created {
             debug!("Created runtime folder at {:?}", p);
             success("Created runtime folder");
-        }else {
+        } else {
             debug!("Runtime folder already present at {:?}", p);
             success("Runtime folder already present");
         }
@@ -49,7 +98,7 @@ fn create_runtime_dir(path: &Path, force_create: bool) -> Result<PathBuf, io::Er
         p
     };
     if !path.exists() {
-        fs::create_dir(path)?;
+        fs::create_dir_all(path)?;
         Ok(rtp(true))
     } else if force_create {
         let full_path = path.canonicalize()?;
@@ -57,7 +106,19 @@ fn create_runtime_dir(path: &Path, force_create: bool) -> Result<PathBuf, io::Er
             "runtime folder present at {:?}; removing the same",
             full_path
         );
-        fs::remove_dir_all(path)?;
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            // the lock is held and the logs are kept for bug reports
+            if FileLock::is_lock_file(&name) || name == LOGS_DIR {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())?;
+            } else {
+                fs::remove_file(entry.path())?;
+            }
+        }
         create_runtime_dir(path, false)
     } else {
         Ok(rtp(false))
@@ -68,8 +129,14 @@ impl Runtime {
     pub(crate) fn builder(mod_path: &Path) -> RuntimeBuilder {
         trying("Generating auto-pack runtime");
         RuntimeBuilder {
-            dir: mod_path.join(".autopack"),
+            project_root: mod_path.to_path_buf(),
+            dir: mod_path.join(RUNTIME_DIR),
+            location: RuntimeLocation::default(),
             proc_file: None,
+            npmrc: None,
+            network: Network::default(),
+            keep_builds: default_keep_builds(),
+            state_format: StateFormat::default(),
         }
     }
 
@@ -81,6 +148,77 @@ impl Runtime {
         self.proc_file.clone()
     }
 
+    pub(crate) fn relocate(&mut self, paths: &Relocation) {
+        self.dir = paths.project(&self.dir);
+        self.proc_file.relocate(paths);
+        if let Some(npmrc) = self.npmrc.as_mut() {
+            npmrc.relocate(paths);
+        }
+        self.network.relocate(paths);
+    }
+
+    /// Service bindings mounted into the build container; ephemeral ones are
+    /// removed when the returned mounts are dropped.
+    pub(crate) fn binding_mounts(&self) -> Result<Vec<BindingMount>> {
+        let mut mounts = vec![self.proc_file.mount()];
+        if let Some(npmrc) = self.npmrc.as_ref() {
+            mounts.push(npmrc.mount()?);
+        }
+        if let Some(ca) = self.network.ca_mount()? {
+            mounts.push(ca);
+        }
+        Ok(mounts)
+    }
+
+    pub(crate) fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub(crate) fn keep_builds(&self) -> usize {
+        self.keep_builds
+    }
+
+    pub(crate) fn state_format(&self) -> StateFormat {
+        self.state_format
+    }
+
+    pub(crate) fn port(&self) -> Option<usize> {
+        self.port
+    }
+
+    pub(crate) fn set_port(&mut self, port: Option<usize>) -> &mut Self {
+        self.port = port;
+        self
+    }
+
+    pub(crate) fn set_keep_builds(&mut self, keep_builds: usize) -> &mut Self {
+        self.keep_builds = keep_builds;
+        self
+    }
+
+    pub(crate) fn set_state_format(&mut self, state_format: StateFormat) -> &mut Self {
+        self.state_format = state_format;
+        self
+    }
+
+    pub(crate) fn set_proc_file(&mut self, proc_file: ProcFile) -> &mut Self {
+        self.proc_file = proc_file;
+        self
+    }
+
+    pub(crate) fn location(&self) -> RuntimeLocation {
+        self.location
+    }
+
+    pub(crate) fn scaffold(&self) -> &Scaffold {
+        &self.scaffold
+    }
+
+    pub(crate) fn set_scaffold(&mut self, scaffold: Scaffold) -> &mut Self {
+        self.scaffold = scaffold;
+        self
+    }
+
     pub(crate) fn project_toml(&self) -> String {
         self.dir
             .join("project.toml")
@@ -91,19 +229,51 @@ impl Runtime {
 }
 
 impl RuntimeBuilder {
+    /// Where to create the runtime folder; set before creating it
+    pub(crate) fn location(
+        &mut self,
+        location: Option<RuntimeLocation>,
+    ) -> Result<&mut Self, AppError> {
+        if let Some(location) = location {
+            self.location = location;
+        }
+        if self.location == RuntimeLocation::Cache {
+            self.dir = location::runtime_dir(&self.project_root, self.location).map_err(|e| {
+                AppError::ToolCacheError("Failed to locate the user cache directory", e)
+            })?;
+            let in_project = self.project_root.join(RUNTIME_DIR);
+            if in_project.exists() {
+                log::warn(&format!(
+                    "{} takes precedence over the runtime folder in the cache; remove it",
+                    in_project.display()
+                ));
+            }
+        }
+        Ok(self)
+    }
+
     pub(crate) fn dir(&mut self, force_create_runtime: bool) -> Result<&mut Self, AppError> {
         trying("Creating runtime folder");
         let runtime_full_path = create_runtime_dir(self.dir.as_path(), force_create_runtime)
             .map_err(|e| AppError::IOError("Failed to create runtime dir", e))?;
+        if self.location == RuntimeLocation::Cache {
+            location::mark_project(&runtime_full_path, &self.project_root)
+                .map_err(|e| AppError::PostConfigureError("Failed to create runtime dir", e))?;
+        }
 
         self.dir = runtime_full_path;
         Ok(self)
     }
 
-    pub(crate) fn proc_file(&mut self, live_reload: bool) -> Result<&mut Self, AppError> {
+    pub(crate) fn proc_file(
+        &mut self,
+        live_reload: bool,
+        app_path: Option<String>,
+    ) -> Result<&mut Self, AppError> {
         trying("Creating proc file");
 
         let proc_file = ProcFile::builder()
+            .app_path(app_path)
             .override_start_entry(live_reload)
             .export(&self.dir)
             .map_err(|e| AppError::PostConfigureError("Failed creating Procfile", e))?
@@ -115,14 +285,45 @@ impl RuntimeBuilder {
         Ok(self)
     }
 
+    pub(crate) fn npmrc(&mut self, npmrc: Option<NpmrcSource>) -> &mut Self {
+        self.npmrc = npmrc;
+        self
+    }
+
+    pub(crate) fn network(&mut self, network: Network) -> &mut Self {
+        self.network = network;
+        self
+    }
+
+    pub(crate) fn keep_builds(&mut self, keep_builds: Option<usize>) -> &mut Self {
+        if let Some(keep) = keep_builds {
+            self.keep_builds = keep;
+        }
+        self
+    }
+
+    pub(crate) fn state_format(&mut self, state_format: Option<StateFormat>) -> &mut Self {
+        if let Some(format) = state_format {
+            self.state_format = format;
+        }
+        self
+    }
+
     pub(crate) fn build(&mut self) -> Runtime {
         success("Generated auto-pack runtime");
         Runtime {
             dir: self.dir.clone(),
+            location: self.location,
             proc_file: self
                 .proc_file
                 .clone()
                 .unwrap_or(Runtime::default().proc_file),
+            npmrc: self.npmrc.clone(),
+            network: self.network.clone(),
+            keep_builds: self.keep_builds,
+            state_format: self.state_format,
+            port: None,
+            scaffold: Scaffold::default(),
         }
     }
 }
@@ -0,0 +1,109 @@
+use crate::lock::write_atomic;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+
+/// Root the Paketo buildpacks read [service bindings](https://github.com/servicebinding/spec) from
+const CONTAINER_BINDINGS_ROOT: &str = "/platform/bindings";
+
+pub(crate) const BINDING_PROVIDER: &str = "autopack";
+
+pub(crate) fn container_binding_path(name: &str) -> String {
+    format!("{}/{}", CONTAINER_BINDINGS_ROOT, name)
+}
+
+/// The lifecycle container runs as the builder's `cnb` user rather than the host user, so
+/// the mounted binding has to be world readable. Its temporary parent stays `0700`, leaving
+/// the bind mount as the only way in.
+#[cfg(unix)]
+fn container_readable(path: &Path, mode: u32) -> anyhow::Result<()> {
+    use std::os::unix::prelude::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn container_readable(_path: &Path, _mode: u32) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Writes the binding `name` under `parent` with its `type` and `provider` files and the given entries
+pub(crate) fn export_binding(
+    parent: &Path,
+    name: &str,
+    binding_type: &str,
+    provider: &str,
+    entries: &[(&str, &[u8])],
+) -> anyhow::Result<PathBuf> {
+    let dir = parent.join(name);
+
+    if !dir.exists() {
+        fs::create_dir_all(&dir)?;
+    }
+
+    write_atomic(&dir.join("type"), binding_type)?;
+    write_atomic(&dir.join("provider"), provider)?;
+    for (entry, contents) in entries {
+        write_atomic(&dir.join(entry), contents)?;
+    }
+
+    Ok(dir.canonicalize()?)
+}
+
+/// A binding volume-mounted into the build container by `pack build`.
+///
+/// Bindings carrying secrets live in a temporary directory which is removed
+/// once the mount is dropped, so they never outlive the build.
+pub(crate) struct BindingMount {
+    host_path: PathBuf,
+    container_path: String,
+    _temp_dir: Option<TempDir>,
+}
+
+impl BindingMount {
+    pub(crate) fn persistent(host_path: PathBuf, name: &str) -> BindingMount {
+        BindingMount {
+            host_path,
+            container_path: container_binding_path(name),
+            _temp_dir: None,
+        }
+    }
+
+    pub(crate) fn ephemeral(
+        name: &str,
+        binding_type: &str,
+        entries: &[(&str, &[u8])],
+    ) -> anyhow::Result<BindingMount> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("autopack-binding")
+            .tempdir()?;
+        let host_path = export_binding(
+            temp_dir.path(),
+            name,
+            binding_type,
+            BINDING_PROVIDER,
+            entries,
+        )?;
+        container_readable(temp_dir.path(), 0o700)?;
+        container_readable(&host_path, 0o755)?;
+        for entry in ["type", "provider"]
+            .into_iter()
+            .chain(entries.iter().map(|(e, _)| *e))
+        {
+            container_readable(&host_path.join(entry), 0o644)?;
+        }
+        Ok(BindingMount {
+            host_path,
+            container_path: container_binding_path(name),
+            _temp_dir: Some(temp_dir),
+        })
+    }
+
+    /// `--volume` argument for pack
+    pub(crate) fn volume(&self) -> String {
+        format!("{}:{}", self.host_path.display(), self.container_path)
+    }
+}
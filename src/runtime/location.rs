@@ -0,0 +1,227 @@
+use crate::{
+    lock::write_atomic,
+    tools::{cache_root, ToolCache},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    env, fmt, fs,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+use tracing::debug;
+
+pub(crate) const RUNTIME_DIR: &str = ".autopack";
+
+/// Written into runtime directories kept outside the project, holding the project root
+const PROJECT_FILE: &str = "project";
+
+/// Where the runtime directory lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RuntimeLocation {
+    /// `.autopack` in the project
+    #[default]
+    Project,
+    /// `<user cache>/autopack/projects/<project>-<hash>`, keeping the project clean
+    Cache,
+}
+
+impl RuntimeLocation {
+    pub(crate) const NAMES: [&'static str; 2] = ["project", "cache"];
+}
+
+impl fmt::Display for RuntimeLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RuntimeLocation::Project => "project",
+            RuntimeLocation::Cache => "cache",
+        })
+    }
+}
+
+impl FromStr for RuntimeLocation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "project" => Ok(RuntimeLocation::Project),
+            "cache" => Ok(RuntimeLocation::Cache),
+            _ => anyhow::bail!(
+                "Unknown runtime location {:?}, expected one of {}",
+                s,
+                RuntimeLocation::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    let path = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+    path.canonicalize().unwrap_or_else(|_| {
+        normalize(
+            &env::current_dir()
+                .map(|cwd| cwd.join(path))
+                .unwrap_or_else(|_| path.to_path_buf()),
+        )
+    })
+}
+
+/// Resolves `.` and `..` without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+/// Runtime directory of the project when kept in the user cache
+fn cache_runtime_dir(project_root: &Path) -> anyhow::Result<PathBuf> {
+    let root = canonical(project_root);
+    let hash = hex::encode(Sha256::digest(root.to_string_lossy().as_bytes()));
+    let name = root
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(cache_root()?
+        .join("projects")
+        .join(format!("{}-{}", name, &hash[..16])))
+}
+
+/// Runtime directory of the project at `location`
+pub(crate) fn runtime_dir(
+    project_root: &Path,
+    location: RuntimeLocation,
+) -> anyhow::Result<PathBuf> {
+    match location {
+        RuntimeLocation::Project => Ok(project_root.join(RUNTIME_DIR)),
+        RuntimeLocation::Cache => cache_runtime_dir(project_root),
+    }
+}
+
+/// Runtime directory of the project: `.autopack` in the project if present, else the one
+/// in the user cache if present, else where a new `.autopack` goes
+pub(crate) fn locate(project_root: &Path) -> PathBuf {
+    let in_project = project_root.join(RUNTIME_DIR);
+    if in_project.exists() {
+        return in_project;
+    }
+    match cache_runtime_dir(project_root) {
+        Ok(dir) if dir.exists() => {
+            debug!("Using the runtime directory at {:?}", dir);
+            dir
+        }
+        _ => in_project,
+    }
+}
+
+/// Records the project of a runtime directory kept outside of it
+pub(crate) fn mark_project(runtime_dir: &Path, project_root: &Path) -> anyhow::Result<()> {
+    write_atomic(
+        &runtime_dir.join(PROJECT_FILE),
+        canonical(project_root).to_string_lossy().as_bytes(),
+    )?;
+    Ok(())
+}
+
+/// Project root of a runtime directory: the one it records, else its parent
+pub(crate) fn project_root(runtime_dir: &Path) -> PathBuf {
+    match fs::read_to_string(runtime_dir.join(PROJECT_FILE)) {
+        Ok(root) => PathBuf::from(root.trim()),
+        Err(_) => canonical(runtime_dir.parent().unwrap_or_else(|| Path::new("."))),
+    }
+}
+
+/// `path` relative to `base` when within it or one of its ancestors, absolute otherwise
+pub(crate) fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    if path.as_os_str().is_empty() {
+        return path.to_path_buf();
+    }
+    let path = canonical(path);
+    if let Ok(rel) = path.strip_prefix(base) {
+        return if rel.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            rel.to_path_buf()
+        };
+    }
+    match base.strip_prefix(&path) {
+        Ok(below) => below.components().map(|_| Component::ParentDir).collect(),
+        Err(_) => path,
+    }
+}
+
+/// Absolute path of a stored `path`, relative ones being relative to `base`
+pub(crate) fn resolve(path: &Path, base: &Path) -> PathBuf {
+    if path.as_os_str().is_empty() || path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        normalize(&base.join(path))
+    }
+}
+
+/// Maps the paths of the state between the form stored in the state file and the absolute
+/// paths used otherwise. Stored paths are relative to the project root, those within the
+/// runtime directory relative to it, and tools relative to the tool cache, so that the
+/// project can be moved or checked out elsewhere.
+pub(crate) struct Relocation {
+    root: PathBuf,
+    runtime_dir: PathBuf,
+    tools: Option<PathBuf>,
+    store: bool,
+}
+
+impl Relocation {
+    fn new(runtime_dir: &Path, store: bool) -> Relocation {
+        Relocation {
+            root: project_root(runtime_dir),
+            runtime_dir: canonical(runtime_dir),
+            tools: ToolCache::new().ok().map(|c| canonical(c.root())),
+            store,
+        }
+    }
+
+    /// Makes the paths of a state in `runtime_dir` relative
+    pub(crate) fn store(runtime_dir: &Path) -> Relocation {
+        Relocation::new(runtime_dir, true)
+    }
+
+    /// Resolves the paths of a state loaded from `runtime_dir`
+    pub(crate) fn load(runtime_dir: &Path) -> Relocation {
+        Relocation::new(runtime_dir, false)
+    }
+
+    fn map(&self, path: &Path, base: &Path) -> PathBuf {
+        if self.store {
+            relative_to(path, base)
+        } else {
+            resolve(path, base)
+        }
+    }
+
+    pub(crate) fn project(&self, path: &Path) -> PathBuf {
+        self.map(path, &self.root)
+    }
+
+    pub(crate) fn runtime(&self, path: &Path) -> PathBuf {
+        self.map(path, &self.runtime_dir)
+    }
+
+    pub(crate) fn tools(&self, path: &Path) -> PathBuf {
+        match self.tools.as_ref() {
+            Some(tools) => self.map(path, tools),
+            None => path.to_path_buf(),
+        }
+    }
+}
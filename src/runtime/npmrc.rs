@@ -0,0 +1,146 @@
+use super::{binding::BindingMount, Relocation};
+use serde::{Deserialize, Serialize};
+use std::{env, fs, path::PathBuf};
+
+/// Keys which only show up in an `.npmrc` carrying credentials
+const CREDENTIAL_KEYS: [&str; 4] = ["_authToken", "_auth", "_password", "${"];
+
+pub(crate) const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org/";
+
+/// Where the credentials for the private registry come from.
+///
+/// Only the source is persisted in the autopack state. The `.npmrc` handed to the
+/// [npm-install buildpack](https://github.com/paketo-buildpacks/npm-install#npmrc) is
+/// assembled at build time into an `npmrc` binding which is removed after the build.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub(crate) enum NpmrcSource {
+    /// an `.npmrc` file; `${VAR}` placeholders are resolved from the host environment
+    File(PathBuf),
+    /// auth token for `registry` read from the environment variable `env_var`
+    Token { env_var: String, registry: String },
+}
+
+/// Looks up an environment variable; the host environment outside of tests
+type Env<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+fn host_env(var: &str) -> Option<String> {
+    env::var(var).ok()
+}
+
+/// Replaces `${VAR}` placeholders the same way npm does, failing on unset variables
+fn expand_env(contents: &str, env: Env) -> anyhow::Result<String> {
+    let mut out = String::with_capacity(contents.len());
+    let mut rest = contents;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .map(|e| start + e)
+            .ok_or_else(|| anyhow::anyhow!("Unterminated `${{` in .npmrc"))?;
+        let var = &rest[start + 2..end];
+        let value = env(var).ok_or_else(|| {
+            anyhow::anyhow!("Environment variable {} used in .npmrc is not set", var)
+        })?;
+        out.push_str(&rest[..start]);
+        out.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+impl NpmrcSource {
+    /// An `.npmrc` is only worth binding when it carries credentials; plain settings
+    /// can stay in the build context.
+    pub(crate) fn has_credentials(path: &std::path::Path) -> bool {
+        fs::read_to_string(path)
+            .map(|c| CREDENTIAL_KEYS.iter().any(|k| c.contains(k)))
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn relocate(&mut self, paths: &Relocation) {
+        if let NpmrcSource::File(path) = self {
+            *path = paths.project(path);
+        }
+    }
+
+    fn contents(&self, env: Env) -> anyhow::Result<String> {
+        match self {
+            NpmrcSource::File(path) => fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed reading {:?} :: {}", path, e))
+                .and_then(|c| expand_env(&c, env)),
+            NpmrcSource::Token { env_var, registry } => {
+                let token = env(env_var).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Environment variable {} holding the npm token is not set",
+                        env_var
+                    )
+                })?;
+                let host = registry
+                    .trim_start_matches("https:")
+                    .trim_start_matches("http:")
+                    .trim_end_matches('/');
+                Ok(format!(
+                    "registry={}\n{}/:_authToken={}\n",
+                    registry, host, token
+                ))
+            }
+        }
+    }
+
+    pub(crate) fn mount(&self) -> anyhow::Result<BindingMount> {
+        self.mount_with(&host_env)
+    }
+
+    fn mount_with(&self, env: Env) -> anyhow::Result<BindingMount> {
+        let contents = self.contents(env)?;
+        BindingMount::ephemeral("npmrc", "npmrc", &[(".npmrc", contents.as_bytes())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_env(var: &str) -> Option<String> {
+        (var == "NPM_TOKEN").then(|| "s3cr3t".to_string())
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        assert_eq!(
+            expand_env("//npm.pkg.github.com/:_authToken=${NPM_TOKEN}\n", &test_env).unwrap(),
+            "//npm.pkg.github.com/:_authToken=s3cr3t\n"
+        );
+        assert!(expand_env("x=${UNSET_VAR}", &test_env).is_err());
+    }
+
+    #[test]
+    fn token_binding_is_removed_after_use() {
+        let source = NpmrcSource::Token {
+            env_var: "NPM_TOKEN".to_string(),
+            registry: "https://npm.pkg.github.com/".to_string(),
+        };
+        let mount = source.mount_with(&test_env).expect("failed mounting");
+        let volume = mount.volume();
+        let host = PathBuf::from(volume.split(":/platform").next().unwrap());
+
+        assert_eq!(fs::read_to_string(host.join("type")).unwrap(), "npmrc");
+        assert_eq!(
+            fs::read_to_string(host.join(".npmrc")).unwrap(),
+            "registry=https://npm.pkg.github.com/\n//npm.pkg.github.com/:_authToken=s3cr3t\n"
+        );
+        // readable by the builder's `cnb` user inside the container
+        #[cfg(unix)]
+        {
+            use std::os::unix::prelude::PermissionsExt;
+            let mode = fs::metadata(host.join(".npmrc"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o644);
+        }
+
+        drop(mount);
+        assert!(!host.exists());
+    }
+}
@@ -1,7 +1,10 @@
+use super::{
+    binding::{export_binding, BindingMount, BINDING_PROVIDER},
+    Relocation,
+};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    fs,
     path::{Path, PathBuf},
 };
 
@@ -23,7 +26,7 @@ impl Default for ProcFile {
     fn default() -> Self {
         ProcFile {
             command_entries: HashMap::new(),
-            binding_provider: "autopack".to_string(),
+            binding_provider: BINDING_PROVIDER.to_string(),
             binding_type: "Procfile".to_string(),
             default_command: "override-start".to_string(),
             file_path: Some(Path::new(".autopack").to_path_buf().join("Procfile")),
@@ -31,6 +34,15 @@ impl Default for ProcFile {
     }
 }
 
+fn procfile(command_entries: &HashMap<String, String>) -> String {
+    command_entries
+        .clone()
+        .into_iter()
+        .fold("".to_string(), |acc, x| {
+            format!("{}: {}\n{}", x.0, x.1, acc)
+        })
+}
+
 impl ProcFile {
     pub(crate) fn builder() -> ProcFileBuilder {
         let pf = ProcFile::default();
@@ -40,6 +52,7 @@ impl ProcFile {
             binding_provider: pf.binding_provider,
             binding_type: pf.binding_type,
             file_path: pf.file_path,
+            app_path: None,
         }
     }
 
@@ -56,8 +69,43 @@ impl ProcFile {
         self.default_command.clone()
     }
 
-    pub(crate) fn container_bindings_path(&self) -> String {
-        "/platform/bindings/Procfile".to_string()
+    /// Procfile contents
+    pub(crate) fn contents(&self) -> String {
+        procfile(&self.command_entries)
+    }
+
+    /// Same Procfile, starting the app with or without live reload
+    pub(crate) fn with_live_reload(&self, live_reload: bool, app_path: Option<String>) -> ProcFile {
+        let mut proc_file = ProcFile::builder()
+            .app_path(app_path)
+            .override_start_entry(live_reload)
+            .build();
+        proc_file.file_path = self.file_path.clone();
+        proc_file
+    }
+
+    /// Rewrites the binding where it was exported
+    pub(crate) fn export(&self) -> anyhow::Result<()> {
+        let dir = PathBuf::from(self.proc_file_path());
+        let parent = dir
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Procfile binding {:?} has no parent", dir))?;
+        export_binding(
+            parent,
+            "Procfile",
+            &self.binding_type,
+            &self.binding_provider,
+            &[("Procfile", self.contents().as_bytes())],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn relocate(&mut self, paths: &Relocation) {
+        self.file_path = self.file_path.as_deref().map(|p| paths.runtime(p));
+    }
+
+    pub(crate) fn mount(&self) -> BindingMount {
+        BindingMount::persistent(PathBuf::from(self.proc_file_path()), &self.binding_type)
     }
 }
 
@@ -67,6 +115,8 @@ pub(crate) struct ProcFileBuilder {
     binding_provider: String,
     binding_type: String,
     file_path: Option<PathBuf>,
+    /// app directory relative to `/workspace` when the project is part of a monorepo
+    app_path: Option<String>,
 }
 
 impl ProcFileBuilder {
@@ -80,15 +130,24 @@ impl ProcFileBuilder {
         self
     }
 
+    pub(crate) fn app_path(&mut self, app_path: Option<String>) -> &mut ProcFileBuilder {
+        self.app_path = app_path;
+        self
+    }
+
     pub(crate) fn override_start_entry(&mut self, live_reload: bool) -> &mut ProcFileBuilder {
         let mut watch_cmd = None;
-        let serve_cmd = "serve -s build -l 8080".to_string();
+        let app_dir = self
+            .app_path
+            .as_ref()
+            .map(|p| format!("/workspace/{}", p))
+            .unwrap_or_else(|| "/workspace".to_string());
+        let serve_cmd = format!("serve -s {}/build -l 8080", app_dir);
         if live_reload {
-            let build_cmd = "npm run build".to_string();
+            let build_cmd = format!("npm run build --prefix {}", app_dir);
             watch_cmd = Some(format!(
-                r#"watchexec --restart --shell none --watch /workspace/src -- bash -c "{} && {}""#,
-                build_cmd,
-                serve_cmd
+                r#"watchexec --restart --shell none --watch {}/src -- bash -c "{} && {}""#,
+                app_dir, build_cmd, serve_cmd
             ));
         }
 
@@ -103,25 +162,17 @@ impl ProcFileBuilder {
     }
 
     pub(crate) fn export(&mut self, export_path: &Path) -> anyhow::Result<&mut ProcFileBuilder> {
-        let dir = export_path.to_path_buf().join("Procfile");
-
-        if !dir.exists() {
-            fs::create_dir(dir.clone())?;
-        }
+        let procfile = procfile(&self.command_entries);
 
-        fs::write(dir.join("type"), self.binding_type.clone())?;
-        fs::write(dir.join("provider"), self.binding_provider.clone())?;
-        fs::write(
-            dir.join("Procfile"),
-            self.command_entries
-                .clone()
-                .into_iter()
-                .fold("".to_string(), |acc, x| {
-                    format!("{}: {}\n{}", x.0, x.1, acc)
-                }),
+        let dir = export_binding(
+            export_path,
+            "Procfile",
+            &self.binding_type,
+            &self.binding_provider,
+            &[("Procfile", procfile.as_bytes())],
         )?;
 
-        self.file_path = Some(dir.canonicalize().map_err(|e| anyhow::anyhow!(e))?);
+        self.file_path = Some(dir);
         Ok(self)
     }
 
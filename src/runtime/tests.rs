@@ -0,0 +1,48 @@
+use super::location::{mark_project, project_root, relative_to, resolve};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+
+#[test]
+fn relative_paths_roundtrip() {
+    let dir = TempDir::new().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    let app = root.join("packages").join("web");
+    fs::create_dir_all(&app).unwrap();
+
+    let cases = [
+        (app.join("src"), PathBuf::from("src")),
+        (app.clone(), PathBuf::from(".")),
+        (root.clone(), PathBuf::from("../..")),
+        (
+            PathBuf::from("/elsewhere/ca.pem"),
+            PathBuf::from("/elsewhere/ca.pem"),
+        ),
+        (PathBuf::new(), PathBuf::new()),
+    ];
+    for (path, stored) in cases {
+        assert_eq!(relative_to(&path, &app), stored);
+        assert_eq!(resolve(&stored, &app), path);
+    }
+    assert_eq!(resolve(Path::new("../web/src"), &app), app.join("src"));
+}
+
+#[test]
+fn finds_the_project_root() {
+    let project = TempDir::new().unwrap();
+    let runtime_dir = project.path().join(".autopack");
+    fs::create_dir(&runtime_dir).unwrap();
+    assert_eq!(
+        project_root(&runtime_dir),
+        project.path().canonicalize().unwrap()
+    );
+
+    let elsewhere = TempDir::new().unwrap();
+    mark_project(elsewhere.path(), project.path()).unwrap();
+    assert_eq!(
+        project_root(elsewhere.path()),
+        project.path().canonicalize().unwrap()
+    );
+}
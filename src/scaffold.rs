@@ -0,0 +1,517 @@
+#[cfg(test)]
+mod tests;
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// npm scripts wired by `init`, so that autopack runs through familiar `npm run` commands
+pub(crate) const SCRIPTS: [(&str, &str); 2] = [
+    ("build:pack", "auto-pack build"),
+    ("start:pack", "auto-pack run"),
+];
+
+/// Line above the `.gitignore` entry, marking it as managed by autopack
+const GITIGNORE_MARKER: &str = "# autopack";
+const GITIGNORE_ENTRY: &str = ".autopack/";
+
+/// Edits autopack made to files of the project, reverted by `deinit`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Scaffold {
+    /// scripts in package.json
+    #[serde(default)]
+    pub(crate) scripts: Vec<String>,
+    /// whether `.gitignore` has the autopack entry
+    #[serde(default)]
+    pub(crate) gitignore: bool,
+}
+
+impl Scaffold {
+    /// Also records the edits of `other`, e.g. the ones of the state a re-init replaces
+    pub(crate) fn merge(&mut self, other: Scaffold) {
+        for name in other.scripts {
+            if !self.scripts.contains(&name) {
+                self.scripts.push(name);
+            }
+        }
+        self.scripts
+            .sort_by_key(|name| SCRIPTS.iter().position(|(n, _)| n == name));
+        self.gitignore |= other.gitignore;
+    }
+}
+
+/// A change to a project file, previewed before it's written
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FileEdit {
+    pub(crate) path: PathBuf,
+    /// empty for files which don't exist yet
+    pub(crate) before: String,
+    pub(crate) after: String,
+}
+
+impl FileEdit {
+    pub(crate) fn write(&self) -> io::Result<()> {
+        crate::lock::write_atomic(&self.path, &self.after)
+    }
+}
+
+fn read_optional(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Member of a JSON object, spanning from the opening quote of its key to the end of its value
+#[derive(Debug)]
+struct Member {
+    key: String,
+    start: usize,
+    value_start: usize,
+    end: usize,
+}
+
+/// Byte offsets of a JSON object within the document text, to edit it in place
+#[derive(Debug)]
+struct Object {
+    open: usize,
+    close: usize,
+    members: Vec<Member>,
+}
+
+fn skip_whitespace(text: &[u8], mut i: usize) -> usize {
+    while i < text.len() && text[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+fn skip_string(text: &[u8], mut i: usize) -> usize {
+    i += 1;
+    while i < text.len() {
+        match text[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+fn skip_value(text: &[u8], i: usize) -> usize {
+    match text.get(i) {
+        Some(b'"') => skip_string(text, i),
+        Some(b'{') | Some(b'[') => {
+            let (mut depth, mut i) = (0, i);
+            while i < text.len() {
+                match text[i] {
+                    b'"' => {
+                        i = skip_string(text, i);
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return i + 1;
+                        }
+                    }
+                    _ => (),
+                }
+                i += 1;
+            }
+            i
+        }
+        _ => {
+            let mut i = i;
+            while i < text.len() && !matches!(text[i], b',' | b'}' | b']') {
+                i += 1;
+            }
+            // trailing whitespace isn't part of the value
+            while i > 0 && text[i - 1].is_ascii_whitespace() {
+                i -= 1;
+            }
+            i
+        }
+    }
+}
+
+/// Object starting at `open`, which has to be valid JSON
+fn object(text: &str, open: usize) -> anyhow::Result<Object> {
+    let bytes = text.as_bytes();
+    if bytes.get(open) != Some(&b'{') {
+        anyhow::bail!("Expected an object at offset {}", open);
+    }
+    let mut members = vec![];
+    let mut i = skip_whitespace(bytes, open + 1);
+    loop {
+        match bytes.get(i) {
+            Some(b'}') => {
+                return Ok(Object {
+                    open,
+                    close: i,
+                    members,
+                })
+            }
+            Some(b'"') => {
+                let key_end = skip_string(bytes, i);
+                let key = serde_json::from_str::<String>(&text[i..key_end])?;
+                let colon = skip_whitespace(bytes, key_end);
+                let value_start = skip_whitespace(bytes, colon + 1);
+                let end = skip_value(bytes, value_start);
+                members.push(Member {
+                    key,
+                    start: i,
+                    value_start,
+                    end,
+                });
+                i = skip_whitespace(bytes, end);
+                if bytes.get(i) == Some(&b',') {
+                    i = skip_whitespace(bytes, i + 1);
+                }
+            }
+            _ => anyhow::bail!("Unexpected JSON at offset {}", i),
+        }
+    }
+}
+
+/// Whitespace before `offset` on its line, `None` when something else precedes it
+fn indent_at(text: &str, offset: usize) -> Option<&str> {
+    let line_start = text[..offset].rfind('\n').map(|i| i + 1)?;
+    let indent = &text[line_start..offset];
+    indent
+        .chars()
+        .all(|c| c == ' ' || c == '\t')
+        .then_some(indent)
+}
+
+fn line_ending(text: &str) -> &'static str {
+    if text.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Layout of the document: line ending and one level of indentation
+struct Layout {
+    newline: &'static str,
+    unit: String,
+}
+
+impl Layout {
+    fn of(text: &str, root: &Object) -> Layout {
+        Layout {
+            newline: line_ending(text),
+            unit: root
+                .members
+                .first()
+                .and_then(|m| indent_at(text, m.start))
+                .unwrap_or("  ")
+                .to_string(),
+        }
+    }
+}
+
+/// Adds `entries` (key, raw JSON value) to the end of `object`, indented like its members
+/// or one level below `parent_indent`
+fn insert_members(
+    text: &str,
+    object: &Object,
+    parent_indent: &str,
+    layout: &Layout,
+    entries: &[(String, String)],
+) -> String {
+    let member_indent = object
+        .members
+        .first()
+        .map(|m| indent_at(text, m.start).map(String::from))
+        .unwrap_or_else(|| Some(format!("{}{}", parent_indent, layout.unit)));
+    let separator = match member_indent.as_ref() {
+        Some(indent) => format!(",{}{}", layout.newline, indent),
+        // all on one line
+        None => ", ".to_string(),
+    };
+    let rendered = entries
+        .iter()
+        .map(|(key, value)| format!("{}: {}", serde_json::Value::from(key.as_str()), value))
+        .collect::<Vec<_>>()
+        .join(&separator);
+
+    match object.members.last() {
+        Some(last) => format!(
+            "{}{}{}{}",
+            &text[..last.end],
+            separator,
+            rendered,
+            &text[last.end..]
+        ),
+        None => {
+            let indent = member_indent.unwrap_or_default();
+            format!(
+                "{}{{{}{}{}{}{}}}{}",
+                &text[..object.open],
+                layout.newline,
+                indent,
+                rendered,
+                layout.newline,
+                parent_indent,
+                &text[object.close + 1..]
+            )
+        }
+    }
+}
+
+/// Removes the member at `index`, along with the separator before or after it
+fn remove_member(text: &str, object: &Object, index: usize) -> String {
+    let member = &object.members[index];
+    match (index, object.members.len()) {
+        (_, 1) => format!("{}{{}}{}", &text[..object.open], &text[object.close + 1..]),
+        (0, _) => format!(
+            "{}{}",
+            &text[..member.start],
+            &text[object.members[1].start..]
+        ),
+        (i, _) => format!(
+            "{}{}",
+            &text[..object.members[i - 1].end],
+            &text[member.end..]
+        ),
+    }
+}
+
+fn root(text: &str) -> anyhow::Result<Object> {
+    // only edit documents which are valid JSON
+    let value = serde_json::from_str::<serde_json::Value>(text)?;
+    if !value.is_object() {
+        anyhow::bail!("package.json isn't a JSON object");
+    }
+    object(text, skip_whitespace(text.as_bytes(), 0))
+}
+
+/// Outcome of adding the autopack scripts to package.json
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ScriptsEdit {
+    /// new text, `None` when nothing changes
+    pub(crate) text: Option<String>,
+    /// scripts autopack adds; ones already present with the same command belong to the user
+    pub(crate) added: Vec<String>,
+    /// scripts of the same name but another command, which are left alone
+    pub(crate) conflicts: Vec<String>,
+}
+
+/// Adds the autopack scripts to the package.json `text`, keeping its key order, indentation
+/// and line endings
+pub(crate) fn add_scripts(text: &str) -> anyhow::Result<ScriptsEdit> {
+    let root = root(text)?;
+    let layout = Layout::of(text, &root);
+    let scripts_member = root.members.iter().find(|m| m.key == "scripts");
+    let scripts = match scripts_member {
+        Some(m) if text.as_bytes()[m.value_start] == b'{' => Some(object(text, m.value_start)?),
+        Some(_) => anyhow::bail!("`scripts` in package.json isn't an object"),
+        None => None,
+    };
+
+    let mut edit = ScriptsEdit::default();
+    let mut missing = vec![];
+    for (name, command) in SCRIPTS {
+        let existing = scripts
+            .as_ref()
+            .and_then(|s| s.members.iter().find(|m| m.key == name))
+            .map(|m| serde_json::from_str::<serde_json::Value>(&text[m.value_start..m.end]))
+            .transpose()?;
+        match existing {
+            Some(value) if value == command => (),
+            Some(_) => edit.conflicts.push(name.to_string()),
+            None => {
+                edit.added.push(name.to_string());
+                missing.push((
+                    name.to_string(),
+                    serde_json::Value::from(command).to_string(),
+                ));
+            }
+        }
+    }
+    if missing.is_empty() {
+        return Ok(edit);
+    }
+
+    edit.text = Some(match (scripts_member, scripts) {
+        (Some(member), Some(scripts)) => {
+            let indent = indent_at(text, member.start).unwrap_or_default();
+            insert_members(text, &scripts, indent, &layout, &missing)
+        }
+        _ => {
+            // a new `scripts` object at the end of the document
+            let indent = format!("{}{}", layout.unit, layout.unit);
+            let value = format!(
+                "{{{}{}{}}}",
+                missing
+                    .iter()
+                    .map(|(k, v)| format!(
+                        "{}{}{}: {}",
+                        layout.newline,
+                        indent,
+                        serde_json::Value::from(k.as_str()),
+                        v
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                layout.newline,
+                layout.unit
+            );
+            insert_members(text, &root, "", &layout, &[("scripts".to_string(), value)])
+        }
+    });
+    Ok(edit)
+}
+
+/// Removes the scripts `names` from the package.json `text` when they still run the command
+/// autopack added, and `scripts` itself once empty
+pub(crate) fn remove_scripts(text: &str, names: &[String]) -> anyhow::Result<String> {
+    let mut text = text.to_string();
+    for name in names {
+        let command = match SCRIPTS.iter().find(|(n, _)| n == name) {
+            Some((_, command)) => *command,
+            None => continue,
+        };
+        let root = root(&text)?;
+        let scripts_index = match root.members.iter().position(|m| m.key == "scripts") {
+            Some(i) => i,
+            None => break,
+        };
+        let scripts_member = &root.members[scripts_index];
+        if text.as_bytes()[scripts_member.value_start] != b'{' {
+            break;
+        }
+        let scripts = object(&text, scripts_member.value_start)?;
+        let index = scripts.members.iter().position(|m| {
+            m.key == *name
+                && serde_json::from_str::<serde_json::Value>(&text[m.value_start..m.end])
+                    .map(|v| v == command)
+                    .unwrap_or(false)
+        });
+        if let Some(index) = index {
+            text = if scripts.members.len() == 1 {
+                remove_member(&text, &root, scripts_index)
+            } else {
+                remove_member(&text, &scripts, index)
+            };
+        }
+    }
+    Ok(text)
+}
+
+fn ignores_runtime_dir(line: &str) -> bool {
+    matches!(
+        line.trim(),
+        ".autopack" | ".autopack/" | "/.autopack" | "/.autopack/"
+    )
+}
+
+/// `.gitignore` text ignoring the runtime folder, `None` when it already is
+pub(crate) fn add_gitignore(text: &str) -> Option<String> {
+    if text.lines().any(ignores_runtime_dir) {
+        return None;
+    }
+    let newline = line_ending(text);
+    let mut text = text.to_string();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push_str(newline);
+    }
+    text.push_str(&format!(
+        "{}{}{}{}",
+        GITIGNORE_MARKER, newline, GITIGNORE_ENTRY, newline
+    ));
+    Some(text)
+}
+
+/// `.gitignore` text without the entry autopack added
+pub(crate) fn remove_gitignore(text: &str) -> String {
+    let lines = text.split_inclusive('\n').collect::<Vec<_>>();
+    let position = lines
+        .windows(2)
+        .position(|w| w[0].trim_end() == GITIGNORE_MARKER && w[1].trim_end() == GITIGNORE_ENTRY);
+    match position {
+        Some(i) => lines
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i && *j != i + 1)
+            .map(|(_, l)| *l)
+            .collect(),
+        None => text.to_string(),
+    }
+}
+
+/// Whether `dir` is within a git work tree
+pub(crate) fn in_git_repo(dir: &Path) -> bool {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors().any(|d| d.join(".git").exists())
+}
+
+/// Edit of package.json adding the scripts, along with the scripts it adds
+pub(crate) fn scripts_edit(project_dir: &Path) -> anyhow::Result<(Option<FileEdit>, ScriptsEdit)> {
+    let path = project_dir.join("package.json");
+    let before = fs::read_to_string(&path)?;
+    let mut edit = add_scripts(&before)?;
+    let file_edit = edit.text.take().map(|after| FileEdit {
+        path,
+        before,
+        after,
+    });
+    Ok((file_edit, edit))
+}
+
+/// Edit of `.gitignore` ignoring the runtime folder if it isn't yet, along with whether
+/// the entry is autopack's
+pub(crate) fn gitignore_edit(project_dir: &Path) -> io::Result<(Option<FileEdit>, bool)> {
+    let path = project_dir.join(".gitignore");
+    let before = read_optional(&path)?;
+    match add_gitignore(&before) {
+        Some(after) => Ok((
+            Some(FileEdit {
+                path,
+                before,
+                after,
+            }),
+            true,
+        )),
+        None => {
+            let managed = remove_gitignore(&before) != before;
+            Ok((None, managed))
+        }
+    }
+}
+
+impl Scaffold {
+    /// Edits reverting the scaffold of the project in `project_dir`
+    pub(crate) fn revert(&self, project_dir: &Path) -> anyhow::Result<Vec<FileEdit>> {
+        let mut edits = vec![];
+        if !self.scripts.is_empty() {
+            let path = project_dir.join("package.json");
+            let before = read_optional(&path)?;
+            if !before.is_empty() {
+                let after = remove_scripts(&before, &self.scripts)?;
+                edits.push(FileEdit {
+                    path,
+                    before,
+                    after,
+                });
+            }
+        }
+        if self.gitignore {
+            let path = project_dir.join(".gitignore");
+            let before = read_optional(&path)?;
+            let after = remove_gitignore(&before);
+            edits.push(FileEdit {
+                path,
+                before,
+                after,
+            });
+        }
+        Ok(edits.into_iter().filter(|e| e.before != e.after).collect())
+    }
+}
@@ -0,0 +1,128 @@
+use super::{add_gitignore, add_scripts, remove_gitignore, remove_scripts, Scaffold};
+use std::fs;
+use tempfile::TempDir;
+
+const PACKAGE_JSON: &str = r#"{
+    "name": "web",
+    "version": "0.1.0",
+    "scripts": {
+        "start": "react-scripts start",
+        "build": "react-scripts build"
+    },
+    "dependencies": {
+        "react": "^18.2.0"
+    }
+}
+"#;
+
+fn managed() -> Vec<String> {
+    vec!["build:pack".to_string(), "start:pack".to_string()]
+}
+
+#[test]
+fn adds_scripts_in_place() {
+    let edit = add_scripts(PACKAGE_JSON).unwrap();
+    let text = edit.text.unwrap();
+    assert_eq!(
+        text,
+        r#"{
+    "name": "web",
+    "version": "0.1.0",
+    "scripts": {
+        "start": "react-scripts start",
+        "build": "react-scripts build",
+        "build:pack": "auto-pack build",
+        "start:pack": "auto-pack run"
+    },
+    "dependencies": {
+        "react": "^18.2.0"
+    }
+}
+"#
+    );
+    assert_eq!(edit.added, managed());
+
+    // idempotent, and scripts already there aren't claimed for `deinit`
+    let again = add_scripts(&text).unwrap();
+    assert_eq!(again.text, None);
+    assert!(again.added.is_empty());
+
+    assert_eq!(remove_scripts(&text, &managed()).unwrap(), PACKAGE_JSON);
+}
+
+#[test]
+fn adds_a_scripts_object() {
+    let text = "{\r\n\t\"name\": \"web\"\r\n}";
+    let added = add_scripts(text).unwrap().text.unwrap();
+    assert_eq!(
+        added,
+        "{\r\n\t\"name\": \"web\",\r\n\t\"scripts\": {\r\n\t\t\"build:pack\": \"auto-pack build\",\
+        \r\n\t\t\"start:pack\": \"auto-pack run\"\r\n\t}\r\n}"
+    );
+    serde_json::from_str::<serde_json::Value>(&added).unwrap();
+    assert_eq!(remove_scripts(&added, &managed()).unwrap(), text);
+
+    let empty = "{\n  \"scripts\": {}\n}\n";
+    let added = add_scripts(empty).unwrap().text.unwrap();
+    assert_eq!(
+        added,
+        "{\n  \"scripts\": {\n    \"build:pack\": \"auto-pack build\",\n    \"start:pack\": \"auto-pack run\"\n  }\n}\n"
+    );
+}
+
+#[test]
+fn leaves_scripts_of_the_user_alone() {
+    let text = r#"{"scripts": {"build:pack": "make image", "x": "a \"}\" b"}}"#;
+    let edit = add_scripts(text).unwrap();
+    assert_eq!(edit.conflicts, ["build:pack"]);
+    assert_eq!(edit.added, ["start:pack"]);
+    let added = edit.text.unwrap();
+    assert_eq!(
+        added,
+        r#"{"scripts": {"build:pack": "make image", "x": "a \"}\" b", "start:pack": "auto-pack run"}}"#
+    );
+    // only scripts still running the autopack command are removed
+    assert_eq!(remove_scripts(&added, &managed()).unwrap(), text);
+
+    assert!(add_scripts("[]").is_err());
+    assert!(add_scripts(r#"{"scripts": "build"}"#).is_err());
+    assert!(add_scripts("{\"name\": ").is_err());
+}
+
+#[test]
+fn manages_the_gitignore_entry() {
+    assert_eq!(add_gitignore("").unwrap(), "# autopack\n.autopack/\n");
+    let added = add_gitignore("node_modules\n/build").unwrap();
+    assert_eq!(added, "node_modules\n/build\n# autopack\n.autopack/\n");
+    assert_eq!(add_gitignore(&added), None);
+    assert_eq!(add_gitignore("/.autopack\n"), None);
+    assert_eq!(remove_gitignore(&added), "node_modules\n/build\n");
+    // entries of the user stay
+    assert_eq!(remove_gitignore(".autopack/\n"), ".autopack/\n");
+}
+
+#[test]
+fn reverts_the_scaffold() {
+    let dir = TempDir::new().unwrap();
+    let package_json = dir.path().join("package.json");
+    fs::write(
+        &package_json,
+        add_scripts(PACKAGE_JSON).unwrap().text.unwrap(),
+    )
+    .unwrap();
+    fs::write(dir.path().join(".gitignore"), "# autopack\n.autopack/\n").unwrap();
+
+    let scaffold = Scaffold {
+        scripts: managed(),
+        gitignore: true,
+    };
+    let edits = scaffold.revert(dir.path()).unwrap();
+    assert_eq!(edits.len(), 2);
+    edits.iter().for_each(|e| e.write().unwrap());
+    assert_eq!(fs::read_to_string(package_json).unwrap(), PACKAGE_JSON);
+    assert_eq!(
+        fs::read_to_string(dir.path().join(".gitignore")).unwrap(),
+        ""
+    );
+    assert!(scaffold.revert(dir.path()).unwrap().is_empty());
+}
@@ -0,0 +1,319 @@
+mod reload;
+#[cfg(test)]
+mod tests;
+
+pub(crate) use self::reload::RELOAD_PORT;
+use self::reload::{inject_script, ReloadServer};
+use crate::{
+    autopack::AutoPack,
+    docker::Docker,
+    fingerprint::{list_changes, Change, FileFilter, Fingerprint},
+    log::{command_err, instruct, success, trying, warn},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::process::{Child, Command};
+use tracing::debug;
+
+const BUILD_DIR: &str = "build";
+const INDEX_HTML: &str = "index.html";
+const REBUILD_LOG: &str = "sync-build.log";
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long to wait for the container to come up, in poll intervals
+const STARTUP_POLLS: usize = 120;
+const MAX_LISTED_CHANGES: usize = 10;
+
+/// Parses `sha256sum` output run within the build directory into per-file hashes
+fn parse_sha256sum(out: &str) -> Fingerprint {
+    let mut builder = Fingerprint::builder();
+    for line in out.lines() {
+        if let Some((hash, path)) = line.split_once("  ") {
+            builder.hash(path.trim_start_matches("./"), hash);
+        }
+    }
+    builder.build()
+}
+
+/// Project sources whose changes trigger a host build
+fn source_filter() -> FileFilter {
+    FileFilter::new(
+        vec![],
+        ["node_modules", BUILD_DIR, "dist"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+    )
+}
+
+/// Image rebuild running in the background while files are synced, so that the image
+/// catches up with what the container serves
+struct BackgroundBuild {
+    argv: Vec<String>,
+    log: PathBuf,
+    child: Option<Child>,
+    pending: bool,
+}
+
+impl BackgroundBuild {
+    fn request(&mut self) {
+        if self.child.is_some() {
+            self.pending = true;
+        } else if let Err(e) = self.spawn() {
+            warn(&format!(
+                "Failed starting the background image build :: {}",
+                e
+            ));
+        }
+    }
+
+    fn spawn(&mut self) -> anyhow::Result<()> {
+        let (program, args) = self
+            .argv
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("No build command"))?;
+        let log = File::create(&self.log)?;
+        let child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .kill_on_drop(true)
+            .spawn()?;
+        debug!("Rebuilding the image in the background :: {:?}", self.argv);
+        self.child = Some(child);
+        Ok(())
+    }
+
+    /// Reports a finished build and starts the one requested meanwhile
+    fn poll(&mut self) {
+        let status = match self.child.as_mut().map(Child::try_wait) {
+            Some(Ok(Some(status))) => status,
+            Some(Err(e)) => {
+                debug!("Failed checking the background image build :: {}", e);
+                return;
+            }
+            _ => return,
+        };
+        self.child = None;
+        if status.success() {
+            success("Image rebuilt in the background");
+        } else {
+            warn(&format!(
+                "Background image build failed, see {}",
+                self.log.display()
+            ));
+        }
+        if self.pending {
+            self.pending = false;
+            self.request();
+        }
+    }
+}
+
+/// Builds the project on the host and copies changed build output into the running container
+pub(crate) struct ArtifactSync {
+    docker: Docker,
+    container: String,
+    project_dir: PathBuf,
+    build_command: String,
+    container_build_dir: String,
+    reload_port: u16,
+    rebuild: BackgroundBuild,
+}
+
+impl ArtifactSync {
+    /// `rebuild` is the command line rebuilding the image in the background
+    pub(crate) fn new(autopack: &AutoPack, reload_port: u16, rebuild: Vec<String>) -> Self {
+        let project = &autopack.client_project;
+        ArtifactSync {
+            docker: autopack.docker.clone(),
+            container: project.image_name.clone(),
+            project_dir: project.path.clone(),
+            build_command: project
+                .package_manager
+                .manager
+                .install_and_build()
+                .1
+                .to_string(),
+            container_build_dir: format!("{}/{}", project.container_app_dir(), BUILD_DIR),
+            reload_port,
+            rebuild: BackgroundBuild {
+                argv: rebuild,
+                log: autopack.runtime.dir().join(REBUILD_LOG),
+                child: None,
+                pending: false,
+            },
+        }
+    }
+
+    /// Syncs on every source change until an error stops it
+    pub(crate) async fn watch(mut self) -> anyhow::Result<()> {
+        let server = ReloadServer::bind(self.reload_port).await?;
+        self.reload_port = server.port()?;
+        let reload = server.notifier();
+        tokio::spawn(server.serve());
+
+        self.wait_for_container().await?;
+        let mut served = self.served().await;
+        let mut sources = None;
+        loop {
+            let current = Fingerprint::builder()
+                .context(&self.project_dir, &source_filter())?
+                .build();
+            if sources.as_ref() != Some(&current.digest) {
+                sources = Some(current.digest);
+                match self.build_on_host().await {
+                    Ok(()) => {
+                        served = self.swap(&served).await?;
+                        // no browser connected yet is fine
+                        let _ = reload.send(());
+                        self.rebuild.request();
+                    }
+                    Err(e) => warn(&format!("{}; keeping the served files", e)),
+                }
+            }
+            self.rebuild.poll();
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn wait_for_container(&self) -> anyhow::Result<()> {
+        for _ in 0..STARTUP_POLLS {
+            if self.docker.exec(&self.container, &["true"]).await.is_ok() {
+                return Ok(());
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        anyhow::bail!("Container {} did not start", self.container)
+    }
+
+    /// Manifest of the build output the container serves; empty if it can't be read,
+    /// which copies everything on the first sync
+    async fn served(&self) -> Fingerprint {
+        let cmd = format!(
+            "cd {} && find . -type f -exec sha256sum {{}} +",
+            self.container_build_dir
+        );
+        match self.docker.exec(&self.container, &["sh", "-c", &cmd]).await {
+            Ok(out) => parse_sha256sum(&out),
+            Err(e) => {
+                debug!("Failed reading the served files :: {}", e);
+                Fingerprint::default()
+            }
+        }
+    }
+
+    async fn build_on_host(&self) -> anyhow::Result<()> {
+        trying(&format!("Running `{}`", self.build_command));
+        #[cfg(not(target_os = "windows"))]
+        let mut cmd = Command::new("sh");
+        #[cfg(not(target_os = "windows"))]
+        cmd.arg("-c");
+        #[cfg(target_os = "windows")]
+        let mut cmd = Command::new("cmd");
+        #[cfg(target_os = "windows")]
+        cmd.arg("/C");
+
+        let out = cmd
+            .arg(&self.build_command)
+            .current_dir(&self.project_dir)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed running `{}` :: {}", self.build_command, e))?;
+        if !out.status.success() {
+            String::from_utf8_lossy(&out.stderr)
+                .lines()
+                .chain(String::from_utf8_lossy(&out.stdout).lines())
+                .for_each(command_err);
+            anyhow::bail!("`{}` failed", self.build_command);
+        }
+        Ok(())
+    }
+
+    /// Copies the build output which differs from what's served, returning the new manifest
+    async fn swap(&self, served: &Fingerprint) -> anyhow::Result<Fingerprint> {
+        let build_dir = self.project_dir.join(BUILD_DIR);
+        // the reload script only goes into the container's copy, the host build stays untouched
+        let injected = fs::read_to_string(build_dir.join(INDEX_HTML))
+            .ok()
+            .and_then(|html| inject_script(&html, self.reload_port))
+            .map(|html| -> anyhow::Result<_> {
+                let mut file = tempfile::NamedTempFile::new()?;
+                file.write_all(html.as_bytes())?;
+                // `docker cp` keeps the private mode of temporary files
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(file.path(), fs::Permissions::from_mode(0o644))?;
+                }
+                Ok((file, hex::encode(Sha256::digest(html.as_bytes()))))
+            })
+            .transpose()?;
+
+        let mut builder = Fingerprint::builder();
+        builder.context(&build_dir, &FileFilter::default())?;
+        if let Some((_, hash)) = injected.as_ref() {
+            builder.hash(INDEX_HTML, hash);
+        }
+        let built = builder.build();
+        let changes = built.changes(served);
+        if changes.is_empty() {
+            instruct("Build output unchanged");
+            return Ok(built);
+        }
+
+        let (mut copied, mut removed) = (vec![], vec![]);
+        for change in changes.iter() {
+            match change {
+                Change::Added(f) | Change::Modified(f) => copied.push(f.as_str()),
+                Change::Removed(f) => removed.push(f.as_str()),
+            }
+        }
+        let container_path = |f: &str| format!("{}/{}", self.container_build_dir, f);
+
+        let dirs = copied
+            .iter()
+            .filter_map(|f| Path::new(f).parent())
+            .filter(|d| !d.as_os_str().is_empty())
+            .map(|d| container_path(&d.to_string_lossy()))
+            .collect::<BTreeSet<_>>();
+        if !dirs.is_empty() {
+            let mut args = vec!["mkdir", "-p"];
+            args.extend(dirs.iter().map(String::as_str));
+            self.docker.exec(&self.container, &args).await?;
+        }
+        for file in copied.iter() {
+            let host_file = match injected.as_ref() {
+                Some((index, _)) if *file == INDEX_HTML => index.path().to_path_buf(),
+                _ => build_dir.join(file),
+            };
+            self.docker
+                .copy_into(&self.container, &host_file, &container_path(file))
+                .await?;
+        }
+        if !removed.is_empty() {
+            let paths = removed
+                .iter()
+                .map(|f| container_path(f))
+                .collect::<Vec<_>>();
+            let mut args = vec!["rm", "-f"];
+            args.extend(paths.iter().map(String::as_str));
+            self.docker.exec(&self.container, &args).await?;
+        }
+
+        success(&format!(
+            "Synced {} file(s) into the container",
+            changes.len()
+        ));
+        list_changes(&changes, MAX_LISTED_CHANGES);
+        Ok(built)
+    }
+}
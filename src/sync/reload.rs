@@ -0,0 +1,103 @@
+use std::net::SocketAddr;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+use tracing::debug;
+
+/// Default port of the reload server, the one livereload clients commonly use
+pub(crate) const RELOAD_PORT: u16 = 35729;
+
+const MARKER: &str = "<!-- autopack reload -->";
+
+/// Adds a script reloading the page on every sync to `index.html`, unless already there
+pub(crate) fn inject_script(index: &str, port: u16) -> Option<String> {
+    if index.contains(MARKER) {
+        return None;
+    }
+    let script = format!(
+        r#"{}<script>new EventSource("//" + location.hostname + ":{}/events").onmessage = function () {{ location.reload(); }};</script>"#,
+        MARKER, port
+    );
+    Some(match index.rfind("</body>") {
+        Some(at) => format!("{}{}{}", &index[..at], script, &index[at..]),
+        None => format!("{}{}", index, script),
+    })
+}
+
+/// Server-sent events endpoint telling connected browsers to reload
+pub(crate) struct ReloadServer {
+    listener: TcpListener,
+    events: broadcast::Sender<()>,
+}
+
+impl ReloadServer {
+    /// Listens on the loopback interface only, the browser runs on this machine
+    pub(crate) async fn bind(port: u16) -> anyhow::Result<ReloadServer> {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port)))
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!("Failed binding the reload server to {} :: {}", port, e)
+            })?;
+        let (events, _) = broadcast::channel(16);
+        Ok(ReloadServer { listener, events })
+    }
+
+    pub(crate) fn port(&self) -> anyhow::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    /// Sender reloading all connected browsers
+    pub(crate) fn notifier(&self) -> broadcast::Sender<()> {
+        self.events.clone()
+    }
+
+    pub(crate) async fn serve(self) {
+        loop {
+            match self.listener.accept().await {
+                Ok((stream, _)) => {
+                    let events = self.events.subscribe();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle(stream, events).await {
+                            debug!("Reload client disconnected :: {}", e);
+                        }
+                    });
+                }
+                Err(e) => debug!("Failed accepting reload client :: {}", e),
+            }
+        }
+    }
+}
+
+async fn handle(stream: TcpStream, mut events: broadcast::Receiver<()>) -> anyhow::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut request = String::new();
+    stream.read_line(&mut request).await?;
+    // skip the headers
+    let mut header = String::new();
+    while stream.read_line(&mut header).await? > 2 {
+        header.clear();
+    }
+
+    let mut stream = stream.into_inner();
+    if request.split(' ').nth(1) != Some("/events") {
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+            .await?;
+        return Ok(());
+    }
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\n\r\n",
+        )
+        .await?;
+    loop {
+        match events.recv().await {
+            Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                stream.write_all(b"data: reload\n\n").await?
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use super::{
+    parse_sha256sum,
+    reload::{inject_script, ReloadServer},
+};
+use crate::fingerprint::Change;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+#[test]
+fn parses_the_served_manifest() {
+    let served =
+        parse_sha256sum("3b5d  ./index.html\n9f86  ./static/js/main.js\n\nnot a manifest line\n");
+    assert_eq!(
+        served.inputs.keys().collect::<Vec<_>>(),
+        vec!["index.html", "static/js/main.js"]
+    );
+
+    let built = parse_sha256sum("3b5d  ./index.html\n1a2b  ./static/js/main.2.js\n");
+    assert_eq!(
+        built.changes(&served),
+        vec![
+            Change::Added("static/js/main.2.js".to_string()),
+            Change::Removed("static/js/main.js".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn injects_the_reload_script_once() {
+    let html = inject_script("<html><body><div id=\"root\"></div></body></html>", 4000).unwrap();
+    assert!(html.contains(":4000/events"));
+    assert!(html.ends_with("</script></body></html>"));
+    assert_eq!(inject_script(&html, 4000), None);
+
+    assert!(inject_script("<div></div>", 4000)
+        .unwrap()
+        .starts_with("<div></div><!-- autopack reload -->"));
+}
+
+#[tokio::test]
+async fn notifies_connected_browsers() {
+    let server = ReloadServer::bind(0).await.unwrap();
+    let port = server.port().unwrap();
+    let reload = server.notifier();
+    tokio::spawn(server.serve());
+
+    let mut missing = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    missing
+        .write_all(b"GET /nope HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    missing.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 404"));
+
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    client
+        .write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut received = String::new();
+    let mut buf = [0; 512];
+    while !received.contains("data: reload") {
+        // the subscription may not be registered before the first send
+        reload.send(()).ok();
+        let n = tokio::time::timeout(std::time::Duration::from_millis(200), client.read(&mut buf))
+            .await
+            .unwrap_or(Ok(0))
+            .unwrap();
+        received.push_str(&String::from_utf8_lossy(&buf[..n]));
+    }
+    assert!(received.starts_with("HTTP/1.1 200 OK"));
+    assert!(received.contains("text/event-stream"));
+}
@@ -0,0 +1,443 @@
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    error::AppError,
+    lock::{write_atomic, FileLock, LockMode},
+    log,
+};
+use futures_util::StreamExt;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use reqwest::{header, StatusCode};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Overrides the user level cache directory, e.g. for CI caches
+const CACHE_DIR_ENV: &str = "AUTOPACK_CACHE_DIR";
+
+const DOWNLOADS_DIR: &str = "downloads";
+
+/// Projects using each cached tool, as `<root>/refs/<tool>/<version>/<project hash>`
+const REFS_DIR: &str = "refs";
+
+/// User level autopack cache, `$XDG_CACHE_HOME/autopack` on Linux unless overridden
+pub(crate) fn cache_root() -> anyhow::Result<PathBuf> {
+    env::var_os(CACHE_DIR_ENV)
+        .map(PathBuf::from)
+        .or_else(|| dirs::cache_dir().map(|d| d.join("autopack")))
+        .ok_or_else(|| anyhow::anyhow!("Could not locate the user cache directory"))
+}
+
+/// A tool version installed in the cache
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CachedTool {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    pub(crate) path: PathBuf,
+    pub(crate) size: u64,
+}
+
+/// User level cache of the tools autopack drives (e.g. pack), shared across projects.
+///
+/// Tools are installed as `<root>/<tool>/<version>/` and downloads in progress are
+/// kept as `<root>/downloads/<artifact>.part` so that they can be resumed. Projects
+/// using a tool hold a ref to it, which keeps it from being pruned.
+#[derive(Clone)]
+pub(crate) struct ToolCache {
+    root: PathBuf,
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn dir_size(path: &Path) -> u64 {
+    fs::read_dir(path)
+        .map(|rd| {
+            rd.filter_map(Result::ok)
+                .map(|e| match e.file_type() {
+                    Ok(t) if t.is_dir() => dir_size(&e.path()),
+                    _ => e.metadata().map(|m| m.len()).unwrap_or(0),
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+fn sha256_file(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn progress_bar(total: Option<u64>, msg: &str) -> ProgressBar {
+    let bar = match total {
+        Some(t) => {
+            let bar = ProgressBar::new(t);
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template(
+                        "[{elapsed_precise}] |{bar:40.cyan/blue}| {bytes:>7}/{total_bytes:7} {msg} [{eta}]",
+                    )
+                    .unwrap()
+                    .progress_chars("█▛▌▖  "),
+            );
+            bar
+        }
+        // servers may not send a content length
+        None => ProgressBar::new_spinner(),
+    };
+    if !log::shows_progress() {
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    bar.set_message(msg.to_string());
+    bar
+}
+
+impl ToolCache {
+    pub(crate) fn new() -> anyhow::Result<ToolCache> {
+        Ok(ToolCache {
+            root: cache_root()?.join("tools"),
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn at(root: &Path) -> ToolCache {
+        ToolCache {
+            root: root.to_path_buf(),
+        }
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub(crate) fn tool_dir(&self, tool: &str, version: &str) -> PathBuf {
+        self.root.join(tool).join(version)
+    }
+
+    fn downloads_dir(&self) -> PathBuf {
+        self.root.join(DOWNLOADS_DIR)
+    }
+
+    pub(crate) fn list(&self) -> anyhow::Result<Vec<CachedTool>> {
+        if !self.root.exists() {
+            return Ok(vec![]);
+        }
+        let mut tools = vec![];
+        for tool in fs::read_dir(&self.root)?.filter_map(Result::ok) {
+            let name = tool.file_name().to_string_lossy().to_string();
+            if name == DOWNLOADS_DIR || name == REFS_DIR || !tool.path().is_dir() {
+                continue;
+            }
+            for version in fs::read_dir(tool.path())?.filter_map(Result::ok) {
+                let version_name = version.file_name().to_string_lossy().to_string();
+                // skip installs in progress
+                if version.path().is_dir() && !version_name.starts_with('.') {
+                    tools.push(CachedTool {
+                        name: name.clone(),
+                        version: version_name,
+                        size: dir_size(&version.path()),
+                        path: version.path(),
+                    });
+                }
+            }
+        }
+        tools.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        Ok(tools)
+    }
+
+    pub(crate) fn remove(&self, tool: &str, version: &str) -> anyhow::Result<()> {
+        let dir = self.tool_dir(tool, version);
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+
+    fn ref_path(&self, tool: &str, version: &str, project_root: &Path) -> PathBuf {
+        let root = project_root
+            .canonicalize()
+            .unwrap_or_else(|_| project_root.to_path_buf());
+        let hash = hex::encode(Sha256::digest(root.to_string_lossy().as_bytes()));
+        self.root
+            .join(REFS_DIR)
+            .join(tool)
+            .join(version)
+            .join(&hash[..16])
+    }
+
+    /// Records that the project at `project_root` uses the tool
+    pub(crate) fn add_ref(
+        &self,
+        tool: &str,
+        version: &str,
+        project_root: &Path,
+    ) -> anyhow::Result<()> {
+        let path = self.ref_path(tool, version, project_root);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let root = project_root
+            .canonicalize()
+            .unwrap_or_else(|_| project_root.to_path_buf());
+        write_atomic(&path, root.to_string_lossy().as_bytes())?;
+        Ok(())
+    }
+
+    /// Drops the ref of the project at `project_root`; `false` if it had none
+    pub(crate) fn remove_ref(
+        &self,
+        tool: &str,
+        version: &str,
+        project_root: &Path,
+    ) -> anyhow::Result<bool> {
+        match fs::remove_file(self.ref_path(tool, version, project_root)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Roots of the projects using the tool which still exist
+    pub(crate) fn refs(&self, tool: &str, version: &str) -> Vec<PathBuf> {
+        let dir = self.root.join(REFS_DIR).join(tool).join(version);
+        fs::read_dir(dir)
+            .map(|rd| {
+                rd.filter_map(Result::ok)
+                    .filter_map(|e| fs::read_to_string(e.path()).ok())
+                    .map(|root| PathBuf::from(root.trim()))
+                    .filter(|root| root.exists())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Removes cached tools not matching `keep` as well as leftover partial downloads
+    pub(crate) fn prune<F>(&self, keep: F) -> anyhow::Result<Vec<CachedTool>>
+    where
+        F: Fn(&CachedTool) -> bool,
+    {
+        let removed = self
+            .list()?
+            .into_iter()
+            .filter(|t| !keep(t))
+            .map(|t| fs::remove_dir_all(&t.path).map(|_| t))
+            .collect::<Result<Vec<_>, _>>()?;
+        for t in removed.iter() {
+            let refs = self.root.join(REFS_DIR).join(&t.name).join(&t.version);
+            if refs.exists() {
+                fs::remove_dir_all(refs)?;
+            }
+        }
+
+        self.prune_downloads()?;
+        Ok(removed)
+    }
+
+    /// Removes the partial downloads no other process is working on
+    fn prune_downloads(&self) -> anyhow::Result<()> {
+        let dir = self.downloads_dir();
+        let artifacts = match fs::read_dir(&dir) {
+            Ok(rd) => rd
+                .filter_map(Result::ok)
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter_map(|name| {
+                    [".part", ".lock"]
+                        .iter()
+                        .find_map(|suffix| name.strip_suffix(suffix).map(str::to_string))
+                })
+                .collect::<BTreeSet<_>>(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        for artifact in artifacts {
+            let lock_path = dir.join(format!("{}.lock", artifact));
+            let lock = match FileLock::acquire(&lock_path, LockMode::Exclusive, false) {
+                Ok(lock) => lock,
+                Err(AppError::Locked(holder, _)) => {
+                    debug!("Keeping the download of {} in use by {}", artifact, holder);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            remove_if_exists(&dir.join(format!("{}.part", artifact)))?;
+            // the lock file can't be removed while open on Windows
+            drop(lock);
+            remove_if_exists(&lock_path)?;
+            remove_if_exists(&FileLock::holder_path(&lock_path))?;
+        }
+        // whatever is left belongs to downloads in progress
+        let _ = fs::remove_dir(&dir);
+        Ok(())
+    }
+
+    /// Downloads `url` into the cache, resuming a previous partial download, and verifies it
+    /// against the SHA-256 published at `<url>.sha256` before handing it out.
+    pub(crate) async fn download_verified(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+    ) -> anyhow::Result<PathBuf> {
+        let artifact = url
+            .rsplit('/')
+            .next()
+            .filter(|a| !a.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("No artifact name in {}", url))?;
+        fs::create_dir_all(self.downloads_dir())?;
+        let part = self.downloads_dir().join(format!("{}.part", artifact));
+        // projects installing the same artifact at once take turns on the partial download
+        let _lock = FileLock::acquire(
+            &self.downloads_dir().join(format!("{}.lock", artifact)),
+            LockMode::Exclusive,
+            true,
+        )?;
+
+        let expected = {
+            let checksum_url = format!("{}.sha256", url);
+            let res = client.get(&checksum_url).send().await?.error_for_status()?;
+            res.text()
+                .await?
+                .split_whitespace()
+                .next()
+                .map(|s| s.to_lowercase())
+                .ok_or_else(|| anyhow::anyhow!("Empty checksum at {}", checksum_url))?
+        };
+
+        let offset = part.metadata().map(|m| m.len()).unwrap_or(0);
+        let mut request = client.get(url);
+        if offset > 0 {
+            debug!("Resuming download of {} from byte {}", artifact, offset);
+            request = request.header(header::RANGE, format!("bytes={}-", offset));
+        }
+        let response = request.send().await?;
+
+        let (mut file, offset) = match response.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                (fs::OpenOptions::new().append(true).open(&part)?, offset)
+            }
+            // the whole artifact is there already
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                (fs::OpenOptions::new().append(true).open(&part)?, offset)
+            }
+            s if s.is_success() => (fs::File::create(&part)?, 0),
+            s => anyhow::bail!("Downloading {} failed with status {}", url, s),
+        };
+
+        if response.status() != StatusCode::RANGE_NOT_SATISFIABLE {
+            let expected_len = response.content_length().map(|l| l + offset);
+            let bar = progress_bar(expected_len, &format!("Downloading {}", artifact));
+            bar.set_position(offset);
+
+            let mut written = offset;
+            let mut stream = response.bytes_stream();
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk?;
+                file.write_all(&chunk).map_err(|e| {
+                    anyhow::anyhow!("Error while writing content to file. Error {}", e)
+                })?;
+                written += chunk.len() as u64;
+                bar.set_position(written);
+            }
+            file.flush()?;
+            bar.finish_and_clear();
+
+            if let Some(len) = expected_len.filter(|l| *l != written) {
+                anyhow::bail!(
+                    "Download of {} incomplete :: expected {} bytes, got {}",
+                    artifact,
+                    len,
+                    written
+                );
+            }
+        }
+
+        let actual = sha256_file(&part)?;
+        if actual != expected {
+            fs::remove_file(&part)?;
+            anyhow::bail!(
+                "Checksum mismatch for {} :: expected {}, got {}",
+                artifact,
+                expected,
+                actual
+            );
+        }
+        debug!("Verified {} with sha256 {}", artifact, actual);
+        Ok(part)
+    }
+
+    /// Verifies a local artifact against the `<artifact>.sha256` next to it, if any
+    pub(crate) fn verify_local(&self, artifact: &Path) -> anyhow::Result<()> {
+        let mut checksum = artifact.as_os_str().to_owned();
+        checksum.push(".sha256");
+        let expected = match fs::read_to_string(&checksum) {
+            Ok(c) => c
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_lowercase(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("No checksum next to {:?}, skipping verification", artifact);
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let actual = sha256_file(artifact)?;
+        if actual != expected {
+            anyhow::bail!(
+                "Checksum mismatch for {:?} :: expected {}, got {}",
+                artifact,
+                expected,
+                actual
+            );
+        }
+        Ok(())
+    }
+
+    /// Populates `<root>/<tool>/<version>` through `populate` in a staging directory which is
+    /// then renamed into place, so that a partially extracted tool is never picked up.
+    pub(crate) fn install_atomically<F>(
+        &self,
+        tool: &str,
+        version: &str,
+        populate: F,
+    ) -> anyhow::Result<PathBuf>
+    where
+        F: FnOnce(&Path) -> anyhow::Result<()>,
+    {
+        let target = self.tool_dir(tool, version);
+        let parent = target
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid tool directory {:?}", target))?;
+        fs::create_dir_all(parent)?;
+
+        let staging = tempfile::Builder::new()
+            .prefix(&format!(".{}-", version))
+            .tempdir_in(parent)?;
+        populate(staging.path())?;
+
+        match fs::rename(staging.path(), &target) {
+            Ok(_) => Ok(target),
+            // another autopack process finished the same install first
+            Err(_) if target.is_dir() => Ok(target),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Drops a partial download once its artifact is installed
+    pub(crate) fn remove_download(&self, download: &Path) {
+        if download.starts_with(self.downloads_dir()) {
+            let _ = fs::remove_file(download);
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use super::ToolCache;
+use crate::lock::{FileLock, LockMode};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Arc, Mutex},
+};
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// Minimal HTTP server serving `files` with support for `Range: bytes=<from>-`,
+/// recording the range of every request it answers
+struct FixtureServer {
+    base: String,
+    ranges: Arc<Mutex<Vec<Option<u64>>>>,
+}
+
+impl FixtureServer {
+    async fn start(files: HashMap<String, Vec<u8>>) -> FixtureServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let ranges = Arc::new(Mutex::new(vec![]));
+        let recorded = ranges.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or("/");
+                let range = request
+                    .lines()
+                    .find_map(|l| {
+                        l.to_lowercase()
+                            .strip_prefix("range: bytes=")
+                            .map(String::from)
+                    })
+                    .and_then(|r| r.trim_end_matches('-').parse::<u64>().ok());
+                recorded.lock().unwrap().push(range);
+
+                let (head, body) = match (files.get(path), range) {
+                    (None, _) => ("404 Not Found".to_string(), vec![]),
+                    (Some(f), Some(from)) if from as usize >= f.len() => {
+                        ("416 Range Not Satisfiable".to_string(), vec![])
+                    }
+                    (Some(f), Some(from)) => (
+                        format!(
+                            "206 Partial Content\r\nContent-Range: bytes {}-{}/{}",
+                            from,
+                            f.len() - 1,
+                            f.len()
+                        ),
+                        f[from as usize..].to_vec(),
+                    ),
+                    (Some(f), None) => ("200 OK".to_string(), f.clone()),
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    head,
+                    body.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.write_all(&body).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+
+        FixtureServer { base, ranges }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base, path)
+    }
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Release-like tarball holding a `pack` script printing `version`
+#[cfg(unix)]
+fn pack_tarball(version: &str) -> Vec<u8> {
+    use flate2::{write::GzEncoder, Compression};
+
+    let script = format!("#!/bin/sh\necho {}\n", version);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(script.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+
+    let mut archive = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+    archive
+        .append_data(&mut header, "pack", script.as_bytes())
+        .unwrap();
+    archive.into_inner().unwrap().finish().unwrap()
+}
+
+fn artifact_files(name: &str, content: &[u8], checksum: &str) -> HashMap<String, Vec<u8>> {
+    HashMap::from([
+        (format!("/{}", name), content.to_vec()),
+        (
+            format!("/{}.sha256", name),
+            format!("{}  {}\n", checksum, name).into_bytes(),
+        ),
+    ])
+}
+
+#[tokio::test]
+async fn downloads_and_verifies_checksum() {
+    let content = b"some release artifact".to_vec();
+    let server =
+        FixtureServer::start(artifact_files("tool.tgz", &content, &sha256(&content))).await;
+    let dir = TempDir::new().unwrap();
+    let cache = ToolCache::at(dir.path());
+
+    let download = cache
+        .download_verified(&reqwest::Client::new(), &server.url("tool.tgz"))
+        .await
+        .expect("download failed");
+
+    assert_eq!(fs::read(&download).unwrap(), content);
+    cache.remove_download(&download);
+    assert!(!download.exists());
+}
+
+#[tokio::test]
+async fn rejects_checksum_mismatch() {
+    let content = b"tampered release artifact".to_vec();
+    let server =
+        FixtureServer::start(artifact_files("tool.tgz", &content, &sha256(b"original"))).await;
+    let dir = TempDir::new().unwrap();
+    let cache = ToolCache::at(dir.path());
+
+    let err = cache
+        .download_verified(&reqwest::Client::new(), &server.url("tool.tgz"))
+        .await
+        .expect_err("expected a checksum mismatch");
+
+    assert!(err.to_string().contains("Checksum mismatch"));
+    // a corrupt download is not resumed
+    assert!(!dir.path().join("downloads").join("tool.tgz.part").exists());
+}
+
+#[tokio::test]
+async fn resumes_partial_download() {
+    let content = (0..64 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let server =
+        FixtureServer::start(artifact_files("tool.tgz", &content, &sha256(&content))).await;
+    let dir = TempDir::new().unwrap();
+    let cache = ToolCache::at(dir.path());
+    fs::create_dir_all(dir.path().join("downloads")).unwrap();
+    fs::write(
+        dir.path().join("downloads").join("tool.tgz.part"),
+        &content[..1000],
+    )
+    .unwrap();
+
+    let download = cache
+        .download_verified(&reqwest::Client::new(), &server.url("tool.tgz"))
+        .await
+        .expect("download failed");
+
+    assert_eq!(fs::read(download).unwrap(), content);
+    assert!(server.ranges.lock().unwrap().contains(&Some(1000)));
+}
+
+#[test]
+fn lists_and_prunes_installed_tools() {
+    let dir = TempDir::new().unwrap();
+    let cache = ToolCache::at(dir.path());
+    for version in ["0.26.0", "0.27.0"] {
+        cache
+            .install_atomically("pack", version, |d| {
+                fs::write(d.join("pack"), version).map_err(Into::into)
+            })
+            .unwrap();
+    }
+    // a failed install leaves nothing behind
+    assert!(cache
+        .install_atomically("pack", "0.28.0", |_| anyhow::bail!("broken archive"))
+        .is_err());
+    fs::create_dir_all(dir.path().join("downloads")).unwrap();
+    fs::write(dir.path().join("downloads").join("stale.part"), "x").unwrap();
+
+    let tools = cache.list().unwrap();
+    assert_eq!(
+        tools.iter().map(|t| t.version.as_str()).collect::<Vec<_>>(),
+        vec!["0.26.0", "0.27.0"]
+    );
+    assert_eq!(tools[0].size, 6);
+
+    let removed = cache.prune(|t| t.version == "0.27.0").unwrap();
+    assert_eq!(removed.len(), 1);
+    assert_eq!(removed[0].version, "0.26.0");
+    assert_eq!(cache.list().unwrap().len(), 1);
+    assert!(!dir.path().join("downloads").exists());
+}
+
+#[test]
+fn prune_keeps_downloads_in_progress() {
+    let dir = TempDir::new().unwrap();
+    let cache = ToolCache::at(dir.path());
+    let downloads = dir.path().join("downloads");
+    fs::create_dir_all(&downloads).unwrap();
+    for artifact in ["busy.tgz", "stale.tgz"] {
+        fs::write(downloads.join(format!("{}.part", artifact)), "x").unwrap();
+    }
+    let lock =
+        FileLock::acquire(&downloads.join("busy.tgz.lock"), LockMode::Exclusive, false).unwrap();
+    fs::write(downloads.join("stale.tgz.lock"), "").unwrap();
+
+    cache.prune(|_| true).unwrap();
+    assert!(downloads.join("busy.tgz.part").exists());
+    assert!(downloads.join("busy.tgz.lock").exists());
+    assert!(!downloads.join("stale.tgz.part").exists());
+    assert!(!downloads.join("stale.tgz.lock").exists());
+
+    drop(lock);
+    cache.prune(|_| true).unwrap();
+    assert!(!downloads.exists());
+}
+
+#[test]
+fn refs_keep_track_of_projects() {
+    let dir = TempDir::new().unwrap();
+    let cache = ToolCache::at(&dir.path().join("tools"));
+    let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+    for project in [&a, &b] {
+        fs::create_dir(project).unwrap();
+        cache.add_ref("pack", "0.27.0", project).unwrap();
+    }
+    // idempotent
+    cache.add_ref("pack", "0.27.0", &a).unwrap();
+    cache
+        .install_atomically("pack", "0.27.0", |d| {
+            fs::write(d.join("pack"), "pack").map_err(Into::into)
+        })
+        .unwrap();
+    assert_eq!(cache.list().unwrap().len(), 1);
+    assert_eq!(cache.refs("pack", "0.27.0").len(), 2);
+
+    // refs of projects which are gone don't count
+    fs::remove_dir(&b).unwrap();
+    assert_eq!(cache.refs("pack", "0.27.0"), [a.canonicalize().unwrap()]);
+    assert!(cache.remove_ref("pack", "0.27.0", &a).unwrap());
+    assert!(!cache.remove_ref("pack", "0.27.0", &a).unwrap());
+    assert!(cache.refs("pack", "0.27.0").is_empty());
+
+    cache.prune(|_| false).unwrap();
+    assert!(!dir.path().join("tools/refs/pack/0.27.0").exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn installs_pack_into_cache() {
+    use crate::{
+        network::Network,
+        pack::{Pack, PackSource},
+    };
+
+    let tarball = pack_tarball("0.27.0+git-fixture");
+    let server =
+        FixtureServer::start(artifact_files("pack.tgz", &tarball, &sha256(&tarball))).await;
+    let dir = TempDir::new().unwrap();
+    let cache = ToolCache::at(dir.path());
+
+    let mut pack = Pack::builder(&cache).build();
+    pack.release_url = server.url("pack.tgz");
+    let pack = pack
+        .install(&cache, &Network::default(), &PackSource::default())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        pack.bin_file_path,
+        cache.tool_dir("pack", "0.27.0").join("pack")
+    );
+    assert!(pack.cli_version().unwrap().contains("0.27.0"));
+    assert!(!dir.path().join("downloads").join("pack.tgz.part").exists());
+
+    // a second install reuses the cached binary
+    let requests = server.ranges.lock().unwrap().len();
+    let mut again = Pack::builder(&cache).build();
+    again
+        .install(&cache, &Network::default(), &PackSource::default())
+        .await
+        .unwrap();
+    assert_eq!(server.ranges.lock().unwrap().len(), requests);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn installs_pack_from_local_archive() {
+    use crate::{
+        network::Network,
+        pack::{Pack, PackSource},
+    };
+
+    let dir = TempDir::new().unwrap();
+    let cache = ToolCache::at(&dir.path().join("cache"));
+    let archive = dir.path().join("pack-v0.27.0-linux.tgz");
+    let tarball = pack_tarball("0.27.0");
+    fs::write(&archive, &tarball).unwrap();
+    let source = PackSource {
+        archive: Some(archive.clone()),
+        offline: true,
+        ..Default::default()
+    };
+
+    fs::write(
+        dir.path().join("pack-v0.27.0-linux.tgz.sha256"),
+        sha256(b"other"),
+    )
+    .unwrap();
+    let mut pack = Pack::builder(&cache).build();
+    assert!(pack
+        .install(&cache, &Network::default(), &source)
+        .await
+        .is_err());
+
+    fs::write(
+        dir.path().join("pack-v0.27.0-linux.tgz.sha256"),
+        sha256(&tarball),
+    )
+    .unwrap();
+    let mut pack = Pack::builder(&cache).build();
+    let pack = pack
+        .install(&cache, &Network::default(), &source)
+        .await
+        .unwrap();
+    assert_eq!(
+        pack.bin_file_path,
+        cache.tool_dir("pack", "0.27.0").join("pack")
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn pins_versions_and_replaces_broken_installs() {
+    use crate::{
+        network::Network,
+        pack::{Pack, PackSource},
+    };
+
+    let dir = TempDir::new().unwrap();
+    let cache = ToolCache::at(dir.path());
+    assert!(Pack::builder(&cache).version(Some("0.20.0")).is_err());
+    assert!(Pack::builder(&cache).version(Some("1.2.0")).is_err());
+
+    let tarball = pack_tarball("0.30.0");
+    let server =
+        FixtureServer::start(artifact_files("pack.tgz", &tarball, &sha256(&tarball))).await;
+    // a cached binary reporting another version is replaced
+    cache
+        .install_atomically("pack", "0.30.0", |d| {
+            fs::write(d.join("pack"), "#!/bin/sh\necho 0.29.9\n").map_err(Into::into)
+        })
+        .unwrap();
+
+    let mut pack = Pack::builder(&cache)
+        .version(Some("v0.30.0"))
+        .unwrap()
+        .build();
+    assert_eq!(pack.release_version, "0.30.0");
+    pack.release_url = server.url("pack.tgz");
+    let pack = pack
+        .install(&cache, &Network::default(), &PackSource::default())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        pack.bin_file_path,
+        cache.tool_dir("pack", "0.30.0").join("pack")
+    );
+    assert_eq!(pack.installed_version().unwrap().to_string(), "0.30.0");
+    assert!(pack.check_outdated().is_ok());
+
+    // the pin outlives the run which set it, states without it aren't pinned
+    let mut state = serde_json::to_value(pack).unwrap();
+    assert!(
+        serde_json::from_value::<Pack>(state.clone())
+            .unwrap()
+            .pinned
+    );
+    state.as_object_mut().unwrap().remove("pinned");
+    assert!(!serde_json::from_value::<Pack>(state).unwrap().pinned);
+}
@@ -0,0 +1,321 @@
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    error::AppError,
+    log::{success, trying},
+};
+use npm_package_json::Package;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Files at the workspace root which are part of the build context whenever present
+const ROOT_FILES: [&str; 15] = [
+    "package.json",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "pnpm-workspace.yaml",
+    ".npmrc",
+    ".yarnrc.yml",
+    ".yarn",
+    ".pnp.cjs",
+    ".pnp.loader.mjs",
+    ".nvmrc",
+    "turbo.json",
+    "nx.json",
+    "lerna.json",
+];
+
+/// Package manager owning the workspace definition
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub(crate) enum WorkspaceKind {
+    #[default]
+    Npm,
+    Yarn,
+    Pnpm,
+}
+
+/// Task runner orchestrating the workspace, if any
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) enum TaskRunner {
+    Nx,
+    Turborepo,
+    Lerna,
+}
+
+/// A package which is a member of the workspace
+#[derive(Debug, Clone)]
+pub(crate) struct WorkspacePackage {
+    pub(crate) name: String,
+    /// path relative to the workspace root
+    pub(crate) path: PathBuf,
+    pub(crate) package_json: Package,
+}
+
+/// Monorepo found while walking up from the client project
+#[derive(Debug, Clone)]
+pub(crate) struct Workspace {
+    pub(crate) root: PathBuf,
+    pub(crate) kind: WorkspaceKind,
+    pub(crate) runner: Option<TaskRunner>,
+    pub(crate) packages: Vec<WorkspacePackage>,
+}
+
+/// The app within a workspace which autopack builds into an image.
+///
+/// Paths other than `root` are relative to `root` so that they can be used
+/// directly in the build context of the buildpack.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub(crate) struct WorkspaceApp {
+    pub(crate) root: PathBuf,
+    pub(crate) kind: WorkspaceKind,
+    pub(crate) runner: Option<TaskRunner>,
+    pub(crate) name: String,
+    pub(crate) app_path: PathBuf,
+    pub(crate) local_deps: Vec<PathBuf>,
+    pub(crate) root_files: Vec<String>,
+}
+
+fn read_package_json(dir: &Path) -> Option<Package> {
+    let p = dir.join("package.json");
+    p.exists().then(|| Package::from_path(p).ok()).flatten()
+}
+
+fn globs_from_package_json(pkg: &Package) -> Option<Vec<String>> {
+    let to_vec = |v: &Value| {
+        v.as_array().map(|a| {
+            a.iter()
+                .filter_map(|x| x.as_str().map(String::from))
+                .collect::<Vec<_>>()
+        })
+    };
+    pkg.others.get("workspaces").and_then(|w| match w {
+        Value::Array(_) => to_vec(w),
+        Value::Object(o) => o.get("packages").and_then(to_vec),
+        _ => None,
+    })
+}
+
+fn globs_from_pnpm(dir: &Path) -> anyhow::Result<Option<Vec<String>>> {
+    #[derive(Deserialize)]
+    struct PnpmWorkspace {
+        #[serde(default)]
+        packages: Vec<String>,
+    }
+
+    let p = dir.join("pnpm-workspace.yaml");
+    if !p.exists() {
+        return Ok(None);
+    }
+    let ws: PnpmWorkspace = serde_yaml::from_str(&fs::read_to_string(&p)?)
+        .map_err(|e| anyhow::anyhow!("Failed parsing {:?} :: {}", p, e))?;
+    Ok(Some(ws.packages))
+}
+
+fn task_runner(dir: &Path) -> Option<TaskRunner> {
+    if dir.join("nx.json").exists() {
+        Some(TaskRunner::Nx)
+    } else if dir.join("turbo.json").exists() {
+        Some(TaskRunner::Turborepo)
+    } else if dir.join("lerna.json").exists() {
+        Some(TaskRunner::Lerna)
+    } else {
+        None
+    }
+}
+
+fn expand_globs(root: &Path, globs: &[String]) -> Vec<WorkspacePackage> {
+    let (negated, positive): (Vec<_>, Vec<_>) = globs.iter().partition(|g| g.starts_with('!'));
+    let excludes: Vec<glob::Pattern> = negated
+        .iter()
+        .filter_map(|g| {
+            let g = g[1..].trim_start_matches("./").trim_end_matches('/');
+            glob::Pattern::new(g).ok()
+        })
+        .collect();
+
+    let mut packages: BTreeMap<PathBuf, WorkspacePackage> = BTreeMap::new();
+    positive
+        .iter()
+        .filter_map(|g| glob::glob(&root.join(g).to_string_lossy()).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|p| p.is_dir() && !p.components().any(|c| c.as_os_str() == "node_modules"))
+        .for_each(|p| {
+            let rel = match p.strip_prefix(root) {
+                Ok(rel) => rel,
+                Err(_) => return,
+            };
+            if excludes.iter().any(|e| e.matches_path(rel)) {
+                debug!("Excluding {:?} from workspace packages", rel);
+                return;
+            }
+            if let Some(package_json) = read_package_json(&p) {
+                packages.insert(
+                    rel.to_path_buf(),
+                    WorkspacePackage {
+                        name: package_json.name.clone(),
+                        path: rel.to_path_buf(),
+                        package_json,
+                    },
+                );
+            }
+        });
+    packages.into_values().collect()
+}
+
+impl Workspace {
+    /// Walks up from `project_path` and returns the nearest workspace root, if any.
+    pub(crate) fn detect(project_path: &Path) -> anyhow::Result<Option<Workspace>> {
+        let start = project_path.canonicalize()?;
+        for dir in start.ancestors() {
+            let pkg = read_package_json(dir);
+            let pnpm_globs = globs_from_pnpm(dir)?;
+            let (kind, globs) = match (pnpm_globs, pkg.as_ref().and_then(globs_from_package_json)) {
+                (Some(g), _) => (WorkspaceKind::Pnpm, g),
+                (None, Some(g)) if dir.join("yarn.lock").exists() => (WorkspaceKind::Yarn, g),
+                (None, Some(g)) => (WorkspaceKind::Npm, g),
+                (None, None) => match task_runner(dir) {
+                    // nx / turbo repos without package manager workspaces use the conventional layout
+                    Some(_) if pkg.is_some() => (
+                        WorkspaceKind::Npm,
+                        vec!["apps/*".into(), "packages/*".into(), "libs/*".into()],
+                    ),
+                    _ => continue,
+                },
+            };
+
+            debug!("Found {:?} workspace at {:?}", kind, dir);
+            return Ok(Some(Workspace {
+                root: dir.to_path_buf(),
+                kind,
+                runner: task_runner(dir),
+                packages: expand_globs(dir, &globs),
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Finds the package to build either by its name (or directory name) when `app` is given,
+    /// or as the workspace package containing `project_path`.
+    pub(crate) fn target(
+        &self,
+        project_path: &Path,
+        app: Option<&str>,
+    ) -> Result<&WorkspacePackage, AppError> {
+        let found = match app {
+            Some(app) => self
+                .packages
+                .iter()
+                .find(|p| p.name == app || p.path.file_name().map(|f| f == app).unwrap_or(false)),
+            None => project_path
+                .canonicalize()
+                .ok()
+                .and_then(|p| p.strip_prefix(&self.root).map(Path::to_path_buf).ok())
+                .and_then(|rel| self.packages.iter().find(|p| rel.starts_with(&p.path))),
+        };
+
+        found.ok_or_else(|| {
+            let names = self
+                .packages
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            AppError::WorkspaceAppNotFound(app.unwrap_or_default().to_string(), names)
+        })
+    }
+
+    /// Workspace packages the given package depends on, transitively
+    fn local_deps(&self, package: &WorkspacePackage) -> Vec<PathBuf> {
+        let mut seen: Vec<PathBuf> = vec![];
+        let mut pending = vec![package];
+        while let Some(p) = pending.pop() {
+            let pj = &p.package_json;
+            pj.dependencies
+                .keys()
+                .chain(pj.dev_dependencies.keys())
+                .chain(pj.peer_dependencies.keys())
+                .filter_map(|d| self.packages.iter().find(|wp| &wp.name == d))
+                .for_each(|wp| {
+                    if wp.path != package.path && !seen.contains(&wp.path) {
+                        seen.push(wp.path.clone());
+                        pending.push(wp);
+                    }
+                });
+        }
+        seen.sort();
+        seen
+    }
+
+    pub(crate) fn app(&self, package: &WorkspacePackage) -> WorkspaceApp {
+        WorkspaceApp {
+            root: self.root.clone(),
+            kind: self.kind,
+            runner: self.runner,
+            name: package.name.clone(),
+            app_path: package.path.clone(),
+            local_deps: self.local_deps(package),
+            root_files: ROOT_FILES
+                .iter()
+                .filter(|f| self.root.join(f).exists())
+                .map(|f| f.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Resolves the app directory for commands run after `init`.
+///
+/// Without `--app` the current directory is used as is.
+pub(crate) fn resolve_app_dir(cwd: &Path, app: Option<&str>) -> Result<PathBuf, AppError> {
+    match app {
+        None => Ok(cwd.to_path_buf()),
+        Some(app) => {
+            trying(&format!("Locating workspace app {}", app));
+            let dir = find_app_dir(cwd, app)?;
+            success(&format!("Found workspace app {}", app));
+            Ok(dir)
+        }
+    }
+}
+
+/// Directory of the workspace app `app`, without reporting progress
+pub(crate) fn find_app_dir(cwd: &Path, app: &str) -> Result<PathBuf, AppError> {
+    let ws = Workspace::detect(cwd)
+        .map_err(|e| AppError::PreconfigureError("Failed detecting workspace", e))?
+        .ok_or_else(|| AppError::WorkspaceNotFound(cwd.to_path_buf()))?;
+    Ok(ws.root.join(&ws.target(cwd, Some(app))?.path))
+}
+
+impl WorkspaceApp {
+    /// Gitignore style patterns selecting the build context out of the workspace root
+    pub(crate) fn include_patterns(&self) -> Vec<String> {
+        let dir_patterns = |p: &PathBuf| {
+            let p = p.to_string_lossy().replace('\\', "/");
+            vec![format!("{}/", p), format!("!{}/node_modules", p)]
+        };
+        let app = self.container_app_path();
+
+        self.root_files
+            .iter()
+            .cloned()
+            .chain(dir_patterns(&self.app_path))
+            .chain([format!("!{}/build", app), format!("!{}/dist", app)])
+            .chain(self.local_deps.iter().flat_map(dir_patterns))
+            .collect()
+    }
+
+    /// App path relative to the workspace root using `/` separators as seen inside the container
+    pub(crate) fn container_app_path(&self) -> String {
+        self.app_path.to_string_lossy().replace('\\', "/")
+    }
+}
@@ -0,0 +1,137 @@
+use super::{resolve_app_dir, TaskRunner, Workspace, WorkspaceKind};
+use std::{fs, path::Path, path::PathBuf};
+use tempfile::{Builder, TempDir};
+
+fn write_package(dir: &Path, name: &str, deps: &[&str]) {
+    fs::create_dir_all(dir).expect("failed creating package dir");
+    let deps = deps
+        .iter()
+        .map(|d| format!(r#""{}": "workspace:*""#, d))
+        .collect::<Vec<_>>()
+        .join(",");
+    fs::write(
+        dir.join("package.json"),
+        format!(
+            r#"{{"name": "{}", "version": "1.0.0", "dependencies": {{ {} }}}}"#,
+            name, deps
+        ),
+    )
+    .expect("failed writing package.json");
+}
+
+fn pnpm_turbo_repo() -> TempDir {
+    let root = Builder::new().tempdir().expect("failed creating temp dir");
+    let r = root.path();
+    fs::write(
+        r.join("package.json"),
+        r#"{"name": "mono", "version": "0.0.0"}"#,
+    )
+    .unwrap();
+    fs::write(
+        r.join("pnpm-workspace.yaml"),
+        "packages:\n  - 'apps/*'\n  - 'packages/*'\n  - '!**/test/**'\n",
+    )
+    .unwrap();
+    fs::write(r.join("pnpm-lock.yaml"), "lockfileVersion: 5.4\n").unwrap();
+    fs::write(r.join("turbo.json"), "{}").unwrap();
+    write_package(&r.join("apps/web"), "@acme/web", &["@acme/ui"]);
+    write_package(&r.join("apps/admin"), "@acme/admin", &[]);
+    write_package(&r.join("packages/ui"), "@acme/ui", &["@acme/tokens"]);
+    write_package(&r.join("packages/tokens"), "@acme/tokens", &[]);
+    root
+}
+
+#[test]
+fn detects_pnpm_turborepo_from_app_dir() {
+    let root = pnpm_turbo_repo();
+    let ws = Workspace::detect(&root.path().join("apps/web"))
+        .expect("failed detecting")
+        .expect("expected a workspace");
+
+    assert_eq!(ws.kind, WorkspaceKind::Pnpm);
+    assert_eq!(ws.runner, Some(TaskRunner::Turborepo));
+    assert_eq!(ws.packages.len(), 4);
+
+    let target = ws
+        .target(&root.path().join("apps/web"), None)
+        .expect("expected the app");
+    let app = ws.app(target);
+
+    assert_eq!(app.name, "@acme/web");
+    assert_eq!(app.app_path, PathBuf::from("apps/web"));
+    assert_eq!(
+        app.local_deps,
+        vec![
+            PathBuf::from("packages/tokens"),
+            PathBuf::from("packages/ui")
+        ]
+    );
+    assert!(app.root_files.contains(&"pnpm-lock.yaml".to_string()));
+    assert!(app
+        .include_patterns()
+        .contains(&"!apps/web/node_modules".to_string()));
+}
+
+#[test]
+fn selects_app_by_name_or_dir() {
+    let root = pnpm_turbo_repo();
+    let ws = Workspace::detect(root.path()).unwrap().unwrap();
+
+    assert_eq!(
+        ws.target(root.path(), Some("@acme/admin")).unwrap().path,
+        PathBuf::from("apps/admin")
+    );
+    assert_eq!(
+        ws.target(root.path(), Some("admin")).unwrap().path,
+        PathBuf::from("apps/admin")
+    );
+    assert!(ws.target(root.path(), Some("nope")).is_err());
+    assert!(ws.target(root.path(), None).is_err());
+
+    let dir = resolve_app_dir(root.path(), Some("web")).expect("expected app dir");
+    assert_eq!(dir, ws.root.join("apps/web"));
+}
+
+#[test]
+fn detects_yarn_workspaces_object_form() {
+    let root = Builder::new().tempdir().unwrap();
+    let r = root.path();
+    fs::write(
+        r.join("package.json"),
+        r#"{"name": "mono", "version": "0.0.0", "workspaces": {"packages": ["apps/*"]}}"#,
+    )
+    .unwrap();
+    fs::write(r.join("yarn.lock"), "").unwrap();
+    write_package(&r.join("apps/site"), "site", &[]);
+
+    let ws = Workspace::detect(&r.join("apps/site")).unwrap().unwrap();
+    assert_eq!(ws.kind, WorkspaceKind::Yarn);
+    assert_eq!(ws.runner, None);
+    assert_eq!(ws.packages[0].name, "site");
+}
+
+#[test]
+fn standalone_project_has_no_workspace() {
+    let root = Builder::new().tempdir().unwrap();
+    write_package(root.path(), "standalone", &[]);
+
+    assert!(Workspace::detect(root.path()).unwrap().is_none());
+}
+
+#[test]
+fn negated_globs_exclude_packages() {
+    let root = Builder::new().tempdir().unwrap();
+    let r = root.path();
+    fs::write(
+        r.join("package.json"),
+        r#"{"name": "mono", "version": "0.0.0", "workspaces": ["packages/*", "!packages/legacy"]}"#,
+    )
+    .unwrap();
+    write_package(&r.join("packages/app"), "app", &[]);
+    write_package(&r.join("packages/legacy"), "legacy", &[]);
+
+    let ws = Workspace::detect(r).unwrap().unwrap();
+    assert_eq!(ws.packages.len(), 1);
+    assert_eq!(ws.packages[0].name, "app");
+    assert!(ws.target(r, Some("legacy")).is_err());
+}